@@ -7,12 +7,12 @@ do
     case $opt in
         "build & deploy all")
             echo "Deploying: $opt";
-            docker build -t cluster.loc:5000/rs-algo-backend:latest rs_algo_backend  ; docker build -t cluster.loc:5000/rs-algo-scanner:latest rs_algo_scanner ; docker build -t cluster.loc:5000/rs-algo-backtest:latest rs_algo_backtest ;  docker build -t cluster.loc:5000/rs-algo-client:latest rs_algo_client ; docker push cluster.loc:5000/rs-algo-backend:latest ; docker push cluster.loc:5000/rs-algo-scanner:latest ; docker push cluster.loc:5000/rs-algo-backtest:latest ; docker push cluster.loc:5000/rs-algo-client:latest ; ansible-playbook playbook.yml  
+            docker build -t cluster.loc:5000/rs-algo-backend:latest rs_algo_backend  ; docker build -t cluster.loc:5000/rs-algo-scanner:latest -f rs_algo_scanner/Dockerfile . ; docker build -t cluster.loc:5000/rs-algo-backtest:latest -f rs_algo_backtest/Dockerfile . ;  docker build -t cluster.loc:5000/rs-algo-client:latest rs_algo_client ; docker push cluster.loc:5000/rs-algo-backend:latest ; docker push cluster.loc:5000/rs-algo-scanner:latest ; docker push cluster.loc:5000/rs-algo-backtest:latest ; docker push cluster.loc:5000/rs-algo-client:latest ; ansible-playbook playbook.yml  
             break
             ;;
         "build all")
             echo "Deploying: $opt";
-            docker build -t cluster.loc:5000/rs-algo-backend:latest rs_algo_backend  ; docker build -t cluster.loc:5000/rs-algo-scanner:latest rs_algo_backtest ;  docker build -t cluster.loc:5000/rs-algo-scanner:latest rs_algo_scanner ;  docker build -t cluster.loc:5000/rs-algo-client:latest rs_algo_client ; docker push cluster.loc:5000/rs-algo-backend:latest ; docker push cluster.loc:5000/rs-algo-scanner:latest ; docker push cluster.loc:5000/rs-algo-backtest:latest ; docker push cluster.loc:5000/rs-algo-client:latest ;
+            docker build -t cluster.loc:5000/rs-algo-backend:latest rs_algo_backend  ; docker build -t cluster.loc:5000/rs-algo-scanner:latest -f rs_algo_backtest/Dockerfile . ;  docker build -t cluster.loc:5000/rs-algo-scanner:latest -f rs_algo_scanner/Dockerfile . ;  docker build -t cluster.loc:5000/rs-algo-client:latest rs_algo_client ; docker push cluster.loc:5000/rs-algo-backend:latest ; docker push cluster.loc:5000/rs-algo-scanner:latest ; docker push cluster.loc:5000/rs-algo-backtest:latest ; docker push cluster.loc:5000/rs-algo-client:latest ;
             break
             ;;
         "deploy all")
@@ -27,12 +27,12 @@ do
             ;;
         "build & deploy rs-algo-scanner")
             echo "Deploying: $opt";
-            docker build -t cluster.loc:5000/rs-algo-scanner:latest rs_algo_scanner ; docker push cluster.loc:5000/rs-algo-scanner:latest ; ansible-playbook playbook.yml 
+            docker build -t cluster.loc:5000/rs-algo-scanner:latest -f rs_algo_scanner/Dockerfile . ; docker push cluster.loc:5000/rs-algo-scanner:latest ; ansible-playbook playbook.yml 
             break
             ;;
         "build & deploy rs-algo-backtest")
             echo "Deploying: $opt";
-            docker build -t cluster.loc:5000/rs-algo-backtest:latest rs_algo_backtest ; docker push cluster.loc:5000/rs-algo-backtest:latest ; ansible-playbook playbook.yml 
+            docker build -t cluster.loc:5000/rs-algo-backtest:latest -f rs_algo_backtest/Dockerfile . ; docker push cluster.loc:5000/rs-algo-backtest:latest ; ansible-playbook playbook.yml 
             break
             ;;
         "build & deploy rs-algo-client")
@@ -0,0 +1,82 @@
+use super::helpers::get_collection;
+use crate::models::app_state::AppState;
+use crate::models::correlation::{CorrelationSummary, Correlations, SymbolCorrelations};
+
+use actix_web::web;
+use bson::doc;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndReplaceOptions, FindOneOptions, ReplaceOptions};
+use std::env;
+
+pub async fn find_by_time_frame(
+    time_frame: &str,
+    state: &web::Data<AppState>,
+) -> Result<Option<CorrelationSummary>, Error> {
+    let collection_name = &env::var("DB_CORRELATIONS_COLLECTION").unwrap();
+    let collection = get_collection::<CorrelationSummary>(&state.db_mem, collection_name).await;
+
+    collection
+        .find_one(
+            doc! { "time_frame": time_frame },
+            FindOneOptions::builder().build(),
+        )
+        .await
+}
+
+pub async fn find_by_symbol(
+    time_frame: &str,
+    symbol: &str,
+    state: &web::Data<AppState>,
+) -> Result<Option<SymbolCorrelations>, Error> {
+    let collection_name = &env::var("DB_SYMBOL_CORRELATIONS_COLLECTION").unwrap();
+    let collection = get_collection::<SymbolCorrelations>(&state.db_mem, collection_name).await;
+
+    collection
+        .find_one(
+            doc! { "time_frame": time_frame, "symbol": symbol },
+            FindOneOptions::builder().build(),
+        )
+        .await
+}
+
+/// Replaces the summary and the pairs of every symbol, then drops the symbols that
+/// weren't part of this scan.
+pub async fn upsert(doc: &Correlations, state: &web::Data<AppState>) -> Result<(), Error> {
+    let collection_name = &env::var("DB_CORRELATIONS_COLLECTION").unwrap();
+    let collection = get_collection::<CorrelationSummary>(&state.db_mem, collection_name).await;
+
+    collection
+        .find_one_and_replace(
+            doc! { "time_frame": doc.time_frame.clone() },
+            doc.summary(),
+            FindOneAndReplaceOptions::builder()
+                .upsert(Some(true))
+                .build(),
+        )
+        .await?;
+
+    let collection_name = &env::var("DB_SYMBOL_CORRELATIONS_COLLECTION").unwrap();
+    let collection = get_collection::<SymbolCorrelations>(&state.db_mem, collection_name).await;
+
+    for symbol_correlations in doc.by_symbol() {
+        collection
+            .replace_one(
+                doc! {
+                    "time_frame": doc.time_frame.clone(),
+                    "symbol": symbol_correlations.symbol.clone(),
+                },
+                symbol_correlations,
+                ReplaceOptions::builder().upsert(Some(true)).build(),
+            )
+            .await?;
+    }
+
+    collection
+        .delete_many(
+            doc! { "time_frame": doc.time_frame.clone(), "date": { "$lt": doc.date } },
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
@@ -2,16 +2,31 @@ use super::helpers::*;
 use crate::models::app_state::AppState;
 use crate::strategies::general::General;
 
+use rs_algo_shared::scanner::candle::Candle;
 use rs_algo_shared::scanner::instrument::*;
 
 use actix_web::web;
-use bson::doc;
+use bson::{doc, Document};
 use futures::stream::StreamExt;
 use mongodb::error::Error;
-use mongodb::options::{FindOneAndReplaceOptions, FindOneOptions, FindOptions};
+use mongodb::options::{FindOneAndReplaceOptions, FindOneOptions, FindOptions, UpdateOptions};
+use mongodb::results::UpdateResult;
+use serde::{Deserialize, Serialize};
 
 use std::env;
 
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct InstrumentCandles {
+    pub symbol: String,
+    pub data: Vec<Candle>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LastCandle {
+    pub symbol: String,
+    pub timestamp: i64,
+}
+
 pub async fn find_by_symbol(
     symbol: &str,
     state: &web::Data<AppState>,
@@ -21,15 +36,67 @@ pub async fn find_by_symbol(
 
     let instrument = collection
         .find_one(doc! { "symbol": symbol}, FindOneOptions::builder().build())
-        .await
-        .unwrap();
+        .await?;
 
     Ok(instrument)
 }
 
+pub async fn find_candles(
+    symbol: &str,
+    time_frame: &str,
+    state: &web::Data<AppState>,
+) -> Result<Option<InstrumentCandles>, Error> {
+    let collection_name =
+        get_collection_name(&env::var("DB_INSTRUMENTS_COLLECTION").unwrap(), time_frame);
+    let collection = get_collection::<InstrumentCandles>(&state.db_mem, &collection_name).await;
+
+    let candles = collection
+        .find_one(
+            doc! { "symbol": symbol},
+            FindOneOptions::builder()
+                .projection(doc! {"_id": 0, "symbol": 1, "data": 1})
+                .build(),
+        )
+        .await?;
+
+    Ok(candles)
+}
+
+pub async fn find_last_candles(
+    time_frame: &str,
+    state: &web::Data<AppState>,
+) -> Result<Vec<LastCandle>, Error> {
+    let collection_name =
+        get_collection_name(&env::var("DB_INSTRUMENTS_COLLECTION").unwrap(), time_frame);
+    let collection = get_collection::<Document>(&state.db_mem, &collection_name).await;
+
+    let mut cursor = collection
+        .find(
+            doc! {},
+            FindOptions::builder()
+                .projection(doc! {"_id": 0, "symbol": 1, "date": 1})
+                .build(),
+        )
+        .await?;
+
+    let mut docs: Vec<LastCandle> = vec![];
+    while let Some(result) = cursor.next().await {
+        let doc = result?;
+        if let (Ok(symbol), Ok(date)) = (doc.get_str("symbol"), doc.get_datetime("date")) {
+            docs.push(LastCandle {
+                symbol: symbol.to_owned(),
+                timestamp: date.timestamp_millis() / 1000,
+            });
+        }
+    }
+    Ok(docs)
+}
+
 pub async fn find_by_params(
     state: &web::Data<AppState>,
     params: String,
+    leaders_first: bool,
+    leaders_only: bool,
     strategy: General,
 ) -> Result<Vec<CompactInstrument>, Error> {
     let collection_name = &env::var("DB_INSTRUMENTS_COMPACT_COLLECTION").unwrap();
@@ -38,22 +105,51 @@ pub async fn find_by_params(
     let collection = get_collection::<CompactInstrument>(&state.db_mem, collection_name).await;
 
     //FIXME
+    let general = params.is_empty();
     let query = match params.as_ref() {
         "" => strategy.query(),
         _ => serde_json::from_str(&params).unwrap(),
     };
 
+    let rank = [
+        "relative_strength.",
+        &env::var("RELATIVE_STRENGTH_TIME_FRAME").unwrap(),
+        ".rank",
+    ]
+    .concat();
+
+    let query = match leaders_only {
+        true => {
+            let min_rank = env::var("RELATIVE_STRENGTH_MIN_RANK")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap();
+            let mut leaders = Document::new();
+            leaders.insert(rank.clone(), doc! { "$gte": min_rank });
+            doc! { "$and": [query, leaders] }
+        }
+        false => query,
+    };
+
+    let mut sort = Document::new();
+    if leaders_first {
+        sort.insert(rank, -1);
+    }
+
     let cursor = collection
         .find(
             query,
             FindOptions::builder()
                 //.sort(doc! {"avg_volume":-1, })
+                .sort(sort)
                 .build(),
         )
-        .await
-        .unwrap();
+        .await?;
 
     let docs = strategy.format_instrument(cursor).await;
+    if general {
+        strategy.check_results(docs.len());
+    }
     Ok(docs)
 }
 
@@ -68,6 +164,7 @@ pub async fn find_detail_by_params(
     let collection = get_collection::<Instrument>(&state.db_mem, collection_name).await;
 
     //FIXME
+    let general = params.is_empty();
     let query = match params.as_ref() {
         "" => strategy.query(),
         _ => serde_json::from_str(&params).unwrap(),
@@ -75,17 +172,14 @@ pub async fn find_detail_by_params(
 
     let mut cursor = collection
         .find(query, FindOptions::builder().build())
-        .await
-        .unwrap();
+        .await?;
 
     let mut instruments: Vec<Instrument> = vec![];
     while let Some(result) = cursor.next().await {
-        match result {
-            Ok(instrument) => {
-                instruments.push(instrument);
-            }
-            _ => {}
-        }
+        instruments.push(result?);
+    }
+    if general {
+        strategy.check_results(instruments.len());
     }
     Ok(instruments)
 }
@@ -97,52 +191,36 @@ pub async fn find_all(state: &web::Data<AppState>) -> Result<Vec<Instrument>, Er
 
     let mut cursor = collection
         .find(doc! {}, FindOptions::builder().build())
-        .await
-        .unwrap();
+        .await?;
 
     let mut instruments: Vec<Instrument> = vec![];
     while let Some(result) = cursor.next().await {
-        match result {
-            Ok(instrument) => {
-                instruments.push(instrument);
-            }
-            _ => {}
-        }
+        instruments.push(result?);
     }
     Ok(instruments)
 }
 
+pub fn instruments_collection_name(mode: &str, time_frame: &str) -> String {
+    match mode {
+        "daily" => get_collection_name(&env::var("DB_INSTRUMENTS_COLLECTION").unwrap(), time_frame),
+        "backtest" => [
+            &env::var("DB_BACKTEST_INSTRUMENTS_COLLECTION").unwrap(),
+            "_",
+            time_frame,
+        ]
+        .concat(),
+        _ => env::var("DB_INSTRUMENTS_COLLECTION").unwrap(),
+    }
+}
+
 pub async fn upsert_instrument(
     mode: &str,
     time_frame: &str,
     doc: &Instrument,
     state: &web::Data<AppState>,
 ) -> Result<Option<Instrument>, Error> {
-    let collection = match mode {
-        "daily" => {
-            let collection_name =
-                get_collection_name(&env::var("DB_INSTRUMENTS_COLLECTION").unwrap(), time_frame);
-
-            get_collection::<Instrument>(&state.db_mem, &collection_name).await
-        }
-        "backtest" => {
-            let collection_name = [
-                &env::var("DB_BACKTEST_INSTRUMENTS_COLLECTION").unwrap(),
-                "_",
-                time_frame,
-            ]
-            .concat();
-
-            get_collection::<Instrument>(&state.db_mem, &collection_name).await
-        }
-        _ => {
-            get_collection::<Instrument>(
-                &state.db_mem,
-                &env::var("DB_INSTRUMENTS_COLLECTION").unwrap(),
-            )
-            .await
-        }
-    };
+    let collection_name = instruments_collection_name(mode, time_frame);
+    let collection = get_collection::<Instrument>(&state.db_mem, &collection_name).await;
 
     collection
         .find_one_and_replace(
@@ -155,21 +233,77 @@ pub async fn upsert_instrument(
         .await
 }
 
+/// Stores the scanner side analysis next to the instrument so it can be screened.
+pub async fn update_analysis(
+    collection_name: &str,
+    symbol: &str,
+    analysis: &Document,
+    state: &web::Data<AppState>,
+) -> Result<UpdateResult, Error> {
+    let collection = get_collection::<Document>(&state.db_mem, collection_name).await;
+
+    collection
+        .update_one(
+            doc! { "symbol": symbol },
+            doc! { "$set": { "analysis": analysis } },
+            None,
+        )
+        .await
+}
+
+/// Stores the cross-sectional ranks of a time frame on the compact instrument.
+pub async fn update_relative_strength(
+    symbol: &str,
+    time_frame: &str,
+    relative_strength: &Document,
+    state: &web::Data<AppState>,
+) -> Result<UpdateResult, Error> {
+    let collection_name = env::var("DB_INSTRUMENTS_COMPACT_COLLECTION").unwrap();
+    let collection = get_collection::<Document>(&state.db_mem, &collection_name).await;
+
+    let mut set = Document::new();
+    set.insert(
+        ["relative_strength.", time_frame].concat(),
+        relative_strength.clone(),
+    );
+
+    collection
+        .update_one(doc! { "symbol": symbol }, doc! { "$set": set }, None)
+        .await
+}
+
+pub async fn update_live(
+    symbol: &str,
+    time_frame: &str,
+    live: &Document,
+    state: &web::Data<AppState>,
+) -> Result<UpdateResult, Error> {
+    let collection_name = env::var("DB_INSTRUMENTS_COMPACT_COLLECTION").unwrap();
+    let collection = get_collection::<Document>(&state.db_mem, &collection_name).await;
+
+    let mut set = Document::new();
+    set.insert(["live.", time_frame].concat(), live.clone());
+
+    collection
+        .update_one(doc! { "symbol": symbol }, doc! { "$set": set }, None)
+        .await
+}
+
+/// Sets the compact fields only, so the relative strength, live and analysis
+/// fields stored by other updates are kept.
 pub async fn upsert_compact_instrument(
     doc: CompactInstrument,
     state: &web::Data<AppState>,
-) -> Result<Option<CompactInstrument>, Error> {
+) -> Result<UpdateResult, Error> {
     let collection_name = env::var("DB_INSTRUMENTS_COMPACT_COLLECTION").unwrap();
 
-    let collection = get_collection::<CompactInstrument>(&state.db_mem, &collection_name).await;
+    let collection = get_collection::<Document>(&state.db_mem, &collection_name).await;
 
     collection
-        .find_one_and_replace(
+        .update_one(
             doc! { "symbol": doc.symbol.clone() },
-            doc,
-            FindOneAndReplaceOptions::builder()
-                .upsert(Some(true))
-                .build(),
+            doc! { "$set": bson::to_document(&doc)? },
+            UpdateOptions::builder().upsert(Some(true)).build(),
         )
         .await
 }
@@ -1,7 +1,9 @@
 pub mod back_test;
 pub mod bot;
+pub mod correlation;
 pub mod helpers;
 pub mod instrument;
 pub mod mongo;
 pub mod portfolio;
+pub mod scan;
 pub mod watch_list;
@@ -0,0 +1,60 @@
+use super::helpers::get_collection;
+use crate::models::app_state::AppState;
+
+use actix_web::web;
+use bson::{doc, Document};
+use futures::stream::StreamExt;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndReplaceOptions, FindOptions};
+use std::env;
+
+pub async fn find(
+    offset: u64,
+    limit: i64,
+    state: &web::Data<AppState>,
+) -> Result<Vec<Document>, Error> {
+    let collection_name = &env::var("DB_SCANS_COLLECTION").unwrap();
+    let collection = get_collection::<Document>(&state.db_hdd, collection_name).await;
+
+    let mut cursor = collection
+        .find(
+            doc! {},
+            FindOptions::builder()
+                .projection(doc! {"_id": 0})
+                .sort(doc! {"started_at": -1})
+                .skip(offset)
+                .limit(limit)
+                .build(),
+        )
+        .await?;
+
+    let mut docs: Vec<Document> = vec![];
+    while let Some(result) = cursor.next().await {
+        if let Ok(doc) = result {
+            docs.push(doc);
+        }
+    }
+    Ok(docs)
+}
+
+/// Keyed by mode and start time so a summary retried from the scanner outbox is
+/// stored once.
+pub async fn upsert(
+    mode: &str,
+    started_at: i64,
+    doc: Document,
+    state: &web::Data<AppState>,
+) -> Result<Option<Document>, Error> {
+    let collection_name = &env::var("DB_SCANS_COLLECTION").unwrap();
+    let collection = get_collection::<Document>(&state.db_hdd, collection_name).await;
+
+    collection
+        .find_one_and_replace(
+            doc! { "mode": mode, "started_at": started_at },
+            doc,
+            FindOneAndReplaceOptions::builder()
+                .upsert(Some(true))
+                .build(),
+        )
+        .await
+}
@@ -18,9 +18,11 @@ use models::app_state::AppState;
 use models::db::Db;
 use services::back_test;
 use services::bot;
+use services::correlation;
 use services::index::index;
 use services::instrument;
 use services::portfolio;
+use services::scan;
 use services::watch_list;
 use std::env;
 
@@ -93,11 +95,39 @@ async fn main() -> Result<()> {
                     .route("/bots/chart/{id}", web::get().to(bot::chart))
                     .route("/instruments", web::post().to(instrument::find))
                     .route("/instruments", web::put().to(instrument::upsert))
+                    .route(
+                        "/instruments/relative_strength",
+                        web::put().to(instrument::upsert_relative_strength),
+                    )
+                    .route("/instruments/live", web::put().to(instrument::upsert_live))
                     .route("/instruments/{symbol}", web::get().to(instrument::find_one))
                     .route(
                         "/instruments/chart/{symbol}",
                         web::get().to(instrument::chart),
                     )
+                    .route(
+                        "/instruments/by_time_frame/{time_frame}/last",
+                        web::get().to(instrument::find_last_candles),
+                    )
+                    .route(
+                        "/instruments/by_time_frame/{time_frame}/{symbol}/candles",
+                        web::get().to(instrument::find_candles),
+                    )
+                    .route("/correlations", web::put().to(correlation::upsert))
+                    .route(
+                        "/correlations/exposure/{symbol}",
+                        web::get().to(correlation::exposure),
+                    )
+                    .route(
+                        "/correlations/{time_frame}",
+                        web::get().to(correlation::find),
+                    )
+                    .route(
+                        "/correlations/{time_frame}/{symbol}",
+                        web::get().to(correlation::find_symbol),
+                    )
+                    .route("/scans", web::get().to(scan::find))
+                    .route("/scans", web::put().to(scan::upsert))
                     .route("/watchlist", web::get().to(watch_list::find))
                     .route("/watchlist", web::put().to(watch_list::upsert))
                     .route("/watchlist", web::delete().to(watch_list::delete))
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Cluster {
+    pub symbols: Vec<String>,
+    pub avg_correlation: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Pair {
+    pub a: String,
+    pub b: String,
+    pub correlation: f64,
+    /// Correlation at every one of the last bars, from the oldest.
+    pub rolling: Vec<Option<f64>>,
+}
+
+/// Return correlations of the scanned universe, published by the scanner. Only the
+/// pairs above the scanner store floor are sent.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Correlations {
+    pub time_frame: String,
+    pub window: usize,
+    pub date: i64,
+    pub symbols: Vec<String>,
+    pub pairs: Vec<Pair>,
+    pub clusters: Vec<Cluster>,
+}
+
+/// Stored once per time frame, the pairs are stored by symbol.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CorrelationSummary {
+    pub time_frame: String,
+    pub window: usize,
+    pub date: i64,
+    pub symbols: Vec<String>,
+    pub clusters: Vec<Cluster>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Correlated {
+    pub symbol: String,
+    pub correlation: f64,
+    pub rolling: Vec<Option<f64>>,
+}
+
+/// Pairs of one symbol, so a document never grows beyond the size of the universe.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SymbolCorrelations {
+    pub time_frame: String,
+    pub symbol: String,
+    pub date: i64,
+    pub correlated: Vec<Correlated>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Exposure {
+    pub symbol: String,
+    pub list: String,
+    pub correlation: f64,
+    pub same_cluster: bool,
+}
+
+/// Upsert response of the portfolio and the watch list.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExposureResponse {
+    pub result: String,
+    pub exposure: Vec<Exposure>,
+}
+
+impl Correlations {
+    pub fn summary(&self) -> CorrelationSummary {
+        CorrelationSummary {
+            time_frame: self.time_frame.clone(),
+            window: self.window,
+            date: self.date,
+            symbols: self.symbols.clone(),
+            clusters: self.clusters.clone(),
+        }
+    }
+
+    /// Every pair is stored on both symbols. Symbols without pairs get an empty list.
+    pub fn by_symbol(&self) -> Vec<SymbolCorrelations> {
+        self.symbols
+            .iter()
+            .map(|symbol| SymbolCorrelations {
+                time_frame: self.time_frame.clone(),
+                symbol: symbol.clone(),
+                date: self.date,
+                correlated: self
+                    .pairs
+                    .iter()
+                    .filter_map(|pair| {
+                        let other = match (&pair.a == symbol, &pair.b == symbol) {
+                            (true, _) => &pair.b,
+                            (_, true) => &pair.a,
+                            _ => return None,
+                        };
+                        Some(Correlated {
+                            symbol: other.clone(),
+                            correlation: pair.correlation,
+                            rolling: pair.rolling.clone(),
+                        })
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+impl SymbolCorrelations {
+    pub fn correlation(&self, other: &str) -> Option<f64> {
+        self.correlated
+            .iter()
+            .find(|correlated| correlated.symbol == other)
+            .map(|correlated| correlated.correlation)
+    }
+
+    /// Held symbols moving with the symbol, or against it, above `threshold`.
+    pub fn exposure(
+        &self,
+        clusters: &[Cluster],
+        held: &[(String, &str)],
+        threshold: f64,
+    ) -> Vec<Exposure> {
+        let cluster = clusters
+            .iter()
+            .find(|cluster| cluster.symbols.contains(&self.symbol));
+
+        held.iter()
+            .filter(|(other, _)| *other != self.symbol)
+            .filter_map(|(other, list)| {
+                let correlation = self.correlation(other)?;
+                let same_cluster =
+                    matches!(cluster, Some(cluster) if cluster.symbols.contains(other));
+
+                match correlation.abs() >= threshold || same_cluster {
+                    true => Some(Exposure {
+                        symbol: other.to_owned(),
+                        list: (*list).to_owned(),
+                        correlation,
+                        same_cluster,
+                    }),
+                    false => None,
+                }
+            })
+            .collect()
+    }
+}
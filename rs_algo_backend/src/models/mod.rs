@@ -1,3 +1,4 @@
 pub mod app_state;
+pub mod correlation;
 pub mod db;
 pub use rs_algo_shared::models::*;
@@ -0,0 +1,168 @@
+use crate::db;
+use crate::error::RsAlgoError;
+use crate::models::app_state::AppState;
+use crate::models::correlation::{Correlations, Exposure};
+
+use actix_web::{web, HttpResponse};
+use rs_algo_shared::helpers::date::*;
+use rs_algo_shared::models::api::*;
+use std::env;
+use std::time::Instant;
+
+pub async fn find(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let time_frame = path.into_inner();
+
+    let correlations = db::correlation::find_by_time_frame(&time_frame, &state)
+        .await
+        .unwrap();
+
+    log::info!(
+        "[FIND CORRELATIONS] {} {:?} {:?}",
+        &time_frame,
+        Local::now(),
+        now.elapsed()
+    );
+
+    match correlations {
+        Some(correlations) => Ok(HttpResponse::Ok().json(correlations)),
+        None => Err(RsAlgoError::NotFound),
+    }
+}
+
+pub async fn find_symbol(
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let (time_frame, symbol) = path.into_inner();
+
+    let correlations = db::correlation::find_by_symbol(&time_frame, &symbol, &state)
+        .await
+        .unwrap();
+
+    log::info!(
+        "[FIND CORRELATIONS] {} {} {:?} {:?}",
+        &time_frame,
+        &symbol,
+        Local::now(),
+        now.elapsed()
+    );
+
+    match correlations {
+        Some(correlations) => Ok(HttpResponse::Ok().json(correlations)),
+        None => Err(RsAlgoError::NotFound),
+    }
+}
+
+pub async fn upsert(
+    correlations: String,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let correlations: Correlations = serde_json::from_str(&correlations).map_err(|err| {
+        log::error!("[CORRELATIONS] Wrong correlations: {}", err);
+        RsAlgoError::Unknown
+    })?;
+
+    db::correlation::upsert(&correlations, &state)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "[CORRELATIONS] Can't upsert {}: {}",
+                correlations.time_frame,
+                err
+            );
+            RsAlgoError::Unknown
+        })?;
+
+    log::info!(
+        "[CORRELATIONS UPSERTED] {} {} symbols {} pairs at {:?} in {:?}",
+        correlations.time_frame,
+        correlations.symbols.len(),
+        correlations.pairs.len(),
+        Local::now(),
+        now.elapsed()
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        result: "ok".to_owned(),
+    }))
+}
+
+pub async fn exposure(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let symbol = path.into_inner();
+    let exposure = find_exposure(&symbol, &state).await;
+
+    log::info!(
+        "[EXPOSURE] {} {} correlated {:?} {:?}",
+        &symbol,
+        exposure.len(),
+        Local::now(),
+        now.elapsed()
+    );
+
+    Ok(HttpResponse::Ok().json(exposure))
+}
+
+/// Portfolio and watch list symbols correlated with `symbol`.
+pub async fn find_exposure(symbol: &str, state: &web::Data<AppState>) -> Vec<Exposure> {
+    let time_frame = env::var("CORRELATION_TIME_FRAME").unwrap();
+    let threshold = env::var("CORRELATION_EXPOSURE_THRESHOLD")
+        .unwrap()
+        .parse::<f64>()
+        .unwrap();
+
+    let summary = match db::correlation::find_by_time_frame(&time_frame, state)
+        .await
+        .unwrap()
+    {
+        Some(summary) => summary,
+        None => return vec![],
+    };
+
+    let correlations = match db::correlation::find_by_symbol(&time_frame, symbol, state)
+        .await
+        .unwrap()
+    {
+        Some(correlations) => correlations,
+        None => return vec![],
+    };
+
+    let portfolio = db::portfolio::find_all(state).await.unwrap();
+    let watch_list = db::watch_list::find_all(state).await.unwrap();
+
+    let held: Vec<(String, &str)> = portfolio
+        .into_iter()
+        .map(|instrument| (instrument.symbol, "portfolio"))
+        .chain(
+            watch_list
+                .into_iter()
+                .map(|instrument| (instrument.symbol, "watch_list")),
+        )
+        .collect();
+
+    correlations.exposure(&summary.clusters, &held, threshold)
+}
+
+/// Logs and returns the positions a new entry duplicates.
+pub async fn warn_exposure(symbol: &str, state: &web::Data<AppState>) -> Vec<Exposure> {
+    let exposure = find_exposure(symbol, state).await;
+    for exposure in exposure.iter() {
+        log::warn!(
+            "[EXPOSURE] {} duplicates {} in the {} ({:.2})",
+            symbol,
+            exposure.symbol,
+            exposure.list,
+            exposure.correlation
+        );
+    }
+    exposure
+}
@@ -11,6 +11,7 @@ use rs_algo_shared::scanner::instrument::*;
 
 use actix_files as fs;
 use actix_web::{web, HttpResponse};
+use bson::Bson;
 use rs_algo_shared::helpers::date::Local;
 use serde::{Deserialize, Serialize};
 use std::env;
@@ -23,6 +24,16 @@ pub struct Params {
     pub time_frame: String,
 }
 
+/// `leaders_first` sorts the results by the relative strength rank and
+/// `leaders_only` keeps the ones ranked `RELATIVE_STRENGTH_MIN_RANK` or higher.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FindParams {
+    #[serde(default)]
+    pub leaders_first: bool,
+    #[serde(default)]
+    pub leaders_only: bool,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct SymbolQuery {
     pub symbol: String,
@@ -37,8 +48,11 @@ pub async fn find_one(
 
     let instrument = db::instrument::find_by_symbol(&symbol, &state)
         .await
-        .unwrap()
-        .unwrap();
+        .map_err(|err| {
+            log::error!("[INSTRUMENT] Can't find {}: {}", symbol, err);
+            RsAlgoError::Unknown
+        })?
+        .ok_or(RsAlgoError::NotFound)?;
 
     log::info!(
         "[FINDONE] {} {} {:?}",
@@ -50,6 +64,59 @@ pub async fn find_one(
     Ok(HttpResponse::Ok().json(instrument))
 }
 
+pub async fn find_candles(
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let (time_frame, symbol) = path.into_inner();
+
+    let candles = db::instrument::find_candles(&symbol, &time_frame, &state)
+        .await
+        .map_err(|err| {
+            log::error!("[CANDLES] Can't find {} {}: {}", symbol, time_frame, err);
+            RsAlgoError::Unknown
+        })?;
+
+    log::info!(
+        "[CANDLES] {} {} {} {:?}",
+        &symbol,
+        &time_frame,
+        Local::now(),
+        now.elapsed()
+    );
+
+    match candles {
+        Some(candles) => Ok(HttpResponse::Ok().json(candles)),
+        None => Err(RsAlgoError::NotFound),
+    }
+}
+
+pub async fn find_last_candles(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let time_frame = path.into_inner();
+
+    let last_candles = db::instrument::find_last_candles(&time_frame, &state)
+        .await
+        .map_err(|err| {
+            log::error!("[LAST CANDLES] Can't find {}: {}", time_frame, err);
+            RsAlgoError::Unknown
+        })?;
+
+    log::info!(
+        "[LAST CANDLES] {} {} instruments {} {:?}",
+        &time_frame,
+        last_candles.len(),
+        Local::now(),
+        now.elapsed()
+    );
+
+    Ok(HttpResponse::Ok().json(last_candles))
+}
+
 pub async fn chart(
     path: web::Path<String>,
     state: web::Data<AppState>,
@@ -60,8 +127,11 @@ pub async fn chart(
 
     let instrument = db::instrument::find_by_symbol(&symbol, &state)
         .await
-        .unwrap()
-        .unwrap();
+        .map_err(|err| {
+            log::error!("[INSTRUMENT] Can't find {}: {}", symbol, err);
+            RsAlgoError::Unknown
+        })?
+        .ok_or(RsAlgoError::NotFound)?;
 
     let output_file = [
         &env::var("BACKEND_PLOTTER_OUTPUT_FOLDER").unwrap(),
@@ -95,13 +165,26 @@ pub async fn chart(
     Ok(file.use_last_modified(true))
 }
 
-pub async fn find(params: String, state: web::Data<AppState>) -> Result<HttpResponse, RsAlgoError> {
+pub async fn find(
+    query: web::Query<FindParams>,
+    params: String,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
     let now = Instant::now();
     let strategy = General::new().unwrap();
 
-    let instruments = db::instrument::find_by_params(&state, params, strategy)
-        .await
-        .unwrap();
+    let instruments = db::instrument::find_by_params(
+        &state,
+        params,
+        query.leaders_first,
+        query.leaders_only,
+        strategy,
+    )
+    .await
+    .map_err(|err| {
+        log::error!("[FIND] Can't find instruments: {}", err);
+        RsAlgoError::Unknown
+    })?;
 
     log::info!("[FIND] {:?} {:?}", Local::now(), now.elapsed());
 
@@ -116,7 +199,10 @@ pub async fn find_detail(
     let strategy = General::new().unwrap();
     let instruments = db::instrument::find_detail_by_params(&state, params, strategy)
         .await
-        .unwrap();
+        .map_err(|err| {
+            log::error!("[FIND] Can't find instruments: {}", err);
+            RsAlgoError::Unknown
+        })?;
 
     log::info!("[FIND] {:?} {:?}", Local::now(), now.elapsed());
 
@@ -128,13 +214,84 @@ pub async fn find_all(
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, RsAlgoError> {
     let now = Instant::now();
-    let instruments = db::instrument::find_all(&state).await.unwrap();
+    let instruments = db::instrument::find_all(&state).await.map_err(|err| {
+        log::error!("[FIND ALL] Can't find instruments: {}", err);
+        RsAlgoError::Unknown
+    })?;
 
     log::info!("[FIND ALL] {:?} {:?}", Local::now(), now.elapsed());
 
     Ok(HttpResponse::Ok().json(instruments))
 }
 
+pub async fn upsert_relative_strength(
+    ranks: String,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let ranks: Vec<serde_json::Value> = serde_json::from_str(&ranks).unwrap();
+
+    for rank in ranks.iter() {
+        let symbol = rank.get("symbol").and_then(|symbol| symbol.as_str());
+        let time_frame = rank
+            .get("time_frame")
+            .and_then(|time_frame| time_frame.as_str());
+
+        if let (Some(symbol), Some(time_frame), Ok(Bson::Document(relative_strength))) =
+            (symbol, time_frame, Bson::try_from(rank.clone()))
+        {
+            db::instrument::update_relative_strength(
+                symbol,
+                time_frame,
+                &relative_strength,
+                &state,
+            )
+            .await
+            .map_err(|err| {
+                log::error!("[RELATIVE STRENGTH] Can't update {}: {}", symbol, err);
+                RsAlgoError::Unknown
+            })?;
+        }
+    }
+
+    log::info!(
+        "[RELATIVE STRENGTH UPSERTED] {} instruments at {:?} in {:?}",
+        ranks.len(),
+        Local::now(),
+        now.elapsed()
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        result: "ok".to_owned(),
+    }))
+}
+
+pub async fn upsert_live(
+    update: String,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let update: serde_json::Value = serde_json::from_str(&update).unwrap();
+    let symbol = update.get("symbol").and_then(|symbol| symbol.as_str());
+    let time_frame = update
+        .get("time_frame")
+        .and_then(|time_frame| time_frame.as_str());
+
+    if let (Some(symbol), Some(time_frame), Ok(Bson::Document(live))) =
+        (symbol, time_frame, Bson::try_from(update.clone()))
+    {
+        db::instrument::update_live(symbol, time_frame, &live, &state)
+            .await
+            .map_err(|err| {
+                log::error!("[LIVE] Can't update {}: {}", symbol, err);
+                RsAlgoError::Unknown
+            })?;
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        result: "ok".to_owned(),
+    }))
+}
+
 pub async fn upsert(
     query: web::Query<Params>,
     instrument: String,
@@ -143,7 +300,16 @@ pub async fn upsert(
     let mode = &query.mode;
     let time_frame = &query.time_frame;
 
-    let mut instrument: Instrument = serde_json::from_str(&instrument).unwrap();
+    let body: serde_json::Value = serde_json::from_str(&instrument).unwrap();
+    let analysis = match body.get("analysis") {
+        Some(analysis) => match Bson::try_from(analysis.clone()) {
+            Ok(Bson::Document(analysis)) => Some(analysis),
+            _ => None,
+        },
+        None => None,
+    };
+
+    let mut instrument: Instrument = serde_json::from_value(body).unwrap();
     let symbol = instrument.symbol.clone();
     //let execution_mode = env::var("EXECUTION_MODE").unwrap();
     //let backtest_market = env::var("BACKTEST_MARKET").unwrap_or("".to_string());
@@ -190,7 +356,24 @@ pub async fn upsert(
         let _insert_result =
             db::instrument::upsert_instrument(mode, time_frame, &instrument, &state)
                 .await
-                .unwrap();
+                .map_err(|err| {
+                    log::error!("[INSTRUMENT] Can't upsert {}: {}", symbol, err);
+                    RsAlgoError::Unknown
+                })?;
+
+        if let Some(analysis) = &analysis {
+            db::instrument::update_analysis(
+                &db::instrument::instruments_collection_name(mode, time_frame),
+                &instrument.symbol,
+                analysis,
+                &state,
+            )
+            .await
+            .map_err(|err| {
+                log::error!("[INSTRUMENT] Can't update {} analysis: {}", symbol, err);
+                RsAlgoError::Unknown
+            })?;
+        }
 
         log::info!(
             "{} {:?} at {:?} in {:?}",
@@ -212,12 +395,34 @@ pub async fn upsert(
 
     if !mode.contains("backtest") && insert_compact_instruments {
         let now = Instant::now();
+        let compact_symbol = instrument.symbol.clone();
         let _insert_compact = db::instrument::upsert_compact_instrument(
             compact_instrument(instrument).unwrap(),
             &state,
         )
         .await
-        .unwrap();
+        .map_err(|err| {
+            log::error!("[COMPACT INSTRUMENT] Can't upsert {}: {}", symbol, err);
+            RsAlgoError::Unknown
+        })?;
+
+        if let Some(analysis) = &analysis {
+            db::instrument::update_analysis(
+                &env::var("DB_INSTRUMENTS_COMPACT_COLLECTION").unwrap(),
+                &compact_symbol,
+                analysis,
+                &state,
+            )
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "[COMPACT INSTRUMENT] Can't update {} analysis: {}",
+                    symbol,
+                    err
+                );
+                RsAlgoError::Unknown
+            })?;
+        }
 
         log::info!(
             "[COMPACT INSTRUMENT UPSERTED] {:?} at {:?} in {:?}",
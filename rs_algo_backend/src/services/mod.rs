@@ -1,6 +1,8 @@
 pub mod back_test;
 pub mod bot;
+pub mod correlation;
 pub mod index;
 pub mod instrument;
 pub mod portfolio;
+pub mod scan;
 pub mod watch_list;
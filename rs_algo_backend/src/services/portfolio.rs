@@ -1,7 +1,9 @@
+use super::correlation;
 use super::instrument;
 use crate::db;
 use crate::error::RsAlgoError;
 use crate::models::app_state::AppState;
+use crate::models::correlation::ExposureResponse;
 
 use actix_web::{web, HttpResponse};
 use bson::doc;
@@ -72,8 +74,11 @@ pub async fn upsert(
         Local::now(),
         now.elapsed()
     );
-    Ok(HttpResponse::Ok().json(ApiResponse {
+
+    let exposure = correlation::warn_exposure(&symbol, &state).await;
+    Ok(HttpResponse::Ok().json(ExposureResponse {
         result: "ok".to_owned(),
+        exposure,
     }))
 }
 
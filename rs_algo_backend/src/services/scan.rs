@@ -0,0 +1,73 @@
+use crate::db;
+use crate::error::RsAlgoError;
+use crate::models::app_state::AppState;
+
+use actix_web::{web, HttpResponse};
+use bson::Bson;
+use rs_algo_shared::helpers::date::*;
+use rs_algo_shared::models::api::*;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Params {
+    pub offset: u64,
+    pub limit: i64,
+}
+
+pub async fn find(
+    query: web::Query<Params>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+
+    let scans = db::scan::find(query.offset, query.limit, &state)
+        .await
+        .unwrap();
+
+    log::info!(
+        "[FIND SCANS] {} scans {:?} {:?}",
+        scans.len(),
+        Local::now(),
+        now.elapsed()
+    );
+
+    Ok(HttpResponse::Ok().json(scans))
+}
+
+pub async fn upsert(
+    summary: String,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, RsAlgoError> {
+    let now = Instant::now();
+    let summary: serde_json::Value = serde_json::from_str(&summary).unwrap();
+
+    let mode = summary
+        .get("mode")
+        .and_then(|mode| mode.as_str())
+        .unwrap_or_default()
+        .to_owned();
+
+    let started_at = summary
+        .get("started_at")
+        .and_then(|started_at| started_at.as_i64())
+        .unwrap_or_default();
+
+    if let Ok(Bson::Document(doc)) = Bson::try_from(summary) {
+        let _upsert = db::scan::upsert(&mode, started_at, doc, &state)
+            .await
+            .unwrap();
+    }
+
+    log::info!(
+        "[SCAN UPSERTED] {} started at {} at {:?} in {:?}",
+        mode,
+        started_at,
+        Local::now(),
+        now.elapsed()
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        result: "ok".to_owned(),
+    }))
+}
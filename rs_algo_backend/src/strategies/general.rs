@@ -18,6 +18,7 @@ pub struct General {
     pub query: Document,
     pub max_pattern_date: DbDateTime,
     pub max_activated_date: DbDateTime,
+    pub max_results: usize,
 }
 //FIMXE impl trait (fix asyn-trait)
 impl General {
@@ -32,15 +33,32 @@ impl General {
             .parse::<i64>()
             .unwrap();
 
+        let max_results = env::var("GENERAL_MAX_RESULTS")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
         Ok(Self {
             query: doc! {},
             max_pattern_date: to_dbtime(Local::now() - Duration::days(max_pattern_days)),
             max_activated_date: to_dbtime(
                 Local::now() - Duration::days(max_pattern_activated_days),
             ),
+            max_results,
         })
     }
 
+    /// The General screen is meant to be a short list, a looser query floods it.
+    pub fn check_results(&self, len: usize) {
+        if len > self.max_results {
+            log::warn!(
+                "[STRATEGY] General screen returned {} instruments, more than {}",
+                len,
+                self.max_results
+            );
+        }
+    }
+
     pub fn query(&self) -> Document {
         let minimum_pattern_target = env::var("MINIMUM_PATTERN_TARGET")
             .unwrap()
@@ -54,6 +72,21 @@ impl General {
 
         let min_volume = env::var("MIN_VOLUME").unwrap().parse::<f64>().unwrap();
 
+        let support_max_distance = env::var("SUPPORT_MAX_DISTANCE")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let support_min_strength = env::var("SUPPORT_MIN_STRENGTH")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let poc_max_distance = env::var("POC_MAX_DISTANCE")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
         doc! {
         "$and": [
             {"$expr": {"$gte": ["$avg_volume",min_volume,]}},
@@ -120,6 +153,7 @@ impl General {
                             {"$expr": {"$gte": ["$prev_price","$indicators.bb.prev_b"]}},
                         ]},
                     ]},
+                    {"analysis.regime.current": { "$nin": ["TrendingUp", "TrendingDown"] }},
                     {"$expr": {"$gte": ["$indicators.rsi.current_a", 30]}},
                     {"$expr": {"$lte": ["$indicators.rsi.current_a", 40]}},
                     {"$and": [
@@ -127,6 +161,24 @@ impl General {
                         {"$expr": {"$ne": [{ "$last": "$patterns.local_patterns.pattern_type" }, "HigherHighsHigherLows"] }},
                     ]},
                 ]},
+                {"analysis.head_and_shoulders": {"$elemMatch" : {
+                    "neckline_break.date": { "$gte" : self.max_activated_date },
+                }}},
+                {"analysis.levels": {"$elemMatch" : {
+                    "level_type": "Support",
+                    "strength": { "$gte" : support_min_strength },
+                    "distance": { "$lte" : support_max_distance },
+                }}},
+                {"analysis.fibonacci.bounced_from": { "$in": [0.382, 0.5, 0.618] }},
+                {"$and": [
+                    {"analysis.regime.current": "TrendingUp"},
+                    {"analysis.vwap.distance": { "$gt" : 0 }},
+                    {"analysis.volume_profile.poc_distance": { "$lte" : poc_max_distance }},
+                ]},
+                {"analysis.divergences": {"$elemMatch" : {
+                    "confirmed_date": { "$gte" : self.max_pattern_date },
+                    "divergence_type": { "$in": ["RegularBullish", "RegularBearish"] },
+                }}},
                 {"$and": [
                     {"$expr": {"$gte": [{ "$last": "$divergences.data.date" }, self.max_pattern_date ] }},
                     {"$expr": {"$in": [{ "$last": "$divergences.data.divergence_type" }, ["Bullish", "Bearish"]] }},
@@ -31,3 +31,166 @@ pub mod bollinger_bands_middle_band;
 pub mod bollinger_bands_reversals;
 pub mod ema_scalping;
 pub mod ema_scalping2;
+
+#[cfg(test)]
+mod tests {
+    use super::bollinger_bands_middle_band::BollingerBandsMiddleBand;
+    use super::bollinger_bands_reversals::BollingerBandsReversals;
+    use super::ema_scalping::EmaScalping;
+    use super::ema_scalping2::EmaScalping2;
+    use super::strategy::Strategy;
+
+    use rs_algo_shared::models::backtest_instrument::*;
+    use rs_algo_shared::models::market::Market;
+    use rs_algo_shared::models::pricing::Pricing;
+    use rs_algo_shared::models::strategy::StrategyType;
+    use rs_algo_shared::models::time_frame::TimeFrame;
+    use rs_algo_shared::scanner::instrument::Instrument;
+    use rs_algo_synthetic::{dates, Generator, Shape, ShapeType};
+
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Once;
+
+    static ENV: Once = Once::new();
+
+    /// Settings of the scanner feeding the backtest, then the backtest ones.
+    fn load_env() {
+        ENV.call_once(|| {
+            let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+            for config in [
+                "rs_algo_scanner/deployment/backtest/D/chart/config.yaml",
+                "rs_algo_backtest/deployment/forex/chart/config.yaml",
+            ] {
+                rs_algo_synthetic::config::load(&root.join(config)).unwrap();
+            }
+        });
+    }
+
+    fn instrument(generator: &Generator) -> Instrument {
+        let from = Utc.with_ymd_and_hms(2023, 1, 2, 0, 0, 0).unwrap();
+        let mut instrument = Instrument::new()
+            .symbol("SYNTH")
+            .market(Market::Forex)
+            .time_frame(TimeFrame::new("M30"))
+            .build()
+            .unwrap();
+        instrument
+            .set_data(generator.generate(&dates(1000, 30, from)))
+            .unwrap();
+        instrument
+    }
+
+    fn pricing() -> Pricing {
+        serde_json::from_value(json!({
+            "symbol": "SYNTH",
+            "ask": 100.0002,
+            "bid": 100.,
+            "spread": 0.0002,
+            "pip_size": 0.0001,
+            "percentage": 0.,
+        }))
+        .unwrap()
+    }
+
+    async fn backtest(
+        strategy: &(impl Strategy + Clone),
+        instrument: &Instrument,
+    ) -> BackTestInstrumentResult {
+        let result = strategy
+            .clone()
+            .test(instrument, &mut pricing(), 10000., 10000., 0.1)
+            .await;
+
+        match result {
+            BackTestResult::BackTestInstrumentResult(result) => result,
+            _ => panic!("{} returned no result", strategy.name()),
+        }
+    }
+
+    /// Runs the strategy twice and compares the trades with the ones recorded in
+    /// `snapshots/`. A missing snapshot is recorded, delete it to accept a change.
+    async fn assert_regression(strategy: impl Strategy + Clone, instrument: &Instrument) {
+        let result = backtest(&strategy, instrument).await;
+        let len = instrument.data().len();
+
+        assert_eq!(result.trades, result.instrument.trades_out.len());
+        assert_eq!(result.trades, result.wining_trades + result.losing_trades);
+        assert!(result
+            .instrument
+            .trades_out
+            .iter()
+            .all(|trade| trade.index_in <= trade.index_out && trade.index_out < len));
+
+        let trades = |result: &BackTestInstrumentResult| {
+            let trades: Vec<_> = result
+                .instrument
+                .trades_out
+                .iter()
+                .map(|trade| json!([trade.index_in, trade.index_out, trade.profit]))
+                .collect();
+            serde_json::to_string_pretty(&json!({
+                "trades": trades,
+                "stop_losses": result.stop_losses,
+                "net_profit": result.net_profit,
+            }))
+            .unwrap()
+        };
+
+        let snapshot = trades(&result);
+        assert_eq!(snapshot, trades(&backtest(&strategy, instrument).await));
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("snapshots")
+            .join([strategy.name(), ".json"].concat());
+        match fs::read_to_string(&path) {
+            Ok(recorded) => assert_eq!(snapshot, recorded, "{}", strategy.name()),
+            Err(_) => {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, snapshot).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn bollinger_bands_strategies() {
+        load_env();
+        let generator = Generator::new(11, "mean_reverting".parse().unwrap())
+            .shape(Shape::new(ShapeType::DoubleBottom, 600));
+        let instrument = instrument(&generator);
+        let strategy_type = Some(StrategyType::LongShort);
+
+        assert_regression(
+            BollingerBandsReversals::new(Some("M30"), None, strategy_type.clone()).unwrap(),
+            &instrument,
+        )
+        .await;
+        assert_regression(
+            BollingerBandsMiddleBand::new(Some("M30"), None, strategy_type).unwrap(),
+            &instrument,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn ema_strategies() {
+        load_env();
+        let generator = Generator::new(12, "regime".parse().unwrap())
+            .shape(Shape::new(ShapeType::HeadAndShoulders, 600));
+        let instrument = instrument(&generator);
+        let strategy_type = Some(StrategyType::LongShort);
+
+        assert_regression(
+            EmaScalping::new(Some("M30"), None, strategy_type.clone()).unwrap(),
+            &instrument,
+        )
+        .await;
+        assert_regression(
+            EmaScalping2::new(Some("M30"), None, strategy_type).unwrap(),
+            &instrument,
+        )
+        .await;
+    }
+}
@@ -0,0 +1,111 @@
+//! Regime smoothing of a 5,000 bar series: the helpers as they were, reading
+//! `LOGARITHMIC_SCANNER` from the environment on every call, against the analysis context.
+//!
+//! `cargo bench --bench smoothing`
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/helpers/regression.rs"]
+mod regression;
+
+use regression::{smooth, Smoother};
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const BARS: usize = 5000;
+const WINDOW: usize = 50;
+const BANDWIDTH: f64 = 0.05;
+const RUNS: u32 = 20;
+
+mod env_lookup {
+    use std::env;
+
+    fn gauss_const(h: f64) -> f64 {
+        let pi = std::f64::consts::PI * 2.;
+        1. / (h * pi.sqrt())
+    }
+
+    fn gauss_exp(x: f64, y: f64, h: f64) -> f64 {
+        let den = h * h;
+        let num = -0.5 * (y - x).powf(2.);
+        num / den
+    }
+
+    fn kernel_function(h: f64, x: f64, y: f64, logarithmic: bool) -> f64 {
+        let gauss_exp = match logarithmic {
+            true => gauss_exp(x, y, h).exp(),
+            false => gauss_exp(x, y, h),
+        };
+        gauss_const(h) * gauss_exp
+    }
+
+    fn weights(bandwidth: f64, x: f64, data: &[f64], logarithmic: bool) -> Vec<f64> {
+        let kernel_sum: f64 = data
+            .iter()
+            .map(|x_i| kernel_function(bandwidth, x, *x_i, logarithmic))
+            .sum();
+        data.iter()
+            .map(|x_i| kernel_function(bandwidth, x, *x_i, logarithmic) / kernel_sum)
+            .collect()
+    }
+
+    #[allow(clippy::ptr_arg)]
+    pub fn kernel_regression(bandwidth: f64, x: f64, data: &Vec<f64>) -> f64 {
+        let logarithmic = env::var("LOGARITHMIC_SCANNER")
+            .unwrap()
+            .parse::<bool>()
+            .unwrap();
+        let w = weights(bandwidth, x, data, logarithmic);
+        data.iter().zip(w.iter()).map(|(a, b)| a * b).sum()
+    }
+
+    pub fn smooth(data: &[f64], window: usize) -> Vec<f64> {
+        (0..data.len())
+            .map(|index| {
+                let from = (index + 1).saturating_sub(window);
+                kernel_regression(super::BANDWIDTH, data[index], &data[from..=index].to_vec())
+            })
+            .collect()
+    }
+}
+
+fn closes() -> Vec<f64> {
+    (0..BARS)
+        .map(|index| {
+            let x = index as f64;
+            (100. + x * 0.01 + (x / 15.).sin() * 8. + (x / 4.).cos() * 2.).ln()
+        })
+        .collect()
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let closes = closes();
+    let smoother = Smoother::Kernel {
+        bandwidth: BANDWIDTH,
+    };
+    env::set_var("LOGARITHMIC_SCANNER", "true");
+
+    let before = time(|| {
+        black_box(env_lookup::smooth(black_box(&closes), WINDOW));
+    });
+    let after = time(|| {
+        black_box(smooth(&smoother, black_box(&closes), WINDOW));
+    });
+
+    println!(
+        "kernel smoothing, {} bars: env lookup {:?}, context {:?} ({:.1}x)",
+        BARS,
+        before,
+        after,
+        before.as_secs_f64() / after.as_secs_f64()
+    );
+}
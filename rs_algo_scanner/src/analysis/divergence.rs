@@ -0,0 +1,283 @@
+use super::{Bars, Swings};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DivergenceIndicator {
+    Rsi,
+    Macd,
+    Stoch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DivergenceType {
+    RegularBullish,
+    RegularBearish,
+    HiddenBullish,
+    HiddenBearish,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Divergence {
+    pub indicator: DivergenceIndicator,
+    pub divergence_type: DivergenceType,
+    pub price_points: Vec<(usize, f64)>,
+    pub indicator_points: Vec<(usize, f64)>,
+    pub date: DbDateTime,
+    pub confirmed_index: Option<usize>,
+    pub confirmed_date: Option<DbDateTime>,
+}
+
+/// Compares every pair of consecutive price swings with the oscillator extremes found
+/// within `window` bars of them. Regular divergences point to a reversal and hidden
+/// ones to a continuation. A divergence is confirmed on the first later bar where both
+/// the close and the oscillator move away from the second swing.
+pub fn detect(
+    bars: &Bars,
+    swings: &Swings,
+    indicator: DivergenceIndicator,
+    values: &[f64],
+    window: usize,
+) -> Vec<Divergence> {
+    let values = aligned(values, bars.close.len());
+    let mut result = vec![];
+
+    for pair in swings.lows.windows(2) {
+        let (first, second) = (pair[0], pair[1]);
+        let oscillator = (
+            oscillator_swing(&values, first.0, window, false),
+            oscillator_swing(&values, second.0, window, false),
+        );
+
+        if let (Some(first_oscillator), Some(second_oscillator)) = oscillator {
+            let divergence_type = if second.1 < first.1 && second_oscillator.1 > first_oscillator.1
+            {
+                Some(DivergenceType::RegularBullish)
+            } else if second.1 > first.1 && second_oscillator.1 < first_oscillator.1 {
+                Some(DivergenceType::HiddenBullish)
+            } else {
+                None
+            };
+
+            if let Some(divergence_type) = divergence_type {
+                let confirmed_index = (second.0 + 1..bars.close.len()).find(|index| {
+                    bars.close[*index] > bars.close[second.0]
+                        && values[*index] > second_oscillator.1
+                });
+
+                result.push(divergence(
+                    bars,
+                    indicator,
+                    divergence_type,
+                    vec![first, second],
+                    vec![first_oscillator, second_oscillator],
+                    confirmed_index,
+                ));
+            }
+        }
+    }
+
+    for pair in swings.highs.windows(2) {
+        let (first, second) = (pair[0], pair[1]);
+        let oscillator = (
+            oscillator_swing(&values, first.0, window, true),
+            oscillator_swing(&values, second.0, window, true),
+        );
+
+        if let (Some(first_oscillator), Some(second_oscillator)) = oscillator {
+            let divergence_type = if second.1 > first.1 && second_oscillator.1 < first_oscillator.1
+            {
+                Some(DivergenceType::RegularBearish)
+            } else if second.1 < first.1 && second_oscillator.1 > first_oscillator.1 {
+                Some(DivergenceType::HiddenBearish)
+            } else {
+                None
+            };
+
+            if let Some(divergence_type) = divergence_type {
+                let confirmed_index = (second.0 + 1..bars.close.len()).find(|index| {
+                    bars.close[*index] < bars.close[second.0]
+                        && values[*index] < second_oscillator.1
+                });
+
+                result.push(divergence(
+                    bars,
+                    indicator,
+                    divergence_type,
+                    vec![first, second],
+                    vec![first_oscillator, second_oscillator],
+                    confirmed_index,
+                ));
+            }
+        }
+    }
+
+    result.sort_by_key(|divergence| divergence.price_points[1].0);
+    result
+}
+
+fn divergence(
+    bars: &Bars,
+    indicator: DivergenceIndicator,
+    divergence_type: DivergenceType,
+    price_points: Vec<(usize, f64)>,
+    indicator_points: Vec<(usize, f64)>,
+    confirmed_index: Option<usize>,
+) -> Divergence {
+    Divergence {
+        indicator,
+        divergence_type,
+        date: bars.dates[price_points[1].0],
+        price_points,
+        indicator_points,
+        confirmed_index,
+        confirmed_date: confirmed_index.map(|index| bars.dates[index]),
+    }
+}
+
+/// Oscillator maximum (or minimum) within `window` bars of a price swing.
+fn oscillator_swing(
+    values: &[f64],
+    index: usize,
+    window: usize,
+    maximum: bool,
+) -> Option<(usize, f64)> {
+    let from = index.saturating_sub(window);
+    let to = (index + window).min(values.len().saturating_sub(1));
+
+    (from..=to)
+        .filter(|index| values[*index].is_finite())
+        .map(|index| (index, values[index]))
+        .reduce(|best, current| match maximum {
+            true if current.1 > best.1 => current,
+            false if current.1 < best.1 => current,
+            _ => best,
+        })
+}
+
+/// Indicators can be shorter than the price series, so they are aligned by the last bar.
+fn aligned(values: &[f64], len: usize) -> Vec<f64> {
+    match values.len() >= len {
+        true => values[values.len() - len..].to_vec(),
+        false => {
+            let mut result = vec![f64::NAN; len - values.len()];
+            result.extend_from_slice(values);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(len: usize, base: f64, points: &[(usize, f64)]) -> Vec<f64> {
+        let mut values = vec![base; len];
+        for (index, value) in points {
+            values[*index] = *value;
+        }
+        values
+    }
+
+    fn swing_lows(lows: Vec<(usize, f64)>) -> Swings {
+        Swings {
+            highs: vec![],
+            lows,
+        }
+    }
+
+    fn swing_highs(highs: Vec<(usize, f64)>) -> Swings {
+        Swings {
+            highs,
+            lows: vec![],
+        }
+    }
+
+    #[test]
+    fn regular_bullish_is_confirmed_when_price_and_oscillator_turn_up() {
+        let closes = series(30, 100., &[(10, 90.), (20, 85.), (21, 86.), (22, 88.)]);
+        let rsi = series(30, 50., &[(10, 25.), (20, 35.), (21, 38.)]);
+        let bars = Bars::from_closes(&closes, 0.);
+        let swings = swing_lows(vec![(10, 90.), (20, 85.)]);
+
+        let divergences = detect(&bars, &swings, DivergenceIndicator::Rsi, &rsi, 3);
+        assert_eq!(divergences.len(), 1);
+
+        let divergence = &divergences[0];
+        assert_eq!(divergence.divergence_type, DivergenceType::RegularBullish);
+        assert_eq!(divergence.indicator, DivergenceIndicator::Rsi);
+        assert_eq!(divergence.indicator_points, vec![(10, 25.), (20, 35.)]);
+        assert_eq!(divergence.date, bars.dates[20]);
+        assert_eq!(divergence.confirmed_index, Some(21));
+        assert_eq!(divergence.confirmed_date, Some(bars.dates[21]));
+    }
+
+    #[test]
+    fn hidden_bearish_on_lower_price_high_and_higher_oscillator_high() {
+        let closes = series(30, 100., &[(10, 110.), (20, 105.)]);
+        let macd = series(30, 50., &[(10, 60.), (20, 70.)]);
+        let bars = Bars::from_closes(&closes, 0.);
+        let swings = swing_highs(vec![(10, 110.), (20, 105.)]);
+
+        let divergences = detect(&bars, &swings, DivergenceIndicator::Macd, &macd, 3);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(
+            divergences[0].divergence_type,
+            DivergenceType::HiddenBearish
+        );
+        assert_eq!(divergences[0].confirmed_index, Some(21));
+    }
+
+    #[test]
+    fn regular_bearish_stays_unconfirmed_while_price_keeps_rising() {
+        let closes: Vec<f64> = (0..30).map(|index| 100. + index as f64).collect();
+        let stoch = series(30, 50., &[(10, 80.), (20, 70.)]);
+        let bars = Bars::from_closes(&closes, 0.);
+        let swings = swing_highs(vec![(10, 110.), (20, 120.)]);
+
+        let divergences = detect(&bars, &swings, DivergenceIndicator::Stoch, &stoch, 3);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(
+            divergences[0].divergence_type,
+            DivergenceType::RegularBearish
+        );
+        assert_eq!(divergences[0].confirmed_index, None);
+        assert_eq!(divergences[0].confirmed_date, None);
+    }
+
+    #[test]
+    fn hidden_bullish_uses_oscillator_extremes_near_the_swings() {
+        let closes = series(30, 100., &[(10, 90.), (20, 95.)]);
+        let rsi = series(30, 50., &[(11, 30.), (19, 20.)]);
+        let bars = Bars::from_closes(&closes, 0.);
+        let swings = swing_lows(vec![(10, 90.), (20, 95.)]);
+
+        let divergences = detect(&bars, &swings, DivergenceIndicator::Rsi, &rsi, 3);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(
+            divergences[0].divergence_type,
+            DivergenceType::HiddenBullish
+        );
+        assert_eq!(divergences[0].indicator_points, vec![(11, 30.), (19, 20.)]);
+    }
+
+    #[test]
+    fn no_divergence_when_oscillator_agrees_with_price() {
+        let closes = series(30, 100., &[(10, 90.), (20, 85.)]);
+        let rsi = series(30, 50., &[(10, 30.), (20, 20.)]);
+        let bars = Bars::from_closes(&closes, 0.);
+        let swings = swing_lows(vec![(10, 90.), (20, 85.)]);
+
+        assert!(detect(&bars, &swings, DivergenceIndicator::Rsi, &rsi, 3).is_empty());
+    }
+
+    #[test]
+    fn shorter_indicators_are_aligned_by_the_last_bar() {
+        let values = aligned(&[1., 2., 3.], 5);
+        assert!(values[0].is_nan() && values[1].is_nan());
+        assert_eq!(&values[2..], &[1., 2., 3.]);
+        assert_eq!(aligned(&[1., 2., 3.], 2), vec![2., 3.]);
+    }
+}
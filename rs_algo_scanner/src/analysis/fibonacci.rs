@@ -0,0 +1,227 @@
+use super::{Bars, Swings};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use serde::Serialize;
+
+const RETRACEMENTS: [f64; 5] = [0.236, 0.382, 0.5, 0.618, 0.786];
+const EXTENSIONS: [f64; 5] = [1.272, 1.414, 1.618, 2., 2.618];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SwingDirection {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FibonacciLevel {
+    pub ratio: f64,
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fibonacci {
+    pub direction: SwingDirection,
+    pub swing_start: (usize, f64),
+    pub swing_end: (usize, f64),
+    pub retracements: Vec<FibonacciLevel>,
+    pub extensions: Vec<FibonacciLevel>,
+    /// Retracement ratio the last close sits at.
+    pub at_level: Option<f64>,
+    /// Retracement ratio touched during the last `bounce_bars` bars and left in the
+    /// direction of the swing.
+    pub bounced_from: Option<f64>,
+    pub date: DbDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct FibonacciParams {
+    pub lookback: usize,
+    pub tolerance: f64,
+    pub bounce_bars: usize,
+}
+
+/// The dominant swing goes from the lowest swing low to the highest swing high of
+/// the last `lookback` bars, up or down depending on which one came last.
+pub fn detect(bars: &Bars, swings: &Swings, params: &FibonacciParams) -> Option<Fibonacci> {
+    let len = bars.close.len();
+    let from = len.saturating_sub(params.lookback);
+
+    let high = swings
+        .highs
+        .iter()
+        .filter(|swing| swing.0 >= from)
+        .copied()
+        .reduce(|best, swing| match swing.1 > best.1 {
+            true => swing,
+            false => best,
+        })?;
+
+    let low = swings
+        .lows
+        .iter()
+        .filter(|swing| swing.0 >= from)
+        .copied()
+        .reduce(|best, swing| match swing.1 < best.1 {
+            true => swing,
+            false => best,
+        })?;
+
+    let height = high.1 - low.1;
+    if height <= 0. {
+        return None;
+    }
+
+    let (direction, swing_start, swing_end) = match high.0 > low.0 {
+        true => (SwingDirection::Up, low, high),
+        false => (SwingDirection::Down, high, low),
+    };
+
+    let level = |ratio: f64| FibonacciLevel {
+        ratio,
+        price: match direction {
+            SwingDirection::Up => swing_end.1 - ratio * height,
+            SwingDirection::Down => swing_end.1 + ratio * height,
+        },
+    };
+
+    let extension = |ratio: f64| FibonacciLevel {
+        ratio,
+        price: match direction {
+            SwingDirection::Up => swing_start.1 + ratio * height,
+            SwingDirection::Down => swing_start.1 - ratio * height,
+        },
+    };
+
+    let retracements: Vec<FibonacciLevel> =
+        RETRACEMENTS.iter().map(|ratio| level(*ratio)).collect();
+    let extensions: Vec<FibonacciLevel> =
+        EXTENSIONS.iter().map(|ratio| extension(*ratio)).collect();
+
+    let close = bars.close[len - 1];
+    let near = |price: f64, level: &FibonacciLevel| {
+        ((price - level.price) / level.price).abs() <= params.tolerance
+    };
+
+    let at_level = retracements
+        .iter()
+        .filter(|level| near(close, level))
+        .min_by(|a, b| (a.price - close).abs().total_cmp(&(b.price - close).abs()))
+        .map(|level| level.ratio);
+
+    let bounce_from = (swing_end.0 + 1).max(len.saturating_sub(params.bounce_bars + 1));
+    let bounced_from = (bounce_from..len.saturating_sub(1))
+        .rev()
+        .find_map(|index| {
+            let touch = match direction {
+                SwingDirection::Up => bars.low[index],
+                SwingDirection::Down => bars.high[index],
+            };
+            retracements
+                .iter()
+                .find(|level| near(touch, level))
+                .filter(|level| match direction {
+                    SwingDirection::Up => close > level.price * (1. + params.tolerance),
+                    SwingDirection::Down => close < level.price * (1. - params.tolerance),
+                })
+        })
+        .map(|level| level.ratio);
+
+    Some(Fibonacci {
+        direction,
+        swing_start,
+        swing_end,
+        retracements,
+        extensions,
+        at_level,
+        bounced_from,
+        date: bars.dates[swing_end.0],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> FibonacciParams {
+        FibonacciParams {
+            lookback: 100,
+            tolerance: 0.005,
+            bounce_bars: 3,
+        }
+    }
+
+    fn up_swings() -> Swings {
+        Swings {
+            highs: vec![(10, 110.), (40, 200.)],
+            lows: vec![(20, 100.), (50, 150.)],
+        }
+    }
+
+    fn detect_closes(closes: &[f64], swings: &Swings) -> Option<Fibonacci> {
+        detect(&Bars::from_closes(closes, 0.1), swings, &params())
+    }
+
+    fn price(levels: &[FibonacciLevel], ratio: f64) -> f64 {
+        levels
+            .iter()
+            .find(|level| level.ratio == ratio)
+            .unwrap()
+            .price
+    }
+
+    #[test]
+    fn retracements_and_extensions_of_an_up_swing() {
+        let fibonacci = detect_closes(&vec![170.; 60], &up_swings()).unwrap();
+
+        assert_eq!(fibonacci.direction, SwingDirection::Up);
+        assert_eq!(fibonacci.swing_start, (20, 100.));
+        assert_eq!(fibonacci.swing_end, (40, 200.));
+        assert!((price(&fibonacci.retracements, 0.382) - 161.8).abs() < 1e-9);
+        assert!((price(&fibonacci.retracements, 0.618) - 138.2).abs() < 1e-9);
+        assert!((price(&fibonacci.extensions, 1.618) - 261.8).abs() < 1e-9);
+        assert_eq!(fibonacci.at_level, None);
+        assert_eq!(fibonacci.bounced_from, None);
+    }
+
+    #[test]
+    fn down_swing_levels_are_measured_from_the_low() {
+        let swings = Swings {
+            highs: vec![(10, 200.)],
+            lows: vec![(30, 100.)],
+        };
+        let fibonacci = detect_closes(&vec![120.; 40], &swings).unwrap();
+
+        assert_eq!(fibonacci.direction, SwingDirection::Down);
+        assert!((price(&fibonacci.retracements, 0.236) - 123.6).abs() < 1e-9);
+        assert!((price(&fibonacci.extensions, 1.272) - 72.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn records_the_level_the_price_sits_at() {
+        let fibonacci = detect_closes(&vec![150.3; 60], &up_swings()).unwrap();
+        assert_eq!(fibonacci.at_level, Some(0.5));
+    }
+
+    #[test]
+    fn records_recent_bounces() {
+        let mut closes = vec![170.; 60];
+        closes[57] = 161.9;
+        closes[58] = 166.;
+        closes[59] = 168.;
+
+        let fibonacci = detect_closes(&closes, &up_swings()).unwrap();
+        assert_eq!(fibonacci.bounced_from, Some(0.382));
+
+        closes[57] = 170.;
+        closes[54] = 161.9;
+        let fibonacci = detect_closes(&closes, &up_swings()).unwrap();
+        assert_eq!(fibonacci.bounced_from, None);
+    }
+
+    #[test]
+    fn ignores_swings_out_of_the_lookback() {
+        let closes = vec![170.; 200];
+        assert!(detect_closes(&closes, &up_swings()).is_none());
+    }
+}
@@ -0,0 +1,335 @@
+use super::{Bars, Swings};
+use crate::helpers::slope_intercept::slope_intercept;
+use crate::prices::{search_breakout, BreakDirection, BreakoutConfig, PriceBreak};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum HeadAndShouldersType {
+    HeadAndShoulders,
+    InverseHeadAndShoulders,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeadAndShoulders {
+    pub pattern_type: HeadAndShouldersType,
+    pub left_shoulder: (usize, f64),
+    pub head: (usize, f64),
+    pub right_shoulder: (usize, f64),
+    pub neckline: Vec<(usize, f64)>,
+    pub height: f64,
+    pub date: DbDateTime,
+    pub neckline_break: Option<PriceBreak>,
+    /// Measured move from the neckline break.
+    pub target_price: Option<f64>,
+    pub target: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pivot {
+    index: usize,
+    price: f64,
+    is_max: bool,
+}
+
+/// Finds head and shoulders (tops) and inverse head and shoulders (bottoms) in the
+/// alternating sequence of local maxima and minima. `threshold` is the maximum
+/// relative difference between the shoulders and the minimum head prominence over them.
+pub fn detect(
+    bars: &Bars,
+    swings: &Swings,
+    threshold: f64,
+    config: &BreakoutConfig,
+) -> Vec<HeadAndShoulders> {
+    let pivots = alternate_pivots(&swings.highs, &swings.lows);
+    let mut result = vec![];
+
+    for window in pivots.windows(5) {
+        let pattern_type = match window[0].is_max {
+            true => HeadAndShouldersType::HeadAndShoulders,
+            false => HeadAndShouldersType::InverseHeadAndShoulders,
+        };
+
+        if let Some(pattern) = formation(bars, window, pattern_type, threshold, config) {
+            result.push(pattern);
+        }
+    }
+
+    result
+}
+
+fn formation(
+    bars: &Bars,
+    window: &[Pivot],
+    pattern_type: HeadAndShouldersType,
+    threshold: f64,
+    config: &BreakoutConfig,
+) -> Option<HeadAndShoulders> {
+    let (left_shoulder, head, right_shoulder) = (window[0], window[2], window[4]);
+    let neckline = vec![
+        (window[1].index, window[1].price),
+        (window[3].index, window[3].price),
+    ];
+
+    let (slope, y_intercept) = slope_intercept(
+        neckline[0].0 as f64,
+        neckline[0].1,
+        neckline[1].0 as f64,
+        neckline[1].1,
+    );
+    let neckline_at = |index: usize| slope * index as f64 + y_intercept;
+
+    let shoulders_avg = (left_shoulder.price + right_shoulder.price) / 2.;
+    let symmetric = (left_shoulder.price - right_shoulder.price).abs() / shoulders_avg <= threshold;
+
+    let is_formation = match pattern_type {
+        HeadAndShouldersType::HeadAndShoulders => {
+            head.price > left_shoulder.price.max(right_shoulder.price) * (1. + threshold)
+                && left_shoulder.price > neckline_at(left_shoulder.index)
+                && right_shoulder.price > neckline_at(right_shoulder.index)
+        }
+        HeadAndShouldersType::InverseHeadAndShoulders => {
+            head.price < left_shoulder.price.min(right_shoulder.price) * (1. - threshold)
+                && left_shoulder.price < neckline_at(left_shoulder.index)
+                && right_shoulder.price < neckline_at(right_shoulder.index)
+        }
+    };
+
+    if !symmetric || !is_formation {
+        return None;
+    }
+
+    let height = (head.price - neckline_at(head.index)).abs();
+
+    let direction = match pattern_type {
+        HeadAndShouldersType::HeadAndShoulders => BreakDirection::Down,
+        HeadAndShouldersType::InverseHeadAndShoulders => BreakDirection::Up,
+    };
+
+    let neckline_break =
+        search_breakout(&neckline, right_shoulder.index + 1, bars, direction, config);
+
+    let target_price = neckline_break
+        .as_ref()
+        .map(|neckline_break| match direction {
+            BreakDirection::Down => neckline_break.price - height,
+            BreakDirection::Up => neckline_break.price + height,
+        });
+
+    let target = neckline_break
+        .as_ref()
+        .zip(target_price)
+        .map(|(neckline_break, target_price)| {
+            ((target_price - neckline_break.price) / neckline_break.price * 100.).abs()
+        });
+
+    Some(HeadAndShoulders {
+        pattern_type,
+        left_shoulder: (left_shoulder.index, left_shoulder.price),
+        head: (head.index, head.price),
+        right_shoulder: (right_shoulder.index, right_shoulder.price),
+        neckline,
+        height,
+        date: bars.dates[right_shoulder.index],
+        neckline_break,
+        target_price,
+        target,
+    })
+}
+
+/// Merges maxima and minima by index, keeping the most extreme pivot of every run
+/// of consecutive pivots of the same kind.
+fn alternate_pivots(maxima: &[(usize, f64)], minima: &[(usize, f64)]) -> Vec<Pivot> {
+    let mut pivots: Vec<Pivot> = maxima
+        .iter()
+        .map(|(index, price)| Pivot {
+            index: *index,
+            price: *price,
+            is_max: true,
+        })
+        .chain(minima.iter().map(|(index, price)| Pivot {
+            index: *index,
+            price: *price,
+            is_max: false,
+        }))
+        .collect();
+
+    pivots.sort_by_key(|pivot| pivot.index);
+
+    let mut result: Vec<Pivot> = vec![];
+    for pivot in pivots {
+        match result.last_mut() {
+            Some(last) if last.is_max == pivot.is_max => {
+                let more_extreme = match pivot.is_max {
+                    true => pivot.price > last.price,
+                    false => pivot.price < last.price,
+                };
+                if more_extreme {
+                    *last = pivot;
+                }
+            }
+            _ => result.push(pivot),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prices::{BreakConfirmation, BreakRule};
+    use rs_algo_synthetic::{Generator, Shape, ShapeType};
+
+    fn zigzag(anchors: &[(usize, f64)]) -> Vec<f64> {
+        let mut closes = vec![anchors[0].1];
+        for pair in anchors.windows(2) {
+            let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+            for x in x1 + 1..=x2 {
+                closes.push(y1 + (y2 - y1) * (x - x1) as f64 / (x2 - x1) as f64);
+            }
+        }
+        closes
+    }
+
+    fn config() -> BreakoutConfig {
+        BreakoutConfig {
+            rule: BreakRule::Close,
+            confirmation: BreakConfirmation::Bars(2),
+            retest_tolerance: 0.005,
+        }
+    }
+
+    fn detect_closes(closes: &[f64]) -> Vec<HeadAndShoulders> {
+        let bars = Bars::from_closes(closes, 0.5);
+        detect(&bars, &Swings::new(&bars, 5., 5, false), 0.03, &config())
+    }
+
+    #[test]
+    fn detects_head_and_shoulders_top_and_neckline_break() {
+        let closes = zigzag(&[
+            (0, 100.),
+            (10, 110.),
+            (20, 100.),
+            (30, 120.),
+            (40, 100.),
+            (50, 110.),
+            (60, 90.),
+        ]);
+
+        let patterns = detect_closes(&closes);
+        assert_eq!(patterns.len(), 1);
+
+        let pattern = &patterns[0];
+        assert_eq!(pattern.pattern_type, HeadAndShouldersType::HeadAndShoulders);
+        assert_eq!(pattern.left_shoulder.0, 10);
+        assert_eq!(pattern.head, (30, 120.5));
+        assert_eq!(pattern.right_shoulder.0, 50);
+        assert_eq!(pattern.neckline, vec![(20, 99.5), (40, 99.5)]);
+        assert!((pattern.height - 21.).abs() < 1e-9);
+
+        let neckline_break = pattern.neckline_break.as_ref().unwrap();
+        assert_eq!(neckline_break.index, 56);
+        assert_eq!(neckline_break.confirmed_index, Some(57));
+        assert!((neckline_break.price - 99.5).abs() < 1e-9);
+        assert!((pattern.target_price.unwrap() - 78.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn detects_inverse_head_and_shoulders() {
+        let closes: Vec<f64> = zigzag(&[
+            (0, 100.),
+            (10, 110.),
+            (20, 100.),
+            (30, 120.),
+            (40, 100.),
+            (50, 110.),
+            (60, 90.),
+        ])
+        .iter()
+        .map(|close| 220. - close)
+        .collect();
+
+        let patterns = detect_closes(&closes);
+        assert_eq!(patterns.len(), 1);
+
+        let pattern = &patterns[0];
+        assert_eq!(
+            pattern.pattern_type,
+            HeadAndShouldersType::InverseHeadAndShoulders
+        );
+        assert_eq!(pattern.head, (30, 99.5));
+        assert_eq!(pattern.neckline, vec![(20, 120.5), (40, 120.5)]);
+
+        let neckline_break = pattern.neckline_break.as_ref().unwrap();
+        assert_eq!(neckline_break.index, 56);
+        assert!((pattern.target_price.unwrap() - 141.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pending_formation_has_no_neckline_break() {
+        let closes = zigzag(&[
+            (0, 100.),
+            (10, 110.),
+            (20, 100.),
+            (30, 120.),
+            (40, 100.),
+            (50, 110.),
+            (60, 104.),
+        ]);
+
+        let patterns = detect_closes(&closes);
+        assert_eq!(patterns.len(), 1);
+        assert!(patterns[0].neckline_break.is_none());
+        assert!(patterns[0].target_price.is_none());
+    }
+
+    #[test]
+    fn ignores_asymmetric_shoulders() {
+        let closes = zigzag(&[
+            (0, 100.),
+            (10, 110.),
+            (20, 100.),
+            (30, 125.),
+            (40, 100.),
+            (50, 117.),
+            (60, 90.),
+        ]);
+
+        assert!(detect_closes(&closes).is_empty());
+    }
+
+    #[test]
+    fn injected_head_and_shoulders_is_found_and_broken() {
+        for (shape_type, pattern_type, seed) in [
+            (
+                ShapeType::HeadAndShoulders,
+                HeadAndShouldersType::HeadAndShoulders,
+                1,
+            ),
+            (
+                ShapeType::InverseHeadAndShoulders,
+                HeadAndShouldersType::InverseHeadAndShoulders,
+                2,
+            ),
+        ] {
+            let generator = Generator::new(seed, "mean_reverting".parse().unwrap())
+                .shape(Shape::new(shape_type, 200));
+            let bars = Bars::from_generator(&generator, 262);
+            let swings = Swings::new(&bars, 2., 5, false);
+
+            let patterns = detect(&bars, &swings, 0.03, &config());
+            let pattern = patterns
+                .iter()
+                .find(|pattern| pattern.pattern_type == pattern_type && pattern.head.0 > 200)
+                .unwrap_or_else(|| panic!("{:?} not found: {:?}", shape_type, patterns));
+
+            assert!((pattern.head.0 as i64 - 230).abs() <= 2);
+            let neckline_break = pattern.neckline_break.as_ref().unwrap();
+            assert!(neckline_break.index > pattern.right_shoulder.0 && neckline_break.index < 260);
+            assert!(neckline_break.confirmed_index.is_some());
+        }
+    }
+}
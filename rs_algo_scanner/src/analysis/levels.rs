@@ -0,0 +1,241 @@
+use super::{Bars, Swings};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum LevelType {
+    Support,
+    Resistance,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Level {
+    pub level_type: LevelType,
+    pub price: f64,
+    pub touches: usize,
+    /// Relative to the strongest level of the instrument, from 0 to 1.
+    pub strength: f64,
+    /// Distance from the last close in percent.
+    pub distance: f64,
+    pub points: Vec<(usize, f64)>,
+    pub last_touch: DbDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelParams {
+    /// Maximum relative distance between neighbour swing prices.
+    pub epsilon: f64,
+    pub min_touches: usize,
+    /// Bars after which a touch counts half.
+    pub half_life: f64,
+}
+
+/// Clusters the swing highs and lows by price (DBSCAN with a relative `epsilon`).
+/// Every cluster becomes a level scored by its touches, weighted by how recent
+/// they are and by their volume compared with the average volume.
+pub fn detect(bars: &Bars, swings: &Swings, params: &LevelParams) -> Vec<Level> {
+    let last_close = match bars.close.last() {
+        Some(close) => *close,
+        None => return vec![],
+    };
+
+    let mut points: Vec<(usize, f64)> = swings
+        .highs
+        .iter()
+        .chain(swings.lows.iter())
+        .copied()
+        .collect();
+    points.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let len = bars.close.len();
+    let avg_volume = bars.volume.iter().sum::<f64>() / len as f64;
+
+    let score = |index: usize| {
+        let recency = 0.5_f64.powf((len - 1 - index) as f64 / params.half_life.max(1.));
+        let volume = match avg_volume > 0. {
+            true => bars.volume[index] / avg_volume,
+            false => 1.,
+        };
+        recency * volume
+    };
+
+    let mut levels: Vec<(Level, f64)> = clusters(&points, params.epsilon, params.min_touches)
+        .into_iter()
+        .map(|cluster| {
+            let mut cluster: Vec<(usize, f64)> =
+                cluster.into_iter().map(|point| points[point]).collect();
+            cluster.sort_by_key(|point| point.0);
+
+            let price = cluster.iter().map(|point| point.1).sum::<f64>() / cluster.len() as f64;
+            let raw_strength: f64 = cluster.iter().map(|point| score(point.0)).sum();
+            let last_touch = bars.dates[cluster[cluster.len() - 1].0];
+
+            let level = Level {
+                level_type: match price <= last_close {
+                    true => LevelType::Support,
+                    false => LevelType::Resistance,
+                },
+                price,
+                touches: cluster.len(),
+                strength: 0.,
+                distance: ((last_close - price) / last_close * 100.).abs(),
+                points: cluster,
+                last_touch,
+            };
+            (level, raw_strength)
+        })
+        .collect();
+
+    let max_strength = levels
+        .iter()
+        .map(|(_, raw_strength)| *raw_strength)
+        .fold(0., f64::max);
+
+    for (level, raw_strength) in levels.iter_mut() {
+        level.strength = match max_strength > 0. {
+            true => *raw_strength / max_strength,
+            false => 0.,
+        };
+    }
+
+    let mut levels: Vec<Level> = levels.into_iter().map(|(level, _)| level).collect();
+    levels.sort_by(|a, b| b.strength.total_cmp(&a.strength));
+    levels
+}
+
+/// DBSCAN over prices sorted in ascending order. Returns the indexes of every cluster.
+fn clusters(prices: &[(usize, f64)], epsilon: f64, min_points: usize) -> Vec<Vec<usize>> {
+    let neighbours: Vec<Vec<usize>> = (0..prices.len())
+        .map(|point| {
+            let price = prices[point].1;
+            (0..prices.len())
+                .filter(|other| ((prices[*other].1 - price) / price).abs() <= epsilon)
+                .collect()
+        })
+        .collect();
+
+    let mut cluster_of: Vec<Option<usize>> = vec![None; prices.len()];
+    let mut result: Vec<Vec<usize>> = vec![];
+
+    for point in 0..prices.len() {
+        if cluster_of[point].is_some() || neighbours[point].len() < min_points.max(1) {
+            continue;
+        }
+
+        let cluster = result.len();
+        let mut members = vec![];
+        let mut queue = vec![point];
+        cluster_of[point] = Some(cluster);
+
+        while let Some(current) = queue.pop() {
+            members.push(current);
+            if neighbours[current].len() < min_points.max(1) {
+                continue;
+            }
+            for neighbour in &neighbours[current] {
+                if cluster_of[*neighbour].is_none() {
+                    cluster_of[*neighbour] = Some(cluster);
+                    queue.push(*neighbour);
+                }
+            }
+        }
+
+        members.sort_unstable();
+        result.push(members);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> LevelParams {
+        LevelParams {
+            epsilon: 0.01,
+            min_touches: 3,
+            half_life: 20.,
+        }
+    }
+
+    fn detect_swings(highs: Vec<(usize, f64)>, lows: Vec<(usize, f64)>) -> (Bars, Vec<Level>) {
+        let bars = Bars::from_closes(&vec![105.; 100], 0.);
+        let levels = detect(&bars, &Swings { highs, lows }, &params());
+        (bars, levels)
+    }
+
+    #[test]
+    fn clusters_nearby_swing_prices() {
+        let (_, levels) = detect_swings(
+            vec![(10, 110.), (30, 110.5), (50, 109.8), (70, 130.)],
+            vec![(20, 100.), (40, 100.4), (60, 99.7), (80, 90.)],
+        );
+
+        assert_eq!(levels.len(), 2);
+        let support = levels
+            .iter()
+            .find(|level| level.level_type == LevelType::Support)
+            .unwrap();
+        assert_eq!(support.touches, 3);
+        assert!((support.price - 100.033).abs() < 1e-3);
+        assert!((support.distance - 4.73).abs() < 1e-2);
+        assert_eq!(support.points[0], (20, 100.));
+
+        let resistance = levels
+            .iter()
+            .find(|level| level.level_type == LevelType::Resistance)
+            .unwrap();
+        assert_eq!(resistance.touches, 3);
+    }
+
+    #[test]
+    fn recent_touches_are_stronger() {
+        let (bars, levels) = detect_swings(
+            vec![(10, 110.), (12, 110.5), (14, 109.8)],
+            vec![(90, 100.), (92, 100.4), (94, 99.7)],
+        );
+
+        assert_eq!(levels[0].level_type, LevelType::Support);
+        assert_eq!(levels[0].strength, 1.);
+        assert!(levels[1].strength < 0.1);
+        assert_eq!(levels[0].last_touch, bars.dates[94]);
+    }
+
+    #[test]
+    fn volume_adds_strength() {
+        let mut bars = Bars::from_closes(&vec![105.; 100], 0.);
+        bars.volume[10] = 5000.;
+        let swings = Swings {
+            highs: vec![(10, 110.), (30, 110.5), (50, 109.8)],
+            lows: vec![(10, 100.), (30, 100.4), (50, 99.7)],
+        };
+
+        let levels = detect(&bars, &swings, &params());
+        assert_eq!(levels[0].level_type, LevelType::Resistance);
+        assert!(levels[1].strength < 1.);
+    }
+
+    #[test]
+    fn sparse_swings_are_noise() {
+        let (_, levels) = detect_swings(
+            vec![(10, 110.), (30, 115.), (50, 120.)],
+            vec![(20, 100.), (40, 100.4)],
+        );
+        assert!(levels.is_empty());
+    }
+
+    #[test]
+    fn dbscan_chains_core_points() {
+        let prices: Vec<(usize, f64)> = [100., 100.8, 101.6, 102.4, 110.]
+            .iter()
+            .enumerate()
+            .map(|(index, price)| (index, *price))
+            .collect();
+
+        assert_eq!(clusters(&prices, 0.01, 2), vec![vec![0, 1, 2, 3]]);
+        assert!(clusters(&prices, 0.01, 4).is_empty());
+    }
+}
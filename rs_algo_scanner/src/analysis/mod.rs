@@ -0,0 +1,616 @@
+use crate::helpers::maxima_minima::maxima_minima;
+use crate::helpers::regression::Smoother;
+use crate::prices::BreakoutConfig;
+
+use rs_algo_shared::helpers::date::*;
+use rs_algo_shared::indicators::Indicator;
+use rs_algo_shared::scanner::candle::Candle;
+use rs_algo_shared::scanner::instrument::Instrument;
+
+use chrono::Datelike;
+use serde::Serialize;
+use std::env;
+
+pub mod divergence;
+pub mod fibonacci;
+pub mod head_and_shoulders;
+pub mod levels;
+pub mod pivots;
+pub mod regime;
+pub mod trendline;
+pub mod volume;
+
+use divergence::{Divergence, DivergenceIndicator};
+use fibonacci::{Fibonacci, FibonacciParams};
+use head_and_shoulders::HeadAndShoulders;
+use levels::{Level, LevelParams};
+use pivots::Pivots;
+use regime::{Regime, RegimeParams};
+use trendline::{TrendlineParams, Trendlines};
+use volume::{VolumeParams, VolumeProfile, Vwap};
+
+/// Scanner side analysis sent to the sinks along with the instrument.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Analysis {
+    pub head_and_shoulders: Vec<HeadAndShoulders>,
+    pub divergences: Vec<Divergence>,
+    pub regime: Regime,
+    pub trendlines: Trendlines,
+    pub levels: Vec<Level>,
+    pub fibonacci: Option<Fibonacci>,
+    pub pivots: Option<Pivots>,
+    pub vwap: Option<Vwap>,
+    pub volume_profile: Option<VolumeProfile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScannedInstrument {
+    #[serde(flatten)]
+    pub instrument: Instrument,
+    pub analysis: Analysis,
+}
+
+/// Column oriented copy of the instrument candles.
+#[derive(Debug, Clone, Default)]
+pub struct Bars {
+    pub dates: Vec<DbDateTime>,
+    pub high: Vec<f64>,
+    pub low: Vec<f64>,
+    pub close: Vec<f64>,
+    pub volume: Vec<f64>,
+    /// Calendar day of every bar, used to resample intraday bars into sessions.
+    pub days: Vec<i32>,
+}
+
+impl Bars {
+    pub fn from_candles(candles: &[Candle]) -> Self {
+        let mut bars = Self::default();
+        for candle in candles {
+            bars.dates.push(to_dbtime(candle.date()));
+            bars.high.push(candle.high());
+            bars.low.push(candle.low());
+            bars.close.push(candle.close());
+            bars.volume.push(candle.volume());
+            bars.days
+                .push(candle.date().date_naive().num_days_from_ce());
+        }
+        bars
+    }
+
+    #[cfg(test)]
+    pub fn from_data(data: &[(chrono::DateTime<Local>, f64, f64, f64, f64, f64)]) -> Self {
+        let mut bars = Self::default();
+        for (date, _open, high, low, close, volume) in data {
+            bars.dates.push(to_dbtime(*date));
+            bars.days.push(date.date_naive().num_days_from_ce());
+            bars.high.push(*high);
+            bars.low.push(*low);
+            bars.close.push(*close);
+            bars.volume.push(*volume);
+        }
+        bars
+    }
+
+    /// Daily bars of a synthetic series.
+    #[cfg(test)]
+    pub fn from_generator(generator: &rs_algo_synthetic::Generator, len: usize) -> Self {
+        use chrono::{TimeZone, Utc};
+
+        let from = Utc.with_ymd_and_hms(2023, 1, 2, 0, 0, 0).unwrap();
+        Self::from_data(&generator.generate(&rs_algo_synthetic::dates(len, 1440, from)))
+    }
+
+    /// Daily bars built from closes, with highs and lows `spread` away from the close.
+    #[cfg(test)]
+    pub fn from_closes(closes: &[f64], spread: f64) -> Self {
+        let start = Local::now() - Duration::days(closes.len() as i64);
+        let mut bars = Self::default();
+        for (index, close) in closes.iter().enumerate() {
+            let date = start + Duration::days(index as i64);
+            bars.dates.push(to_dbtime(date));
+            bars.days.push(date.date_naive().num_days_from_ce());
+            bars.high.push(close + spread);
+            bars.low.push(close - spread);
+            bars.close.push(*close);
+            bars.volume.push(1000.);
+        }
+        bars
+    }
+}
+
+/// Local maxima of the highs and local minima of the lows, sorted by index.
+#[derive(Debug, Clone, Default)]
+pub struct Swings {
+    pub highs: Vec<(usize, f64)>,
+    pub lows: Vec<(usize, f64)>,
+}
+
+impl Swings {
+    pub fn new(bars: &Bars, min_prominence: f64, min_distance: usize, logarithmic: bool) -> Self {
+        let highs = price_source(&bars.high, logarithmic);
+        let lows = price_source(&bars.low, logarithmic);
+        let inverted_lows: Vec<f64> = lows.iter().map(|low| -low).collect();
+
+        let mut highs = maxima_minima(&highs, &highs, min_prominence, min_distance, logarithmic)
+            .unwrap_or_default();
+        let mut lows = maxima_minima(
+            &inverted_lows,
+            &lows,
+            min_prominence,
+            min_distance,
+            logarithmic,
+        )
+        .unwrap_or_default();
+
+        highs.sort_by_key(|peak| peak.0);
+        lows.sort_by_key(|peak| peak.0);
+
+        Self { highs, lows }
+    }
+}
+
+/// Prices in the scale expected by the peak and regression helpers, which work
+/// with log prices in logarithmic mode.
+pub fn price_source(values: &[f64], logarithmic: bool) -> Vec<f64> {
+    match logarithmic {
+        true => values.iter().map(|value| value.ln()).collect(),
+        false => values.to_vec(),
+    }
+}
+
+/// Analysis settings. Built once per run and shared by every worker.
+#[derive(Debug, Clone)]
+pub struct AnalysisContext {
+    pub logarithmic: bool,
+    pub min_prominence: f64,
+    pub min_distance: usize,
+    pub head_and_shoulders_threshold: f64,
+    pub divergences: bool,
+    pub divergence_min_prominence: f64,
+    pub divergence_min_distance: usize,
+    pub divergence_window_size: usize,
+    pub regime: RegimeParams,
+    pub trendline: TrendlineParams,
+    pub levels: LevelParams,
+    pub fibonacci: FibonacciParams,
+    pub volume: VolumeParams,
+    pub breakout: BreakoutConfig,
+}
+
+impl AnalysisContext {
+    pub fn from_env() -> Self {
+        let logarithmic = env::var("LOGARITHMIC_SCANNER")
+            .unwrap()
+            .parse::<bool>()
+            .unwrap();
+
+        let min_prominence = env::var("LOCAL_MIN_PROMINENCE")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let min_distance = env::var("LOCAL_PROMINENCE_MIN_DISTANCE")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        let head_and_shoulders_threshold = env::var("HEAD_AND_SHOULDERS_THRESHOLD")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let divergences = env::var("DIVERGENCES").unwrap().parse::<bool>().unwrap();
+
+        let divergence_min_prominence = env::var("DIVERGENCE_MIN_PROMINENCE")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let divergence_min_distance = env::var("DIVERGENCE_PROMINENCE_MIN_DISTANCE")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        let divergence_window_size = env::var("DIVERGENCES_WINDOW_SIZE")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        let smoother = match env::var("REGIME_SMOOTHER").unwrap().as_ref() {
+            "loess" => Smoother::Loess,
+            "kalman" => Smoother::Kalman {
+                process_noise: env::var("KALMAN_PROCESS_NOISE")
+                    .unwrap()
+                    .parse::<f64>()
+                    .unwrap(),
+                measurement_noise: env::var("KALMAN_MEASUREMENT_NOISE")
+                    .unwrap()
+                    .parse::<f64>()
+                    .unwrap(),
+            },
+            _ => Smoother::Kernel {
+                bandwidth: env::var("KERNEL_REGRESSION_BANDWIDTH")
+                    .unwrap()
+                    .parse::<f64>()
+                    .unwrap(),
+            },
+        };
+
+        let regime = RegimeParams {
+            smoother,
+            smoothing_window: env::var("REGIME_SMOOTHING_WINDOW")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            slope_period: env::var("REGIME_SLOPE_PERIOD")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            slope_threshold: env::var("REGIME_SLOPE_THRESHOLD")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+            volatility_period: env::var("REGIME_VOLATILITY_PERIOD")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            volatility_factor: env::var("REGIME_VOLATILITY_FACTOR")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+        };
+
+        let trendline = TrendlineParams {
+            points: env::var("TRENDLINE_POINTS")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            degree: env::var("TRENDLINE_DEGREE")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            min_r_squared: env::var("TRENDLINE_MIN_R_SQUARED")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+            touch_tolerance: env::var("TRENDLINE_TOUCH_TOLERANCE")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+            parallel_threshold: env::var("CHANNEL_PARALLEL_THRESHOLD")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+        };
+
+        let levels = LevelParams {
+            epsilon: env::var("SR_LEVELS_EPSILON")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+            min_touches: env::var("SR_LEVELS_MIN_TOUCHES")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            half_life: env::var("SR_LEVELS_HALF_LIFE")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+        };
+
+        let fibonacci = FibonacciParams {
+            lookback: env::var("FIBONACCI_LOOKBACK")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            tolerance: env::var("FIBONACCI_TOLERANCE")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+            bounce_bars: env::var("FIBONACCI_BOUNCE_BARS")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+        };
+
+        let volume = VolumeParams {
+            bands: env::var("VWAP_BANDS")
+                .unwrap()
+                .split(',')
+                .map(|band| band.trim().parse::<f64>().unwrap())
+                .collect(),
+            profile_bins: env::var("VOLUME_PROFILE_BINS")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            profile_lookback: env::var("VOLUME_PROFILE_LOOKBACK")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(),
+            value_area: env::var("VOLUME_VALUE_AREA")
+                .unwrap()
+                .parse::<f64>()
+                .unwrap(),
+        };
+
+        Self {
+            logarithmic,
+            min_prominence,
+            min_distance,
+            head_and_shoulders_threshold,
+            divergences,
+            divergence_min_prominence,
+            divergence_min_distance,
+            divergence_window_size,
+            regime,
+            trendline,
+            levels,
+            fibonacci,
+            volume,
+            breakout: BreakoutConfig::from_env(),
+        }
+    }
+}
+
+pub fn analyze(instrument: &Instrument, context: &AnalysisContext) -> Analysis {
+    let bars = Bars::from_candles(instrument.data());
+    let indicators = instrument.indicators();
+
+    let oscillators = [
+        (DivergenceIndicator::Rsi, indicators.rsi.get_data_a()),
+        (DivergenceIndicator::Macd, indicators.macd.get_data_a()),
+        (DivergenceIndicator::Stoch, indicators.stoch.get_data_a()),
+    ];
+
+    // Time frames are expressed in minutes
+    let intraday = instrument.time_frame().to_number() < 1440;
+
+    analyze_bars(&bars, &oscillators, intraday, context)
+}
+
+fn analyze_bars(
+    bars: &Bars,
+    oscillators: &[(DivergenceIndicator, &Vec<f64>)],
+    intraday: bool,
+    context: &AnalysisContext,
+) -> Analysis {
+    let swings = Swings::new(
+        bars,
+        context.min_prominence,
+        context.min_distance,
+        context.logarithmic,
+    );
+
+    let divergences = match context.divergences {
+        true => {
+            let divergence_swings = Swings::new(
+                bars,
+                context.divergence_min_prominence,
+                context.divergence_min_distance,
+                context.logarithmic,
+            );
+
+            oscillators
+                .iter()
+                .flat_map(|(indicator, values)| {
+                    divergence::detect(
+                        bars,
+                        &divergence_swings,
+                        *indicator,
+                        values,
+                        context.divergence_window_size,
+                    )
+                })
+                .collect()
+        }
+        false => vec![],
+    };
+
+    Analysis {
+        head_and_shoulders: head_and_shoulders::detect(
+            bars,
+            &swings,
+            context.head_and_shoulders_threshold,
+            &context.breakout,
+        ),
+        divergences,
+        regime: regime::classify(bars, &context.regime, context.logarithmic),
+        trendlines: trendline::detect(bars, &swings, &context.trendline),
+        levels: levels::detect(bars, &swings, &context.levels),
+        fibonacci: fibonacci::detect(bars, &swings, &context.fibonacci),
+        pivots: pivots::detect(bars, intraday),
+        vwap: volume::vwap(bars, &swings, intraday, &context.volume),
+        volume_profile: volume::volume_profile(bars, &context.volume),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prices::{BreakConfirmation, BreakRule};
+    use chrono::{TimeZone, Utc};
+    use head_and_shoulders::HeadAndShouldersType;
+    use rs_algo_shared::models::market::Market;
+    use rs_algo_shared::models::time_frame::TimeFrame;
+    use rs_algo_synthetic::{dates, Generator, Shape, ShapeType};
+    use std::process::Command;
+
+    fn context() -> AnalysisContext {
+        AnalysisContext {
+            logarithmic: true,
+            min_prominence: 0.02,
+            min_distance: 5,
+            head_and_shoulders_threshold: 0.03,
+            divergences: true,
+            divergence_min_prominence: 0.01,
+            divergence_min_distance: 3,
+            divergence_window_size: 3,
+            regime: RegimeParams {
+                smoother: Smoother::Kernel { bandwidth: 0.05 },
+                smoothing_window: 50,
+                slope_period: 10,
+                slope_threshold: 0.001,
+                volatility_period: 20,
+                volatility_factor: 2.,
+            },
+            trendline: TrendlineParams {
+                points: 4,
+                degree: 1,
+                min_r_squared: 0.8,
+                touch_tolerance: 0.01,
+                parallel_threshold: 0.05,
+            },
+            levels: LevelParams {
+                epsilon: 0.01,
+                min_touches: 3,
+                half_life: 50.,
+            },
+            fibonacci: FibonacciParams {
+                lookback: 120,
+                tolerance: 0.005,
+                bounce_bars: 3,
+            },
+            volume: VolumeParams {
+                bands: vec![1., 2.],
+                profile_bins: 24,
+                profile_lookback: 120,
+                value_area: 0.7,
+            },
+            breakout: BreakoutConfig {
+                rule: BreakRule::Close,
+                confirmation: BreakConfirmation::Bars(2),
+                retest_tolerance: 0.005,
+            },
+        }
+    }
+
+    fn closes(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|index| {
+                let x = index as f64;
+                100. + x * 0.01 + (x / 15.).sin() * 8. + (x / 4.).cos() * 2.
+            })
+            .collect()
+    }
+
+    #[test]
+    fn price_source_uses_log_prices_in_logarithmic_mode() {
+        assert_eq!(price_source(&[1., 2.], false), vec![1., 2.]);
+        assert_eq!(price_source(&[1.], true), vec![0.]);
+    }
+
+    /// Environment matching `context()`.
+    const ENV: [(&str, &str); 34] = [
+        ("LOGARITHMIC_SCANNER", "true"),
+        ("LOCAL_MIN_PROMINENCE", "0.02"),
+        ("LOCAL_PROMINENCE_MIN_DISTANCE", "5"),
+        ("HEAD_AND_SHOULDERS_THRESHOLD", "0.03"),
+        ("DIVERGENCES", "true"),
+        ("DIVERGENCE_MIN_PROMINENCE", "0.01"),
+        ("DIVERGENCE_PROMINENCE_MIN_DISTANCE", "3"),
+        ("DIVERGENCES_WINDOW_SIZE", "3"),
+        ("REGIME_SMOOTHER", "kernel"),
+        ("KERNEL_REGRESSION_BANDWIDTH", "0.05"),
+        ("REGIME_SMOOTHING_WINDOW", "50"),
+        ("REGIME_SLOPE_PERIOD", "10"),
+        ("REGIME_SLOPE_THRESHOLD", "0.001"),
+        ("REGIME_VOLATILITY_PERIOD", "20"),
+        ("REGIME_VOLATILITY_FACTOR", "2"),
+        ("TRENDLINE_POINTS", "4"),
+        ("TRENDLINE_DEGREE", "1"),
+        ("TRENDLINE_MIN_R_SQUARED", "0.8"),
+        ("TRENDLINE_TOUCH_TOLERANCE", "0.01"),
+        ("CHANNEL_PARALLEL_THRESHOLD", "0.05"),
+        ("SR_LEVELS_EPSILON", "0.01"),
+        ("SR_LEVELS_MIN_TOUCHES", "3"),
+        ("SR_LEVELS_HALF_LIFE", "50"),
+        ("FIBONACCI_LOOKBACK", "120"),
+        ("FIBONACCI_TOLERANCE", "0.005"),
+        ("FIBONACCI_BOUNCE_BARS", "3"),
+        ("VWAP_BANDS", "1,2"),
+        ("VOLUME_PROFILE_BINS", "24"),
+        ("VOLUME_PROFILE_LOOKBACK", "120"),
+        ("VOLUME_VALUE_AREA", "0.7"),
+        ("PRICE_BREAK_RULE", "close"),
+        ("PRICE_BREAK_CONFIRMATION", "bars"),
+        ("PRICE_BREAK_CONFIRMATION_BARS", "2"),
+        ("PRICE_BREAK_RETEST_TOLERANCE", "0.005"),
+    ];
+
+    /// Runs in a child process with `ENV`, so no other test sees the variables it sets.
+    #[test]
+    fn context_is_read_once_from_env() {
+        if env::var("ANALYSIS_CONTEXT_FROM_ENV").is_err() {
+            let output = Command::new(env::current_exe().unwrap())
+                .args(["analysis::tests::context_is_read_once_from_env", "--exact"])
+                .env("ANALYSIS_CONTEXT_FROM_ENV", "true")
+                .envs(ENV)
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(stdout.contains("1 passed"), "{}", stdout);
+            return;
+        }
+
+        let bars = Bars::from_closes(&closes(500), 0.5);
+        let rsi: Vec<f64> = closes(500).iter().map(|close| close - 50.).collect();
+        let oscillators = [(DivergenceIndicator::Rsi, &rsi)];
+        let analysis = |context: &AnalysisContext| {
+            serde_json::to_value(analyze_bars(&bars, &oscillators, false, context)).unwrap()
+        };
+
+        let env_context = AnalysisContext::from_env();
+        assert_eq!(format!("{:?}", env_context), format!("{:?}", context()));
+        let first = analysis(&env_context);
+        assert_eq!(first["regime"]["series"].as_array().unwrap().len(), 500);
+
+        env::set_var("LOGARITHMIC_SCANNER", "false");
+        env::set_var("KERNEL_REGRESSION_BANDWIDTH", "1");
+        env::set_var("REGIME_SLOPE_PERIOD", "2");
+        env::set_var("LOCAL_MIN_PROMINENCE", "0.2");
+        env::set_var("VWAP_BANDS", "3");
+        assert_eq!(analysis(&env_context), first);
+        assert_ne!(analysis(&AnalysisContext::from_env()), first);
+    }
+
+    #[test]
+    fn analyzes_a_shape_injected_through_the_instrument() {
+        let generator = Generator::new(2, "mean_reverting".parse().unwrap())
+            .shape(Shape::new(ShapeType::InverseHeadAndShoulders, 150));
+        let from = Utc.with_ymd_and_hms(2023, 1, 2, 0, 0, 0).unwrap();
+        let mut instrument = Instrument::new()
+            .symbol("SYNTH_OU")
+            .market(Market::Crypto)
+            .time_frame(TimeFrame::new("D"))
+            .build()
+            .unwrap();
+        instrument
+            .set_data(generator.generate(&dates(212, 1440, from)))
+            .unwrap();
+
+        let context = AnalysisContext {
+            logarithmic: false,
+            min_prominence: 2.,
+            breakout: BreakoutConfig {
+                rule: BreakRule::Close,
+                confirmation: BreakConfirmation::Bars(2),
+                retest_tolerance: 0.005,
+            },
+            ..context()
+        };
+        let analysis = analyze(&instrument, &context);
+
+        let pattern = analysis
+            .head_and_shoulders
+            .iter()
+            .find(|pattern| {
+                pattern.pattern_type == HeadAndShouldersType::InverseHeadAndShoulders
+                    && pattern.head.0 > 150
+            })
+            .unwrap_or_else(|| panic!("not found: {:?}", analysis.head_and_shoulders));
+
+        assert!((pattern.head.0 as i64 - 180).abs() <= 2);
+        let neckline_break = pattern.neckline_break.as_ref().unwrap();
+        assert!(neckline_break.confirmed_index.is_some());
+    }
+}
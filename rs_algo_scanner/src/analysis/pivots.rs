@@ -0,0 +1,215 @@
+use super::Bars;
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum PivotType {
+    Classic,
+    Fibonacci,
+    Camarilla,
+    Woodie,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PivotLevels {
+    pub pivot_type: PivotType,
+    pub pivot: f64,
+    /// R1, R2... from the nearest to the farthest.
+    pub resistances: Vec<f64>,
+    /// S1, S2... from the nearest to the farthest.
+    pub supports: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pivots {
+    pub session_date: DbDateTime,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub levels: Vec<PivotLevels>,
+}
+
+/// Pivot levels of the previous session. Intraday bars are resampled into daily
+/// sessions; otherwise the previous bar is the session.
+pub fn detect(bars: &Bars, intraday: bool) -> Option<Pivots> {
+    let len = bars.close.len();
+    if len < 2 {
+        return None;
+    }
+
+    let (from, to) = match intraday {
+        true => {
+            let current_day = bars.days[len - 1];
+            let to = bars.days.iter().rposition(|day| *day < current_day)?;
+            let session_day = bars.days[to];
+            let from = bars.days[..to]
+                .iter()
+                .rposition(|day| *day < session_day)
+                .map_or(0, |index| index + 1);
+            (from, to)
+        }
+        false => (len - 2, len - 2),
+    };
+
+    let high = bars.high[from..=to]
+        .iter()
+        .copied()
+        .fold(f64::MIN, f64::max);
+    let low = bars.low[from..=to].iter().copied().fold(f64::MAX, f64::min);
+    let close = bars.close[to];
+
+    Some(Pivots {
+        session_date: bars.dates[from],
+        high,
+        low,
+        close,
+        levels: vec![
+            classic(high, low, close),
+            fibonacci(high, low, close),
+            camarilla(high, low, close),
+            woodie(high, low, close),
+        ],
+    })
+}
+
+fn classic(high: f64, low: f64, close: f64) -> PivotLevels {
+    let pivot = (high + low + close) / 3.;
+    let range = high - low;
+    PivotLevels {
+        pivot_type: PivotType::Classic,
+        pivot,
+        resistances: vec![2. * pivot - low, pivot + range, high + 2. * (pivot - low)],
+        supports: vec![2. * pivot - high, pivot - range, low - 2. * (high - pivot)],
+    }
+}
+
+fn fibonacci(high: f64, low: f64, close: f64) -> PivotLevels {
+    let pivot = (high + low + close) / 3.;
+    let range = high - low;
+    let ratios = [0.382, 0.618, 1.];
+    PivotLevels {
+        pivot_type: PivotType::Fibonacci,
+        pivot,
+        resistances: ratios.iter().map(|ratio| pivot + ratio * range).collect(),
+        supports: ratios.iter().map(|ratio| pivot - ratio * range).collect(),
+    }
+}
+
+fn camarilla(high: f64, low: f64, close: f64) -> PivotLevels {
+    let range = high - low;
+    let divisors = [12., 6., 4., 2.];
+    PivotLevels {
+        pivot_type: PivotType::Camarilla,
+        pivot: (high + low + close) / 3.,
+        resistances: divisors
+            .iter()
+            .map(|divisor| close + range * 1.1 / divisor)
+            .collect(),
+        supports: divisors
+            .iter()
+            .map(|divisor| close - range * 1.1 / divisor)
+            .collect(),
+    }
+}
+
+fn woodie(high: f64, low: f64, close: f64) -> PivotLevels {
+    let pivot = (high + low + 2. * close) / 4.;
+    let range = high - low;
+    PivotLevels {
+        pivot_type: PivotType::Woodie,
+        pivot,
+        resistances: vec![2. * pivot - low, pivot + range],
+        supports: vec![2. * pivot - high, pivot - range],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_levels(levels: &[f64], expected: &[f64]) {
+        assert_eq!(levels.len(), expected.len());
+        for (level, expected) in levels.iter().zip(expected) {
+            assert!((level - expected).abs() < 1e-9, "{} {}", level, expected);
+        }
+    }
+
+    fn family(pivots: &Pivots, pivot_type: PivotType) -> &PivotLevels {
+        pivots
+            .levels
+            .iter()
+            .find(|levels| levels.pivot_type == pivot_type)
+            .unwrap()
+    }
+
+    /// High 110, low 90 and close 105 on the previous bar.
+    fn daily_bars() -> Bars {
+        let mut bars = Bars::from_closes(&[100., 105., 120.], 10.);
+        bars.high[1] = 110.;
+        bars.low[1] = 90.;
+        bars
+    }
+
+    #[test]
+    fn classic_and_fibonacci_pivots() {
+        let pivots = detect(&daily_bars(), false).unwrap();
+        assert_eq!((pivots.high, pivots.low, pivots.close), (110., 90., 105.));
+
+        let classic = family(&pivots, PivotType::Classic);
+        assert!((classic.pivot - 101.666666667).abs() < 1e-9);
+        assert_levels(
+            &classic.resistances,
+            &[113.333333333, 121.666666667, 133.333333333],
+        );
+        assert_levels(
+            &classic.supports,
+            &[93.333333333, 81.666666667, 73.333333333],
+        );
+
+        let fibonacci = family(&pivots, PivotType::Fibonacci);
+        assert_levels(
+            &fibonacci.resistances,
+            &[109.306666667, 114.026666667, 121.666666667],
+        );
+    }
+
+    #[test]
+    fn camarilla_and_woodie_pivots() {
+        let pivots = detect(&daily_bars(), false).unwrap();
+
+        let camarilla = family(&pivots, PivotType::Camarilla);
+        assert_levels(
+            &camarilla.resistances,
+            &[106.833333333, 108.666666667, 110.5, 116.],
+        );
+        assert_levels(
+            &camarilla.supports,
+            &[103.166666667, 101.333333333, 99.5, 94.],
+        );
+
+        let woodie = family(&pivots, PivotType::Woodie);
+        assert!((woodie.pivot - 102.5).abs() < 1e-9);
+        assert_levels(&woodie.resistances, &[115., 122.5]);
+        assert_levels(&woodie.supports, &[95., 82.5]);
+    }
+
+    #[test]
+    fn intraday_bars_are_resampled_into_the_previous_day() {
+        let mut bars = Bars::from_closes(&[100., 104., 96., 101., 103., 99., 102.], 1.);
+        bars.days = vec![1, 1, 2, 2, 2, 3, 3];
+
+        let pivots = detect(&bars, true).unwrap();
+        assert_eq!(pivots.session_date, bars.dates[2]);
+        assert_eq!((pivots.high, pivots.low, pivots.close), (104., 95., 103.));
+    }
+
+    #[test]
+    fn needs_a_previous_session() {
+        let mut bars = Bars::from_closes(&[100., 104.], 1.);
+        bars.days = vec![1, 1];
+        assert!(detect(&bars, true).is_none());
+        assert!(detect(&Bars::from_closes(&[100.], 1.), false).is_none());
+    }
+}
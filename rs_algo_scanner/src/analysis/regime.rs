@@ -0,0 +1,236 @@
+use super::{price_source, Bars};
+use crate::helpers::regression::{smooth as smooth_series, Smoother};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum RegimeType {
+    TrendingUp,
+    TrendingDown,
+    Ranging,
+    HighVolatility,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Regime {
+    pub current: RegimeType,
+    pub slope: f64,
+    pub volatility: f64,
+    pub series: Vec<RegimeType>,
+}
+
+impl Default for Regime {
+    fn default() -> Self {
+        Self {
+            current: RegimeType::Ranging,
+            slope: 0.,
+            volatility: 0.,
+            series: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegimeParams {
+    pub smoother: Smoother,
+    pub smoothing_window: usize,
+    pub slope_period: usize,
+    pub slope_threshold: f64,
+    pub volatility_period: usize,
+    pub volatility_factor: f64,
+}
+
+/// Labels every bar using the slope of the smoothed closes (relative change
+/// per bar over `slope_period` bars) and the standard deviation of the returns.
+/// Bars whose volatility is `volatility_factor` times above its running average are
+/// labeled as high volatility whatever the slope is.
+pub fn classify(bars: &Bars, params: &RegimeParams, logarithmic: bool) -> Regime {
+    let len = bars.close.len();
+    if len == 0 {
+        return Regime::default();
+    }
+
+    let smoothed = smooth(bars, params, logarithmic);
+    let slope_period = params.slope_period.max(1);
+    let volatility_period = params.volatility_period.max(2);
+
+    let slopes: Vec<f64> = (0..len)
+        .map(|index| match index >= slope_period {
+            true => (smoothed[index] / smoothed[index - slope_period] - 1.) / slope_period as f64,
+            false => 0.,
+        })
+        .collect();
+
+    let returns: Vec<f64> = (0..len)
+        .map(|index| match index > 0 {
+            true => bars.close[index] / bars.close[index - 1] - 1.,
+            false => 0.,
+        })
+        .collect();
+
+    let volatility: Vec<f64> = (0..len)
+        .map(|index| match index >= volatility_period {
+            true => std_dev(&returns[index + 1 - volatility_period..=index]),
+            false => 0.,
+        })
+        .collect();
+
+    let mut volatility_sum = 0.;
+    let series: Vec<RegimeType> = (0..len)
+        .map(|index| {
+            let mut high_volatility = false;
+            if index >= volatility_period {
+                volatility_sum += volatility[index];
+                let samples = index + 1 - volatility_period;
+                let average = volatility_sum / samples as f64;
+                high_volatility = samples >= volatility_period
+                    && volatility[index] > average * params.volatility_factor;
+            }
+
+            if high_volatility {
+                RegimeType::HighVolatility
+            } else if slopes[index] > params.slope_threshold {
+                RegimeType::TrendingUp
+            } else if slopes[index] < -params.slope_threshold {
+                RegimeType::TrendingDown
+            } else {
+                RegimeType::Ranging
+            }
+        })
+        .collect();
+
+    Regime {
+        current: series[len - 1],
+        slope: slopes[len - 1],
+        volatility: volatility[len - 1],
+        series,
+    }
+}
+
+/// Causal smoothing over the previous `smoothing_window` closes.
+fn smooth(bars: &Bars, params: &RegimeParams, logarithmic: bool) -> Vec<f64> {
+    let closes = price_source(&bars.close, logarithmic);
+
+    smooth_series(&params.smoother, &closes, params.smoothing_window)
+        .into_iter()
+        .zip(closes.iter())
+        .map(|(smoothed, close)| {
+            let smoothed = match smoothed.is_finite() {
+                true => smoothed,
+                false => *close,
+            };
+            match logarithmic {
+                true => smoothed.exp(),
+                false => smoothed,
+            }
+        })
+        .collect()
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / values.len() as f64;
+    variance.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> RegimeParams {
+        RegimeParams {
+            smoother: Smoother::Loess,
+            smoothing_window: 5,
+            slope_period: 5,
+            slope_threshold: 0.002,
+            volatility_period: 5,
+            volatility_factor: 2.,
+        }
+    }
+
+    /// Closes changing `growth` per bar with a steady 0.2% zigzag around the trend.
+    fn closes(len: usize, growth: f64) -> Vec<f64> {
+        (0..len)
+            .map(|index| {
+                let zigzag = match index % 2 {
+                    0 => 1.002,
+                    _ => 0.998,
+                };
+                100. * (1. + growth).powi(index as i32) * zigzag
+            })
+            .collect()
+    }
+
+    /// Every bar after the slope warm up has the expected regime.
+    fn assert_regime(regime: &Regime, expected: RegimeType) {
+        let warm_up = params().slope_period;
+        assert_eq!(regime.current, expected);
+        assert!(regime.series[..warm_up]
+            .iter()
+            .all(|regime| *regime == RegimeType::Ranging));
+        assert!(
+            regime.series[warm_up..]
+                .iter()
+                .all(|regime| *regime == expected),
+            "{:?}",
+            regime.series
+        );
+    }
+
+    #[test]
+    fn trending_up() {
+        let regime = classify(&Bars::from_closes(&closes(60, 0.01), 1.), &params(), false);
+        assert_regime(&regime, RegimeType::TrendingUp);
+        assert!(
+            regime.slope > 0.008 && regime.slope < 0.012,
+            "{}",
+            regime.slope
+        );
+    }
+
+    #[test]
+    fn trending_down() {
+        let regime = classify(&Bars::from_closes(&closes(60, -0.01), 1.), &params(), true);
+        assert_regime(&regime, RegimeType::TrendingDown);
+        assert!(
+            regime.slope < -0.008 && regime.slope > -0.012,
+            "{}",
+            regime.slope
+        );
+    }
+
+    #[test]
+    fn ranging() {
+        let regime = classify(&Bars::from_closes(&closes(60, 0.), 1.), &params(), false);
+        assert_regime(&regime, RegimeType::Ranging);
+        assert!(regime.slope.abs() < params().slope_threshold);
+    }
+
+    #[test]
+    fn high_volatility() {
+        let mut data = closes(60, 0.);
+        data.extend([95., 105., 95., 105., 95.]);
+        let regime = classify(&Bars::from_closes(&data, 1.), &params(), false);
+
+        assert_eq!(regime.current, RegimeType::HighVolatility);
+        assert!(regime.series[..60]
+            .iter()
+            .all(|regime| *regime == RegimeType::Ranging));
+        assert!(regime.series[60..]
+            .iter()
+            .all(|regime| *regime == RegimeType::HighVolatility));
+        assert_eq!(regime.series.len(), data.len());
+    }
+
+    #[test]
+    fn empty_bars() {
+        assert_eq!(
+            classify(&Bars::default(), &params(), false),
+            Regime::default()
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use super::{Bars, Swings};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use polyfit_rs::polyfit_rs::polyfit;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trendline {
+    pub points: Vec<(usize, f64)>,
+    /// Polynomial coefficients, lowest degree first, in bars from `start`.
+    pub coefficients: Vec<f64>,
+    pub r_squared: f64,
+    /// Slope at `end` in percent of the line value per bar.
+    pub slope: f64,
+    pub touches: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Trendline {
+    pub fn value_at(&self, index: usize) -> f64 {
+        let x = index as f64 - self.start as f64;
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0., |value, coefficient| value * x + coefficient)
+    }
+
+    fn derivative_at(&self, index: usize) -> f64 {
+        let x = index as f64 - self.start as f64;
+        self.coefficients
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .fold(0., |value, (power, coefficient)| {
+                value * x + power as f64 * coefficient
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ChannelType {
+    ChannelUp,
+    ChannelDown,
+    HorizontalChannel,
+    RisingWedge,
+    FallingWedge,
+    Triangle,
+    Broadening,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Channel {
+    pub channel_type: ChannelType,
+    /// Distance between the lines at the last bar, in percent of the lower one.
+    pub width: f64,
+    pub date: DbDateTime,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Trendlines {
+    pub upper: Option<Trendline>,
+    pub lower: Option<Trendline>,
+    pub channel: Option<Channel>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrendlineParams {
+    /// Number of recent swings every line is fitted to.
+    pub points: usize,
+    pub degree: usize,
+    pub min_r_squared: f64,
+    /// Relative distance to the line that counts as a touch.
+    pub touch_tolerance: f64,
+    /// Maximum slope difference, in percent per bar, between parallel lines.
+    pub parallel_threshold: f64,
+}
+
+/// Fits the upper line to the last swing highs and the lower one to the last swing
+/// lows. Lines below `min_r_squared` are discarded, and the pair is classified as a
+/// channel, wedge, triangle or broadening formation when both lines are valid.
+pub fn detect(bars: &Bars, swings: &Swings, params: &TrendlineParams) -> Trendlines {
+    let end = match bars.close.len() {
+        0 => return Trendlines::default(),
+        len => len - 1,
+    };
+
+    let line = |swings: &[(usize, f64)], values: &[f64]| {
+        let from = swings.len().saturating_sub(params.points);
+        fit(&swings[from..], params.degree, end)
+            .filter(|line| line.r_squared >= params.min_r_squared)
+            .map(|mut line| {
+                line.touches = touches(&line, values, params.touch_tolerance);
+                line
+            })
+    };
+
+    let upper = line(&swings.highs, &bars.high);
+    let lower = line(&swings.lows, &bars.low);
+
+    let channel = match (&upper, &lower) {
+        (Some(upper), Some(lower)) => {
+            channel(upper, lower, params.parallel_threshold).map(|(channel_type, width)| Channel {
+                channel_type,
+                width,
+                date: bars.dates[end],
+            })
+        }
+        _ => None,
+    };
+
+    Trendlines {
+        upper,
+        lower,
+        channel,
+    }
+}
+
+/// Least squares polynomial through the points. The slope is measured at `end`.
+pub fn fit(points: &[(usize, f64)], degree: usize, end: usize) -> Option<Trendline> {
+    if degree == 0 || points.len() <= degree {
+        return None;
+    }
+
+    let start = points[0].0;
+    let x_values: Vec<f64> = points
+        .iter()
+        .map(|(index, _)| (index - start) as f64)
+        .collect();
+    let y_values: Vec<f64> = points.iter().map(|(_, price)| *price).collect();
+    let coefficients = polyfit(&x_values, &y_values, degree).ok()?;
+
+    let mut line = Trendline {
+        points: points.to_vec(),
+        coefficients,
+        r_squared: 0.,
+        slope: 0.,
+        touches: 0,
+        start,
+        end,
+    };
+
+    let mean = y_values.iter().sum::<f64>() / y_values.len() as f64;
+    let (residuals, total) = points
+        .iter()
+        .fold((0., 0.), |(residuals, total), (index, price)| {
+            (
+                residuals + (price - line.value_at(*index)).powi(2),
+                total + (price - mean).powi(2),
+            )
+        });
+
+    line.r_squared = match total > f64::EPSILON {
+        true => 1. - residuals / total,
+        false if residuals <= f64::EPSILON => 1.,
+        false => 0.,
+    };
+    line.slope = line.derivative_at(end) / line.value_at(end) * 100.;
+
+    match line.coefficients.iter().all(|value| value.is_finite()) {
+        true => Some(line),
+        false => None,
+    }
+}
+
+/// Number of times the values come within `tolerance` of the line, counting
+/// consecutive touching bars once.
+fn touches(line: &Trendline, values: &[f64], tolerance: f64) -> usize {
+    let mut touching = false;
+    let mut result = 0;
+
+    for (index, value) in values
+        .iter()
+        .enumerate()
+        .take(line.end + 1)
+        .skip(line.start)
+    {
+        let line_value = line.value_at(index);
+        let touches = ((value - line_value) / line_value).abs() <= tolerance;
+        if touches && !touching {
+            result += 1;
+        }
+        touching = touches;
+    }
+
+    result
+}
+
+fn channel(
+    upper: &Trendline,
+    lower: &Trendline,
+    parallel_threshold: f64,
+) -> Option<(ChannelType, f64)> {
+    let start = upper.start.max(lower.start);
+    let end = upper.end;
+    let start_width = upper.value_at(start) - lower.value_at(start);
+    let end_width = upper.value_at(end) - lower.value_at(end);
+
+    if start_width <= 0. || end_width <= 0. {
+        return None;
+    }
+
+    let slope = (upper.slope + lower.slope) / 2.;
+    let channel_type = if (upper.slope - lower.slope).abs() <= parallel_threshold {
+        if slope > parallel_threshold {
+            ChannelType::ChannelUp
+        } else if slope < -parallel_threshold {
+            ChannelType::ChannelDown
+        } else {
+            ChannelType::HorizontalChannel
+        }
+    } else if end_width < start_width {
+        if upper.slope > 0. && lower.slope > 0. {
+            ChannelType::RisingWedge
+        } else if upper.slope < 0. && lower.slope < 0. {
+            ChannelType::FallingWedge
+        } else {
+            ChannelType::Triangle
+        }
+    } else {
+        ChannelType::Broadening
+    };
+
+    Some((channel_type, end_width / lower.value_at(end) * 100.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> TrendlineParams {
+        TrendlineParams {
+            points: 3,
+            degree: 1,
+            min_r_squared: 0.9,
+            touch_tolerance: 0.002,
+            parallel_threshold: 0.05,
+        }
+    }
+
+    fn line(from: (usize, f64), step: f64, every: usize, count: usize) -> Vec<(usize, f64)> {
+        (0..count)
+            .map(|n| (from.0 + n * every, from.1 + step * (n * every) as f64))
+            .collect()
+    }
+
+    /// Bars touching the swings, and half way between the lines everywhere else.
+    fn bars(highs: &[(usize, f64)], lows: &[(usize, f64)], len: usize) -> Bars {
+        let mut bars = Bars::from_closes(&vec![100.; len], 0.);
+        let upper = fit(highs, 1, len - 1).unwrap();
+        let lower = fit(lows, 1, len - 1).unwrap();
+        for index in 0..len {
+            let middle = (upper.value_at(index) + lower.value_at(index)) / 2.;
+            bars.high[index] = middle;
+            bars.low[index] = middle;
+            bars.close[index] = middle;
+        }
+        for (index, price) in highs {
+            bars.high[*index] = *price;
+        }
+        for (index, price) in lows {
+            bars.low[*index] = *price;
+        }
+        bars
+    }
+
+    fn detect_lines(highs: Vec<(usize, f64)>, lows: Vec<(usize, f64)>) -> Trendlines {
+        let bars = bars(&highs, &lows, 50);
+        detect(&bars, &Swings { highs, lows }, &params())
+    }
+
+    #[test]
+    fn fits_lines_with_slope_in_percent_per_bar() {
+        let line = fit(&line((10, 100.), 0.5, 10, 3), 1, 30).unwrap();
+        assert!((line.r_squared - 1.).abs() < 1e-9);
+        assert!((line.value_at(30) - 110.).abs() < 1e-6);
+        assert!((line.slope - 0.5 / 110. * 100.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fits_polynomials() {
+        let points: Vec<(usize, f64)> = (0..6)
+            .map(|n| {
+                (
+                    n * 5,
+                    100. + (n * 5) as f64 * 0.1 + ((n * 5) as f64).powi(2) * 0.01,
+                )
+            })
+            .collect();
+        let curve = fit(&points, 2, 30).unwrap();
+        assert!((curve.r_squared - 1.).abs() < 1e-6);
+        assert!((curve.value_at(30) - 112.).abs() < 1e-6);
+        assert!((curve.derivative_at(30) - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn r_squared_drops_with_scattered_points() {
+        let line = fit(&[(0, 100.), (10, 110.), (20, 95.), (30, 108.)], 1, 30).unwrap();
+        assert!(line.r_squared < 0.5);
+        assert!(fit(&[(0, 100.)], 1, 30).is_none());
+    }
+
+    #[test]
+    fn counts_touches() {
+        let trendlines = detect_lines(line((5, 110.), 0., 10, 4), line((10, 100.), 0., 10, 4));
+        let upper = trendlines.upper.unwrap();
+        assert_eq!(upper.points.len(), 3);
+        assert_eq!(upper.start, 15);
+        assert_eq!(upper.touches, 3);
+        assert_eq!(trendlines.lower.unwrap().touches, 3);
+    }
+
+    #[test]
+    fn detects_channels() {
+        let up = detect_lines(line((5, 110.), 0.2, 10, 4), line((10, 101.), 0.2, 10, 4));
+        assert_eq!(up.channel.unwrap().channel_type, ChannelType::ChannelUp);
+
+        let down = detect_lines(line((5, 110.), -0.2, 10, 4), line((10, 99.), -0.2, 10, 4));
+        assert_eq!(down.channel.unwrap().channel_type, ChannelType::ChannelDown);
+
+        let horizontal = detect_lines(line((5, 110.), 0., 10, 4), line((10, 100.), 0., 10, 4));
+        let channel = horizontal.channel.unwrap();
+        assert_eq!(channel.channel_type, ChannelType::HorizontalChannel);
+        assert!((channel.width - 10.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn detects_wedges_triangles_and_broadenings() {
+        let rising = detect_lines(line((5, 110.), 0.1, 10, 4), line((10, 95.), 0.4, 10, 4));
+        assert_eq!(
+            rising.channel.unwrap().channel_type,
+            ChannelType::RisingWedge
+        );
+
+        let falling = detect_lines(line((5, 115.), -0.4, 10, 4), line((10, 100.), -0.1, 10, 4));
+        assert_eq!(
+            falling.channel.unwrap().channel_type,
+            ChannelType::FallingWedge
+        );
+
+        let triangle = detect_lines(line((5, 115.), -0.2, 10, 4), line((10, 95.), 0.2, 10, 4));
+        assert_eq!(
+            triangle.channel.unwrap().channel_type,
+            ChannelType::Triangle
+        );
+
+        let broadening = detect_lines(line((5, 105.), 0.2, 10, 4), line((10, 100.), -0.2, 10, 4));
+        assert_eq!(
+            broadening.channel.unwrap().channel_type,
+            ChannelType::Broadening
+        );
+    }
+
+    #[test]
+    fn no_channel_without_valid_lines() {
+        let trendlines = detect_lines(
+            vec![(5, 110.), (15, 118.), (25, 104.), (35, 115.)],
+            line((10, 100.), 0., 10, 4),
+        );
+        assert!(trendlines.upper.is_none());
+        assert!(trendlines.lower.is_some());
+        assert!(trendlines.channel.is_none());
+    }
+}
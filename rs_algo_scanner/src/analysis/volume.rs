@@ -0,0 +1,315 @@
+use super::{Bars, Swings};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnchoredVwap {
+    pub anchor: (usize, f64),
+    pub date: DbDateTime,
+    pub vwap: f64,
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Vwap {
+    pub session_date: DbDateTime,
+    pub vwap: f64,
+    pub std_dev: f64,
+    pub upper_bands: Vec<f64>,
+    pub lower_bands: Vec<f64>,
+    /// Last close distance in percent, positive above the VWAP.
+    pub distance: f64,
+    /// VWAP anchored at the last swing high and the last swing low.
+    pub anchored: Vec<AnchoredVwap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VolumeProfile {
+    /// Middle price and volume of every bin, from the lowest price.
+    pub bins: Vec<(f64, f64)>,
+    pub point_of_control: f64,
+    pub value_area_high: f64,
+    pub value_area_low: f64,
+    /// Last close distance to the point of control in percent.
+    pub poc_distance: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct VolumeParams {
+    /// Standard deviation multipliers of the VWAP bands.
+    pub bands: Vec<f64>,
+    pub profile_bins: usize,
+    pub profile_lookback: usize,
+    /// Share of the volume inside the value area.
+    pub value_area: f64,
+}
+
+/// VWAP of the current session (the current month for daily and longer time frames)
+/// with standard deviation bands, plus the VWAPs anchored at the last swings.
+pub fn vwap(bars: &Bars, swings: &Swings, intraday: bool, params: &VolumeParams) -> Option<Vwap> {
+    let len = bars.close.len();
+    let close = *bars.close.last()?;
+
+    let session = |day: i32| match intraday {
+        true => day,
+        false => month(day),
+    };
+    let current_session = session(bars.days[len - 1]);
+    let from = bars
+        .days
+        .iter()
+        .rposition(|day| session(*day) != current_session)
+        .map_or(0, |index| index + 1);
+
+    let (vwap, std_dev) = weighted_price(bars, from)?;
+
+    let anchored = swings
+        .highs
+        .last()
+        .into_iter()
+        .chain(swings.lows.last())
+        .filter_map(|anchor| {
+            weighted_price(bars, anchor.0).map(|(vwap, _)| AnchoredVwap {
+                anchor: *anchor,
+                date: bars.dates[anchor.0],
+                vwap,
+                distance: distance(close, vwap),
+            })
+        })
+        .collect();
+
+    Some(Vwap {
+        session_date: bars.dates[from],
+        vwap,
+        std_dev,
+        upper_bands: params
+            .bands
+            .iter()
+            .map(|band| vwap + band * std_dev)
+            .collect(),
+        lower_bands: params
+            .bands
+            .iter()
+            .map(|band| vwap - band * std_dev)
+            .collect(),
+        distance: distance(close, vwap),
+        anchored,
+    })
+}
+
+/// Volume by price of the last `profile_lookback` bars. The volume of every bar is
+/// spread over the bins its range covers. The value area grows from the point of
+/// control towards the heavier neighbour bin until it holds `value_area` of the volume.
+pub fn volume_profile(bars: &Bars, params: &VolumeParams) -> Option<VolumeProfile> {
+    let len = bars.close.len();
+    let close = *bars.close.last()?;
+    let from = len.saturating_sub(params.profile_lookback);
+    let num_bins = params.profile_bins.max(1);
+
+    let low = bars.low[from..].iter().copied().fold(f64::MAX, f64::min);
+    let high = bars.high[from..].iter().copied().fold(f64::MIN, f64::max);
+    let bin_size = (high - low) / num_bins as f64;
+    if bin_size <= 0. {
+        return None;
+    }
+
+    let mut volumes = vec![0.; num_bins];
+    for index in from..len {
+        let (bar_low, bar_high) = (bars.low[index], bars.high[index]);
+        let first = (((bar_low - low) / bin_size) as usize).min(num_bins - 1);
+        let last = (((bar_high - low) / bin_size) as usize).min(num_bins - 1);
+
+        for (bin, volume) in volumes.iter_mut().enumerate().take(last + 1).skip(first) {
+            let bin_low = low + bin as f64 * bin_size;
+            let overlap = bar_high.min(bin_low + bin_size) - bar_low.max(bin_low);
+            *volume += match bar_high > bar_low {
+                true => bars.volume[index] * overlap.max(0.) / (bar_high - bar_low),
+                false => bars.volume[index] / (last - first + 1) as f64,
+            };
+        }
+    }
+
+    let total: f64 = volumes.iter().sum();
+    if total <= 0. {
+        return None;
+    }
+
+    let poc = volumes
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(bin, _)| bin)?;
+
+    let (mut value_area_from, mut value_area_to) = (poc, poc);
+    let mut value_area_volume = volumes[poc];
+    while value_area_volume < total * params.value_area {
+        let below = match value_area_from > 0 {
+            true => volumes[value_area_from - 1],
+            false => -1.,
+        };
+        let above = match value_area_to < num_bins - 1 {
+            true => volumes[value_area_to + 1],
+            false => -1.,
+        };
+        if below < 0. && above < 0. {
+            break;
+        }
+        if above >= below {
+            value_area_to += 1;
+            value_area_volume += above;
+        } else {
+            value_area_from -= 1;
+            value_area_volume += below;
+        }
+    }
+
+    let middle = |bin: usize| low + (bin as f64 + 0.5) * bin_size;
+    let point_of_control = middle(poc);
+
+    Some(VolumeProfile {
+        bins: volumes
+            .iter()
+            .enumerate()
+            .map(|(bin, volume)| (middle(bin), *volume))
+            .collect(),
+        point_of_control,
+        value_area_high: low + (value_area_to + 1) as f64 * bin_size,
+        value_area_low: low + value_area_from as f64 * bin_size,
+        poc_distance: distance(close, point_of_control).abs(),
+    })
+}
+
+/// Volume weighted typical price and its standard deviation from `from` to the last bar.
+fn weighted_price(bars: &Bars, from: usize) -> Option<(f64, f64)> {
+    let typical = |index: usize| (bars.high[index] + bars.low[index] + bars.close[index]) / 3.;
+    let range = from..bars.close.len();
+
+    let volume: f64 = bars.volume[range.clone()].iter().sum();
+    if volume <= 0. {
+        return None;
+    }
+
+    let vwap = range
+        .clone()
+        .map(|index| typical(index) * bars.volume[index])
+        .sum::<f64>()
+        / volume;
+
+    let variance = range
+        .map(|index| (typical(index) - vwap).powi(2) * bars.volume[index])
+        .sum::<f64>()
+        / volume;
+
+    Some((vwap, variance.sqrt()))
+}
+
+/// Months since the common era of a day since the common era.
+fn month(day: i32) -> i32 {
+    NaiveDate::from_num_days_from_ce_opt(day)
+        .map_or(0, |date| date.year() * 12 + date.month0() as i32)
+}
+
+fn distance(price: f64, reference: f64) -> f64 {
+    (price - reference) / reference * 100.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> VolumeParams {
+        VolumeParams {
+            bands: vec![1., 2.],
+            profile_bins: 10,
+            profile_lookback: 100,
+            value_area: 0.7,
+        }
+    }
+
+    fn no_swings() -> Swings {
+        Swings {
+            highs: vec![],
+            lows: vec![],
+        }
+    }
+
+    #[test]
+    fn volume_weighted_price_and_bands() {
+        let mut bars = Bars::from_closes(&[100., 110.], 0.);
+        bars.volume = vec![3000., 1000.];
+        bars.days = vec![738000, 738001];
+
+        let vwap = vwap(&bars, &no_swings(), false, &params()).unwrap();
+        assert!((vwap.vwap - 102.5).abs() < 1e-9);
+        assert!((vwap.std_dev - 4.330_127_018_922_193).abs() < 1e-9);
+        assert!((vwap.upper_bands[1] - (102.5 + 2. * vwap.std_dev)).abs() < 1e-9);
+        assert!((vwap.lower_bands[0] - (102.5 - vwap.std_dev)).abs() < 1e-9);
+        assert!((vwap.distance - 7.317_073_170_731_707).abs() < 1e-9);
+    }
+
+    #[test]
+    fn intraday_vwap_starts_with_the_session() {
+        let mut bars = Bars::from_closes(&[90., 100., 110.], 0.);
+        bars.days = vec![1, 2, 2];
+
+        let vwap = vwap(&bars, &no_swings(), true, &params()).unwrap();
+        assert!((vwap.vwap - 105.).abs() < 1e-9);
+        assert_eq!(vwap.session_date, bars.dates[1]);
+    }
+
+    #[test]
+    fn daily_vwap_starts_with_the_month() {
+        let mut bars = Bars::from_closes(&[90., 95., 100., 110.], 0.);
+        // 2021-07-30, 2021-07-31, 2021-08-01 and 2021-08-02
+        bars.days = vec![738001, 738002, 738003, 738004];
+
+        let vwap = vwap(&bars, &no_swings(), false, &params()).unwrap();
+        assert!((vwap.vwap - 105.).abs() < 1e-9);
+        assert_eq!(vwap.session_date, bars.dates[2]);
+    }
+
+    #[test]
+    fn anchored_at_the_last_swings() {
+        let bars = Bars::from_closes(&[100., 120., 110., 90., 95., 100.], 0.);
+        let swings = Swings {
+            highs: vec![(1, 120.)],
+            lows: vec![(3, 90.)],
+        };
+
+        let vwap = vwap(&bars, &swings, false, &params()).unwrap();
+        assert_eq!(vwap.anchored.len(), 2);
+        assert_eq!(vwap.anchored[0].anchor, (1, 120.));
+        assert!((vwap.anchored[0].vwap - 103.).abs() < 1e-9);
+        assert!((vwap.anchored[1].vwap - 95.).abs() < 1e-9);
+        assert!((vwap.anchored[1].distance - 5.263_157_894_736_842).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_vwap_without_volume() {
+        let mut bars = Bars::from_closes(&[100., 110.], 1.);
+        bars.volume = vec![0., 0.];
+        assert!(vwap(&bars, &no_swings(), false, &params()).is_none());
+        assert!(volume_profile(&bars, &params()).is_none());
+    }
+
+    #[test]
+    fn point_of_control_and_value_area() {
+        let mut closes = vec![105.; 10];
+        closes[5] = 105.2;
+        closes.extend([100.5, 109.5]);
+        let mut bars = Bars::from_closes(&closes, 0.5);
+        bars.volume[10] = 500.;
+        bars.volume[11] = 500.;
+
+        let profile = volume_profile(&bars, &params()).unwrap();
+        assert_eq!(profile.bins.len(), 10);
+        assert!((profile.point_of_control - 105.5).abs() < 1e-9);
+        assert!((profile.bins.iter().map(|bin| bin.1).sum::<f64>() - 11000.).abs() < 1e-6);
+        assert!((profile.value_area_low - 104.).abs() < 1e-9);
+        assert!((profile.value_area_high - 106.).abs() < 1e-9);
+        assert!((profile.poc_distance - 3.791_469_194_312_796).abs() < 1e-9);
+    }
+}
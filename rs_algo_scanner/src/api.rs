@@ -0,0 +1,104 @@
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+
+use rs_algo_shared::broker::VEC_DOHLC;
+use rs_algo_shared::helpers::http::{request, HttpMethod};
+use rs_algo_shared::scanner::candle::Candle;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LastCandle {
+    pub symbol: String,
+    pub timestamp: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct InstrumentCandles {
+    data: Vec<Candle>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+struct ListedInstrument {
+    symbol: String,
+}
+
+pub fn backend_symbol(symbol: &str) -> &str {
+    symbol.split('_').next().unwrap_or(symbol)
+}
+
+pub async fn get_last_candles(time_frame: &str) -> Result<HashMap<String, i64>> {
+    let endpoint = env::var("BACKEND_INSTRUMENTS_ENDPOINT").unwrap();
+    let url = [&endpoint, "/by_time_frame/", time_frame, "/last"].concat();
+
+    let last_candles: Vec<LastCandle> = request(&url, &String::from("all"), HttpMethod::Get)
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?
+        .json()
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?;
+
+    Ok(last_candles
+        .into_iter()
+        .map(|last_candle| (last_candle.symbol, last_candle.timestamp))
+        .collect())
+}
+
+/// Candles of the stored instrument, without its indicators and patterns.
+pub async fn get_candles(symbol: &str, time_frame: &str) -> Result<VEC_DOHLC> {
+    let endpoint = env::var("BACKEND_INSTRUMENTS_ENDPOINT").unwrap();
+    let url = [
+        &endpoint,
+        "/by_time_frame/",
+        time_frame,
+        "/",
+        backend_symbol(symbol),
+        "/candles",
+    ]
+    .concat();
+
+    let candles: InstrumentCandles = request(&url, &String::from("all"), HttpMethod::Get)
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?
+        .json()
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?;
+
+    Ok(candles
+        .data
+        .iter()
+        .map(|candle| {
+            (
+                candle.date,
+                candle.open,
+                candle.high,
+                candle.low,
+                candle.close,
+                candle.volume,
+            )
+        })
+        .collect())
+}
+
+pub async fn get_watch_list() -> Result<HashSet<String>> {
+    get_listed_symbols(&env::var("BACKEND_WATCHLIST_ENDPOINT").unwrap()).await
+}
+
+pub async fn get_portfolio() -> Result<HashSet<String>> {
+    get_listed_symbols(&env::var("BACKEND_PORTFOLIO_ENDPOINT").unwrap()).await
+}
+
+async fn get_listed_symbols(url: &str) -> Result<HashSet<String>> {
+    let instruments: Vec<ListedInstrument> = request(url, &String::from("all"), HttpMethod::Get)
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?
+        .json()
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?;
+
+    Ok(instruments
+        .into_iter()
+        .map(|instrument| instrument.symbol)
+        .collect())
+}
@@ -1,3 +1,6 @@
+use crate::analysis::levels::LevelType;
+use crate::analysis::pivots::PivotType;
+use crate::analysis::Analysis;
 use crate::error::Result;
 use rs_algo_shared::indicators::Indicator;
 use rs_algo_shared::scanner::instrument::Instrument;
@@ -15,7 +18,7 @@ impl Backend {
         Self {}
     }
 
-    pub fn render(&self, instrument: &Instrument) -> Result<()> {
+    pub fn render(&self, instrument: &Instrument, analysis: &Analysis) -> Result<()> {
         let to_date = instrument.data().last().unwrap().date();
         let from_date = instrument.data().first().unwrap().date();
         let price_source = env::var("PRICE_SOURCE").unwrap();
@@ -29,9 +32,13 @@ impl Backend {
             .parse::<f64>()
             .unwrap();
 
+        let render_pivots = env::var("RENDER_PIVOTS").unwrap();
+
         let output_file = [
             &env::var("BACKEND_PLOTTER_OUTPUT_FOLDER").unwrap(),
             instrument.symbol(),
+            "_",
+            &instrument.time_frame().to_string(),
             ".png",
         ]
         .concat();
@@ -43,7 +50,6 @@ impl Backend {
         let local_minima = instrument.peaks().local_minima();
         let _extrema_maxima = instrument.peaks().extrema_maxima();
         let _extrema_minima = instrument.peaks().extrema_minima();
-        let _horizontal_levels = instrument.horizontal_levels();
 
         let local_patterns = instrument.patterns().local_patterns.clone();
         let local_pattern_breaks: Vec<usize> = instrument
@@ -265,6 +271,102 @@ impl Backend {
                 .label(format!("{:?}", pattern.pattern_type));
         }
 
+        // SUPPORT RESISTANCE LEVELS
+
+        for level in analysis.levels.iter() {
+            let color = match level.level_type {
+                LevelType::Support => GREEN_LINE,
+                LevelType::Resistance => RED_LINE,
+            };
+            chart
+                .draw_series(LineSeries::new(
+                    [(from_date, level.price), (to_date, level.price)],
+                    color
+                        .mix(0.2 + 0.6 * level.strength)
+                        .stroke_width(1 + (level.strength * 2.) as u32),
+                ))
+                .unwrap();
+        }
+
+        // FIBONACCI LEVELS
+
+        if let Some(fibonacci) = &analysis.fibonacci {
+            let swing_date = data[fibonacci.swing_start.0].date();
+            let levels = fibonacci
+                .retracements
+                .iter()
+                .map(|level| (level, ORANGE_LINE))
+                .chain(fibonacci.extensions.iter().map(|level| (level, BLUE_LINE2)))
+                .filter(|(level, _)| level.price >= min_price && level.price <= max_price);
+
+            for (level, color) in levels {
+                let style = match fibonacci.at_level == Some(level.ratio)
+                    || fibonacci.bounced_from == Some(level.ratio)
+                {
+                    true => color.mix(0.9).stroke_width(2),
+                    false => color.mix(0.4).stroke_width(1),
+                };
+
+                chart
+                    .draw_series(LineSeries::new(
+                        [(swing_date, level.price), (to_date, level.price)],
+                        style,
+                    ))
+                    .unwrap();
+
+                chart
+                    .draw_series(std::iter::once(Text::new(
+                        format!("{:.1}%", level.ratio * 100.),
+                        (swing_date, level.price),
+                        ("sans-serif", 10),
+                    )))
+                    .unwrap();
+            }
+        }
+
+        // PIVOTS
+
+        let pivot_type = match render_pivots.as_ref() {
+            "classic" => Some(PivotType::Classic),
+            "fibonacci" => Some(PivotType::Fibonacci),
+            "camarilla" => Some(PivotType::Camarilla),
+            "woodie" => Some(PivotType::Woodie),
+            _ => None,
+        };
+
+        let pivot_levels = analysis.pivots.as_ref().and_then(|pivots| {
+            pivots
+                .levels
+                .iter()
+                .find(|levels| Some(levels.pivot_type) == pivot_type)
+        });
+
+        if let Some(pivot_levels) = pivot_levels {
+            let prices = std::iter::once((pivot_levels.pivot, BLACK.mix(0.5)))
+                .chain(
+                    pivot_levels
+                        .resistances
+                        .iter()
+                        .map(|price| (*price, RED_LINE.mix(0.3))),
+                )
+                .chain(
+                    pivot_levels
+                        .supports
+                        .iter()
+                        .map(|price| (*price, GREEN_LINE.mix(0.3))),
+                )
+                .filter(|(price, _)| *price >= min_price && *price <= max_price);
+
+            for (price, color) in prices {
+                chart
+                    .draw_series(LineSeries::new(
+                        [(from_date, price), (to_date, price)],
+                        color,
+                    ))
+                    .unwrap();
+            }
+        }
+
         // LOCAL MAXIMA MINIMA
 
         chart
@@ -0,0 +1,345 @@
+use rs_algo_shared::broker::*;
+use rs_algo_shared::error::{Result, RsAlgoErrorKind};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Broker backed by a directory of OHLCV history files.
+///
+/// Every file is named `{SYMBOL}_{TIME_FRAME}.csv` or `{SYMBOL}_{TIME_FRAME}.json`
+/// (e.g. `BITCOIN_D.csv`, `EURUSD_H1.json`). An optional `symbols.json` file with
+/// the symbol metadata can be placed in the same directory.
+#[derive(Debug)]
+pub struct FileBroker {
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileCandle {
+    date: serde_json::Value,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    #[serde(default)]
+    volume: f64,
+}
+
+#[async_trait]
+impl Broker for FileBroker {
+    async fn new() -> Self {
+        let path = env::var("FILE_BROKER_PATH").unwrap();
+        Self {
+            path: PathBuf::from(path),
+        }
+    }
+
+    async fn login(&mut self, _username: &str, _password: &str) -> Result<&mut Self> {
+        if !self.path.is_dir() {
+            log::error!("[FILE BROKER] {:?} is not a directory", self.path);
+            return Err(RsAlgoErrorKind::WrongInstrumentConf.into());
+        }
+
+        log::info!("[FILE BROKER] Reading data from {:?}", self.path);
+        Ok(self)
+    }
+
+    async fn get_symbols(&mut self) -> Result<Response<VEC_DOHLC>> {
+        let symbols_file = self.path.join("symbols.json");
+
+        let symbols = match symbols_file.exists() {
+            true => {
+                let content = fs::read_to_string(&symbols_file)
+                    .map_err(|_e| RsAlgoErrorKind::WrongInstrumentConf)?;
+                serde_json::from_str::<Vec<Symbol>>(&content)
+                    .map_err(|_e| RsAlgoErrorKind::WrongInstrumentConf)?
+            }
+            false => self.symbols_from_files()?,
+        };
+
+        Ok(Response {
+            msg_type: MessageType::GetSymbols,
+            symbol: "".to_owned(),
+            symbols,
+            data: vec![],
+        })
+    }
+
+    async fn get_instrument_data(
+        &mut self,
+        symbol: &str,
+        period: usize,
+        start: i64,
+    ) -> Result<Response<VEC_DOHLC>> {
+        let file_name = [symbol, "_", time_frame_code(period)?].concat();
+        let csv_file = self.path.join([&file_name, ".csv"].concat());
+        let json_file = self.path.join([&file_name, ".json"].concat());
+
+        let data = if csv_file.exists() {
+            read_csv(&csv_file)?
+        } else if json_file.exists() {
+            read_json(&json_file)?
+        } else {
+            log::error!("[FILE BROKER] No data file found for {}", file_name);
+            return Err(RsAlgoErrorKind::WrongInstrumentConf.into());
+        };
+
+        let data: VEC_DOHLC = data
+            .into_iter()
+            .filter(|candle| candle.0.timestamp() >= start)
+            .collect();
+
+        Ok(Response {
+            msg_type: MessageType::GetInstrumentPrice,
+            symbol: symbol.to_owned(),
+            symbols: vec![],
+            data,
+        })
+    }
+}
+
+impl FileBroker {
+    fn symbols_from_files(&self) -> Result<Vec<Symbol>> {
+        let entries =
+            fs::read_dir(&self.path).map_err(|_e| RsAlgoErrorKind::WrongInstrumentConf)?;
+
+        let mut symbols: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("csv") | Some("json")
+                )
+            })
+            .filter_map(|path| {
+                let stem = path.file_stem()?.to_str()?.to_owned();
+                let (symbol, _time_frame) = stem.rsplit_once('_')?;
+                Some(symbol.to_owned())
+            })
+            .collect();
+
+        symbols.sort();
+        symbols.dedup();
+
+        Ok(symbols
+            .into_iter()
+            .map(|symbol| Symbol {
+                symbol,
+                category: "".to_owned(),
+                description: "".to_owned(),
+                currency: "".to_owned(),
+            })
+            .collect())
+    }
+}
+
+pub fn time_frame_code(period: usize) -> Result<&'static str> {
+    match period {
+        1 => Ok("M1"),
+        5 => Ok("M5"),
+        15 => Ok("M15"),
+        30 => Ok("M30"),
+        60 => Ok("H1"),
+        240 => Ok("H4"),
+        1440 => Ok("D"),
+        10080 => Ok("W"),
+        _ => {
+            log::error!("[FILE BROKER] Unsupported time frame of {} minutes", period);
+            Err(RsAlgoErrorKind::WrongInstrumentConf.into())
+        }
+    }
+}
+
+fn read_csv(path: &Path) -> Result<VEC_DOHLC> {
+    let content = fs::read_to_string(path).map_err(|_e| RsAlgoErrorKind::WrongInstrumentConf)?;
+    let mut data: VEC_DOHLC = vec![];
+
+    let lines = content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    for (index, line) in lines.enumerate() {
+        match parse_csv_line(line) {
+            Ok(candle) => data.push(candle),
+            // Header
+            Err(_) if index == 0 => continue,
+            Err(err) => {
+                log::error!("[FILE BROKER] Wrong line {} in {:?}", line, path);
+                return Err(err);
+            }
+        }
+    }
+
+    data.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(data)
+}
+
+fn parse_csv_line(line: &str) -> Result<(DateTime<Local>, f64, f64, f64, f64, f64)> {
+    let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+    if fields.len() < 5 {
+        return Err(RsAlgoErrorKind::InvalidCandle.into());
+    }
+
+    let volume = match fields.get(5) {
+        Some(volume) => parse_price(volume)?,
+        None => 0.,
+    };
+
+    Ok((
+        parse_date(fields[0])?,
+        parse_price(fields[1])?,
+        parse_price(fields[2])?,
+        parse_price(fields[3])?,
+        parse_price(fields[4])?,
+        volume,
+    ))
+}
+
+fn read_json(path: &Path) -> Result<VEC_DOHLC> {
+    let content = fs::read_to_string(path).map_err(|_e| RsAlgoErrorKind::WrongInstrumentConf)?;
+    let candles: Vec<FileCandle> =
+        serde_json::from_str(&content).map_err(|_e| RsAlgoErrorKind::InvalidCandle)?;
+
+    let mut data: VEC_DOHLC = vec![];
+    for candle in candles {
+        let date = match &candle.date {
+            serde_json::Value::String(date) => parse_date(date)?,
+            serde_json::Value::Number(date) => parse_date(&date.to_string())?,
+            _ => return Err(RsAlgoErrorKind::InvalidCandle.into()),
+        };
+        data.push((
+            date,
+            candle.open,
+            candle.high,
+            candle.low,
+            candle.close,
+            candle.volume,
+        ));
+    }
+
+    data.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(data)
+}
+
+fn parse_price(value: &str) -> Result<f64> {
+    value
+        .parse::<f64>()
+        .map_err(|_e| RsAlgoErrorKind::InvalidCandle.into())
+}
+
+/// Accepts RFC 3339 dates, `%Y-%m-%d %H:%M:%S`, `%Y-%m-%d` and unix timestamps
+/// in seconds or milliseconds.
+pub fn parse_date(value: &str) -> Result<DateTime<Local>> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        let date = match timestamp > 100_000_000_000 {
+            true => Local.timestamp_millis_opt(timestamp),
+            false => Local.timestamp_opt(timestamp, 0),
+        };
+        return date
+            .single()
+            .ok_or_else(|| RsAlgoErrorKind::InvalidCandle.into());
+    }
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Local));
+    }
+
+    let naive = match NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        Ok(date) => date,
+        Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_e| RsAlgoErrorKind::InvalidCandle)?
+            .and_hms_opt(0, 0, 0)
+            .unwrap(),
+    };
+
+    Local
+        .from_local_datetime(&naive)
+        .single()
+        .ok_or_else(|| RsAlgoErrorKind::InvalidCandle.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn broker(name: &str, files: &[(&str, &str)]) -> FileBroker {
+        let path = env::temp_dir().join(
+            [
+                "rs_algo_file_broker_",
+                name,
+                "_",
+                &std::process::id().to_string(),
+            ]
+            .concat(),
+        );
+        fs::create_dir_all(&path).unwrap();
+        for (file_name, content) in files {
+            fs::write(path.join(file_name), content).unwrap();
+        }
+        FileBroker { path }
+    }
+
+    #[tokio::test]
+    async fn reads_csv_with_and_without_header() {
+        let csv = "2023-01-03,10,12,9,11,100\n2023-01-02,9,10,8,10\n";
+        for (name, content) in [
+            ("csv", csv.to_owned()),
+            (
+                "header",
+                ["Date,Open,High,Low,Close,Volume\n", csv].concat(),
+            ),
+        ] {
+            let mut broker = broker(name, &[("EURUSD_D.csv", &content)]);
+            let data = broker
+                .get_instrument_data("EURUSD", 1440, 0)
+                .await
+                .unwrap()
+                .data;
+
+            assert_eq!(data.len(), 2);
+            assert_eq!((data[0].1, data[0].4, data[0].5), (9., 10., 0.));
+            assert_eq!((data[1].1, data[1].2, data[1].5), (10., 12., 100.));
+            fs::remove_dir_all(&broker.path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_json() {
+        let json = r#"[
+            {"date": "2023-01-02T10:00:00Z", "open": 1, "high": 2, "low": 0.5, "close": 1.5},
+            {"date": 1672657200, "open": 1.5, "high": 3, "low": 1, "close": 2, "volume": 7}
+        ]"#;
+        let mut broker = broker("json", &[("BITCOIN_H1.json", json)]);
+
+        let data = broker
+            .get_instrument_data("BITCOIN", 60, 0)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].0.timestamp(), 1672653600);
+        assert_eq!((data[1].4, data[1].5), (2., 7.));
+
+        let symbols = broker.get_symbols().await.unwrap().symbols;
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].symbol, "BITCOIN");
+        fs::remove_dir_all(&broker.path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_rows_missing_files_and_time_frames() {
+        let csv = "date,open,high,low,close\n2023-01-02,9,10,8,10\n2023-01-03,10,x,9,11\n";
+        let mut broker = broker("errors", &[("EURUSD_D.csv", csv)]);
+
+        assert!(broker.get_instrument_data("EURUSD", 1440, 0).await.is_err());
+        assert!(broker.get_instrument_data("GBPUSD", 1440, 0).await.is_err());
+        assert!(broker.get_instrument_data("EURUSD", 7, 0).await.is_err());
+        fs::remove_dir_all(&broker.path).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use super::file::{parse_date, time_frame_code};
+use crate::daemon;
+use crate::error::{self, RsAlgoError};
+
+use rs_algo_shared::broker::*;
+use rs_algo_shared::error::{Result, RsAlgoErrorKind};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rs_algo_synthetic::{hash, Generator, Model, Shape};
+use std::env;
+
+/// Broker generating reproducible prices for the symbols of `SYNTHETIC_SYMBOLS`.
+///
+/// Every symbol is written as `{SYMBOL}:{MODEL}` followed by the shapes to inject,
+/// e.g. `SYNTH_DB:gbm+double_bottom@400,SYNTH_OU:mean_reverting`. Series start at
+/// `SYNTHETIC_START_DATE`, so a bar keeps its prices from one request to the next,
+/// and shapes are placed by bar number from that date.
+#[derive(Debug)]
+pub struct SyntheticBroker {
+    seed: u64,
+    start_date: String,
+    symbols: String,
+    generators: Vec<(String, Generator)>,
+}
+
+#[async_trait]
+impl Broker for SyntheticBroker {
+    async fn new() -> Self {
+        Self {
+            seed: env::var("SYNTHETIC_SEED").unwrap().parse::<u64>().unwrap(),
+            start_date: env::var("SYNTHETIC_START_DATE").unwrap(),
+            symbols: env::var("SYNTHETIC_SYMBOLS").unwrap(),
+            generators: vec![],
+        }
+    }
+
+    async fn login(&mut self, _username: &str, _password: &str) -> Result<&mut Self> {
+        let mut generators = vec![];
+
+        for spec in self.symbols.split(',').map(|spec| spec.trim()) {
+            if spec.is_empty() {
+                continue;
+            }
+
+            let (symbol, generator) = spec
+                .split_once(':')
+                .ok_or_else(|| {
+                    RsAlgoError::new(error::RsAlgoErrorKind::WrongInstrumentConf, "missing model")
+                })
+                .and_then(|(symbol, rest)| {
+                    let mut fields = rest.split('+');
+                    let model = fields.next().unwrap_or_default().parse::<Model>()?;
+                    let shapes = fields
+                        .map(|shape| shape.parse::<Shape>())
+                        .collect::<rs_algo_synthetic::Result<Vec<Shape>>>()?;
+                    let generator = shapes.into_iter().fold(
+                        Generator::new(self.seed ^ hash(symbol), model),
+                        Generator::shape,
+                    );
+                    Ok((symbol, generator))
+                })
+                .map_err(|err| {
+                    log::error!("[SYNTHETIC BROKER] Wrong symbol {}: {}", spec, err);
+                    RsAlgoErrorKind::WrongInstrumentConf
+                })?;
+
+            generators.push((symbol.to_owned(), generator));
+        }
+
+        log::info!(
+            "[SYNTHETIC BROKER] Generating {} symbols with seed {}",
+            generators.len(),
+            self.seed
+        );
+
+        self.generators = generators;
+        Ok(self)
+    }
+
+    async fn get_symbols(&mut self) -> Result<Response<VEC_DOHLC>> {
+        // Synthetic bars are continuous, like the crypto markets
+        let symbols = self
+            .generators
+            .iter()
+            .map(|(symbol, _generator)| Symbol {
+                symbol: symbol.to_owned(),
+                category: "CRT".to_owned(),
+                description: "Synthetic".to_owned(),
+                currency: "".to_owned(),
+            })
+            .collect();
+
+        Ok(Response {
+            msg_type: MessageType::GetSymbols,
+            symbol: "".to_owned(),
+            symbols,
+            data: vec![],
+        })
+    }
+
+    async fn get_instrument_data(
+        &mut self,
+        symbol: &str,
+        period: usize,
+        start: i64,
+    ) -> Result<Response<VEC_DOHLC>> {
+        let generator = match self.generators.iter().find(|(name, _)| name == symbol) {
+            Some((_name, generator)) => generator,
+            None => {
+                log::error!("[SYNTHETIC BROKER] Unknown symbol {}", symbol);
+                return Err(RsAlgoErrorKind::RequestError.into());
+            }
+        };
+
+        let from = parse_date(&self.start_date)?.with_timezone(&Utc);
+        let dates = bar_dates(period as i64, from, Utc::now());
+
+        // Every time frame is an independent series
+        let generator = Generator {
+            seed: generator.seed ^ hash(time_frame_code(period)?),
+            ..generator.clone()
+        };
+
+        let data: VEC_DOHLC = generator
+            .generate(&dates)
+            .into_iter()
+            .filter(|candle| candle.0.timestamp() >= start)
+            .collect();
+
+        Ok(Response {
+            msg_type: MessageType::GetInstrumentPrice,
+            symbol: symbol.to_owned(),
+            symbols: vec![],
+            data,
+        })
+    }
+}
+
+/// Bars opened from the bar that contains `from` until `to`.
+fn bar_dates(minutes: i64, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut date = daemon::last_close(minutes, from);
+    let mut dates = vec![];
+    while date <= to {
+        dates.push(date);
+        date = daemon::next_close(minutes, date);
+    }
+    dates
+}
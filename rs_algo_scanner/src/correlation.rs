@@ -0,0 +1,572 @@
+use crate::analysis::ScannedInstrument;
+use crate::api;
+use crate::daemon::Trigger;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::outbox::{self, Outbox};
+
+use chrono::Local;
+use rs_algo_shared::models::market::Market;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::env;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone)]
+pub struct CorrelationParams {
+    pub enabled: bool,
+    pub time_frame: String,
+    /// Number of returns every correlation is computed over.
+    pub window: usize,
+    /// Minimum average correlation between the members of a cluster.
+    pub cluster_threshold: f64,
+    /// Pairs correlated below this absolute value aren't sent to the backend.
+    pub store_floor: f64,
+    /// Number of bars of the rolling correlation series of every pair.
+    pub rolling_points: usize,
+}
+
+impl CorrelationParams {
+    pub fn from_env() -> Self {
+        let enabled = env::var("CORRELATION").unwrap().parse::<bool>().unwrap();
+
+        let window = env::var("CORRELATION_WINDOW")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        let cluster_threshold = env::var("CORRELATION_CLUSTER_THRESHOLD")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let store_floor = env::var("CORRELATION_STORE_FLOOR")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let rolling_points = env::var("CORRELATION_ROLLING_POINTS")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        Self {
+            enabled,
+            time_frame: env::var("CORRELATION_TIME_FRAME").unwrap(),
+            window,
+            cluster_threshold,
+            store_floor,
+            rolling_points,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cluster {
+    pub symbols: Vec<String>,
+    pub avg_correlation: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pair {
+    pub a: String,
+    pub b: String,
+    pub correlation: f64,
+    /// Correlation over the window ending at every one of the last `rolling_points`
+    /// bars, from the oldest. The last one is `correlation`.
+    pub rolling: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Correlations {
+    pub time_frame: String,
+    pub window: usize,
+    pub date: i64,
+    pub symbols: Vec<String>,
+    /// Correlation of the returns of every pair of symbols, in the order of `symbols`.
+    /// Only used for clustering, the backend gets the `pairs`.
+    #[serde(skip)]
+    pub matrix: Vec<Vec<Option<f64>>>,
+    /// Pairs correlated above the store floor.
+    pub pairs: Vec<Pair>,
+    pub clusters: Vec<Cluster>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PriceSeries {
+    pub symbol: String,
+    /// Bar number since the epoch, so bars stamped at different hours still align.
+    pub bars: Vec<i64>,
+    pub closes: Vec<f64>,
+}
+
+/// Keeps the closes of the scanned instruments of one time frame so the universe can
+/// be correlated once the scan is over.
+#[derive(Debug)]
+pub struct CorrelationCollector {
+    params: CorrelationParams,
+    series: Mutex<Vec<PriceSeries>>,
+}
+
+impl CorrelationCollector {
+    pub fn new(params: CorrelationParams) -> Self {
+        Self {
+            params,
+            series: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.params.enabled
+    }
+
+    pub fn add(&self, scanned: &ScannedInstrument) {
+        let instrument = &scanned.instrument;
+        let time_frame = instrument.time_frame();
+        if !self.params.enabled || time_frame.to_string() != self.params.time_frame {
+            return;
+        }
+
+        // Time frames are expressed in minutes
+        let bar_seconds = (time_frame.to_number() * 60).max(1);
+
+        self.series.lock().unwrap().push(PriceSeries {
+            symbol: api::backend_symbol(instrument.symbol()).to_owned(),
+            bars: instrument
+                .data()
+                .iter()
+                .map(|candle| candle.date().timestamp() / bar_seconds)
+                .collect(),
+            closes: instrument
+                .data()
+                .iter()
+                .map(|candle| candle.close())
+                .collect(),
+        });
+    }
+
+    pub fn correlations(&self) -> Correlations {
+        correlations(&self.series.lock().unwrap(), &self.params)
+    }
+
+    /// The upload replaces the stored correlations, so it needs the correlation time
+    /// frame scanned for every market of the universe.
+    pub fn covers(&self, triggers: &[Trigger], markets: &[Market]) -> bool {
+        triggers.iter().any(|trigger| {
+            trigger.time_frame.to_string() == self.params.time_frame
+                && markets
+                    .iter()
+                    .all(|market| trigger.markets.contains(market))
+        })
+    }
+
+    /// Sends the matrix to the backend, queueing it in the outbox when it's down.
+    pub async fn upload(&self, outbox: &Outbox) -> Result<()> {
+        let correlations = self.correlations();
+        if correlations.symbols.is_empty() {
+            log::info!(
+                "[CORRELATION] No {} instruments scanned, nothing to upload",
+                self.params.time_frame
+            );
+            return Ok(());
+        }
+
+        let url = env::var("BACKEND_CORRELATIONS_ENDPOINT").unwrap();
+        let key = ["correlations_", &self.params.time_frame].concat();
+
+        log::info!(
+            "[CORRELATION] {} symbols in {} clusters",
+            correlations.symbols.len(),
+            correlations.clusters.len()
+        );
+
+        match outbox::upload(&url, &correlations).await {
+            Ok(()) => {
+                outbox.remove(&key).await;
+                Ok(())
+            }
+            Err(err) => {
+                let body = serde_json::to_value(&correlations).map_err(|err| {
+                    RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+                })?;
+                outbox.push(&key, &url, body, &err).await
+            }
+        }
+    }
+}
+
+pub fn correlations(series: &[PriceSeries], params: &CorrelationParams) -> Correlations {
+    let len = series.len();
+    let mut matrix = vec![vec![None; len]; len];
+
+    for a in 0..len {
+        matrix[a][a] = Some(1.);
+        for b in (a + 1)..len {
+            let value = correlation(&series[a], &series[b], params.window);
+            matrix[a][b] = value;
+            matrix[b][a] = value;
+        }
+    }
+
+    let symbols: Vec<String> = series.iter().map(|series| series.symbol.clone()).collect();
+    let clusters = clusters(&symbols, &matrix, params.cluster_threshold);
+
+    let mut pairs = vec![];
+    for a in 0..len {
+        for b in (a + 1)..len {
+            if let Some(correlation) = matrix[a][b] {
+                if correlation.abs() >= params.store_floor {
+                    pairs.push(Pair {
+                        a: symbols[a].clone(),
+                        b: symbols[b].clone(),
+                        correlation,
+                        rolling: rolling_correlation(
+                            &series[a],
+                            &series[b],
+                            params.window,
+                            params.rolling_points,
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    Correlations {
+        time_frame: params.time_frame.clone(),
+        window: params.window,
+        date: Local::now().timestamp(),
+        symbols,
+        matrix,
+        pairs,
+        clusters,
+    }
+}
+
+/// Pearson correlation of the log returns of the last `window` bars both series have
+/// in common. Needs at least half of the window.
+pub fn correlation(a: &PriceSeries, b: &PriceSeries, window: usize) -> Option<f64> {
+    pearson(&common_closes(a, b, window + 1), window)
+}
+
+/// Correlation over `window` bars ending at every one of the last `points` bars both
+/// series have in common, from the oldest.
+pub fn rolling_correlation(
+    a: &PriceSeries,
+    b: &PriceSeries,
+    window: usize,
+    points: usize,
+) -> Vec<Option<f64>> {
+    let common = common_closes(a, b, window + points);
+    (0..points)
+        .rev()
+        .map(|offset| match offset < common.len() {
+            true => pearson(
+                &common[offset..(offset + window + 1).min(common.len())],
+                window,
+            ),
+            false => None,
+        })
+        .collect()
+}
+
+/// Closes of the last `len` bars both series have, from the newest.
+fn common_closes(a: &PriceSeries, b: &PriceSeries, len: usize) -> Vec<(f64, f64)> {
+    let mut common: Vec<(f64, f64)> = vec![];
+    let (mut i, mut j) = (a.bars.len(), b.bars.len());
+
+    while i > 0 && j > 0 && common.len() < len {
+        match a.bars[i - 1].cmp(&b.bars[j - 1]) {
+            Ordering::Greater => i -= 1,
+            Ordering::Less => j -= 1,
+            Ordering::Equal => {
+                common.push((a.closes[i - 1], b.closes[j - 1]));
+                i -= 1;
+                j -= 1;
+            }
+        }
+    }
+
+    common
+}
+
+/// Pearson correlation of the log returns of `common` closes, from the newest.
+fn pearson(common: &[(f64, f64)], window: usize) -> Option<f64> {
+    let returns: Vec<(f64, f64)> = common
+        .windows(2)
+        .filter(|pair| pair.iter().all(|(a, b)| *a > 0. && *b > 0.))
+        .map(|pair| ((pair[0].0 / pair[1].0).ln(), (pair[0].1 / pair[1].1).ln()))
+        .collect();
+
+    if returns.len() < (window / 2).max(3) {
+        return None;
+    }
+
+    let n = returns.len() as f64;
+    let mean_a = returns.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_b = returns.iter().map(|(_, b)| b).sum::<f64>() / n;
+
+    let (covariance, variance_a, variance_b) = returns.iter().fold(
+        (0., 0., 0.),
+        |(covariance, variance_a, variance_b), (a, b)| {
+            (
+                covariance + (a - mean_a) * (b - mean_b),
+                variance_a + (a - mean_a).powi(2),
+                variance_b + (b - mean_b).powi(2),
+            )
+        },
+    );
+
+    match variance_a > 0. && variance_b > 0. {
+        true => Some((covariance / (variance_a * variance_b).sqrt()).clamp(-1., 1.)),
+        false => None,
+    }
+}
+
+/// Agglomerative clustering with average linkage over `1 - correlation`, merging
+/// while the closest clusters are correlated above `threshold`. Unknown correlations
+/// count as uncorrelated. Only clusters of two or more symbols are returned.
+pub fn clusters(symbols: &[String], matrix: &[Vec<Option<f64>>], threshold: f64) -> Vec<Cluster> {
+    let len = symbols.len();
+    let mut members: Vec<Vec<usize>> = (0..len).map(|index| vec![index]).collect();
+    let mut distances: Vec<Vec<f64>> = matrix
+        .iter()
+        .map(|row| row.iter().map(|value| 1. - value.unwrap_or(0.)).collect())
+        .collect();
+    let mut active: Vec<bool> = vec![true; len];
+
+    loop {
+        let mut closest: Option<(usize, usize, f64)> = None;
+        for a in (0..len).filter(|a| active[*a]) {
+            for b in ((a + 1)..len).filter(|b| active[*b]) {
+                match closest {
+                    Some((_, _, distance)) if distance <= distances[a][b] => {}
+                    _ => closest = Some((a, b, distances[a][b])),
+                }
+            }
+        }
+
+        let (a, b) = match closest {
+            Some((a, b, distance)) if distance <= 1. - threshold => (a, b),
+            _ => break,
+        };
+
+        let (size_a, size_b) = (members[a].len() as f64, members[b].len() as f64);
+        for other in (0..len).filter(|other| active[*other] && *other != a && *other != b) {
+            let distance =
+                (size_a * distances[a][other] + size_b * distances[b][other]) / (size_a + size_b);
+            distances[a][other] = distance;
+            distances[other][a] = distance;
+        }
+
+        let merged = std::mem::take(&mut members[b]);
+        members[a].extend(merged);
+        active[b] = false;
+    }
+
+    let mut result: Vec<Cluster> = members
+        .into_iter()
+        .filter(|cluster| cluster.len() > 1)
+        .map(|mut cluster| {
+            cluster.sort_unstable();
+            let pairs: Vec<f64> = cluster
+                .iter()
+                .enumerate()
+                .flat_map(|(position, a)| {
+                    cluster[(position + 1)..]
+                        .iter()
+                        .map(move |b| matrix[*a][*b].unwrap_or(0.))
+                })
+                .collect();
+
+            Cluster {
+                symbols: cluster
+                    .iter()
+                    .map(|index| symbols[*index].clone())
+                    .collect(),
+                avg_correlation: pairs.iter().sum::<f64>() / pairs.len() as f64,
+            }
+        })
+        .collect();
+
+    result.sort_by(|a, b| b.avg_correlation.total_cmp(&a.avg_correlation));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_algo_shared::models::time_frame::TimeFrame;
+
+    fn params() -> CorrelationParams {
+        CorrelationParams {
+            enabled: true,
+            time_frame: String::from("D"),
+            window: 20,
+            cluster_threshold: 0.7,
+            store_floor: 0.5,
+            rolling_points: 5,
+        }
+    }
+
+    fn series(symbol: &str, from: i64, closes: &[f64]) -> PriceSeries {
+        PriceSeries {
+            symbol: symbol.to_owned(),
+            bars: (from..from + closes.len() as i64).collect(),
+            closes: closes.to_vec(),
+        }
+    }
+
+    fn walk(len: usize, seed: u64) -> Vec<f64> {
+        let mut state = seed;
+        let mut price = 100.;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                price *= 1. + ((state >> 33) as f64 / (1u64 << 31) as f64 - 0.5) * 0.04;
+                price
+            })
+            .collect()
+    }
+
+    fn scaled(closes: &[f64], factor: f64) -> Vec<f64> {
+        closes.iter().map(|close| close * factor).collect()
+    }
+
+    fn inverse(closes: &[f64]) -> Vec<f64> {
+        closes.iter().map(|close| 10000. / close).collect()
+    }
+
+    #[test]
+    fn correlates_returns() {
+        let closes = walk(40, 1);
+        let a = series("EURUSD", 0, &closes);
+
+        let same = correlation(&a, &series("EURGBP", 0, &scaled(&closes, 0.8)), 20);
+        assert!((same.unwrap() - 1.).abs() < 1e-9);
+
+        let opposite = correlation(&a, &series("USDEUR", 0, &inverse(&closes)), 20);
+        assert!((opposite.unwrap() + 1.).abs() < 1e-9);
+
+        let other = correlation(&a, &series("BITCOIN", 0, &walk(40, 7)), 20).unwrap();
+        assert!(other.abs() < 0.7);
+    }
+
+    #[test]
+    fn aligns_the_bars_both_series_have() {
+        let closes = walk(40, 1);
+        let a = series("US500", 0, &closes);
+
+        // Every other bar missing, same prices on the bars in common
+        let sparse = PriceSeries {
+            symbol: String::from("BITCOIN"),
+            bars: (0..40).step_by(2).collect(),
+            closes: closes.iter().step_by(2).copied().collect(),
+        };
+        assert!((correlation(&a, &sparse, 10).unwrap() - 1.).abs() < 1e-9);
+        assert_eq!(correlation(&a, &sparse, 40), None);
+
+        let shifted = series("GOLD", 100, &closes);
+        assert_eq!(correlation(&a, &shifted, 20), None);
+    }
+
+    #[test]
+    fn clusters_correlated_symbols() {
+        let fx = walk(60, 1);
+        let crypto = walk(60, 2);
+        let universe = vec![
+            series("EURUSD", 0, &fx),
+            series("BITCOIN", 0, &crypto),
+            series("GBPUSD", 0, &scaled(&fx, 1.2)),
+            series("ETHEREUM", 0, &scaled(&crypto, 0.05)),
+            series("GOLD", 0, &walk(60, 3)),
+            series("USDCHF", 0, &inverse(&fx)),
+        ];
+
+        let correlations = correlations(&universe, &params());
+        assert_eq!(correlations.matrix[0][0], Some(1.));
+        assert!((correlations.matrix[0][5].unwrap() + 1.).abs() < 1e-9);
+        assert_eq!(correlations.matrix[0][5], correlations.matrix[5][0]);
+
+        assert_eq!(correlations.clusters.len(), 2);
+        let symbols: Vec<&Vec<String>> = correlations
+            .clusters
+            .iter()
+            .map(|cluster| &cluster.symbols)
+            .collect();
+        assert!(symbols.contains(&&vec![String::from("EURUSD"), String::from("GBPUSD")]));
+        assert!(symbols.contains(&&vec![String::from("BITCOIN"), String::from("ETHEREUM")]));
+    }
+
+    #[test]
+    fn covers_the_correlation_time_frame_of_every_market() {
+        let collector = CorrelationCollector::new(params());
+        let markets = vec![Market::Forex, Market::Crypto];
+        let trigger = |time_frame: &str, markets: Vec<Market>| Trigger {
+            time_frame: TimeFrame::new(time_frame),
+            close: chrono::Utc::now(),
+            markets,
+        };
+
+        assert!(collector.covers(&[trigger("D", markets.clone())], &markets));
+        assert!(!collector.covers(&[trigger("D", vec![Market::Crypto])], &markets));
+        assert!(!collector.covers(&[trigger("H1", markets.clone())], &markets));
+        assert!(!collector.covers(&[], &markets));
+    }
+
+    #[test]
+    fn stores_the_pairs_above_the_floor_with_their_rolling_series() {
+        let fx = walk(60, 1);
+        let universe = vec![
+            series("EURUSD", 0, &fx),
+            series("GBPUSD", 0, &scaled(&fx, 1.2)),
+            series("GOLD", 0, &walk(60, 3)),
+        ];
+
+        let correlations = correlations(&universe, &params());
+        let json = serde_json::to_value(&correlations).unwrap();
+        assert!(json.get("matrix").is_none());
+
+        assert_eq!(correlations.pairs.len(), 1);
+        let pair = &correlations.pairs[0];
+        assert_eq!((pair.a.as_str(), pair.b.as_str()), ("EURUSD", "GBPUSD"));
+        assert_eq!(pair.rolling.len(), 5);
+        assert_eq!(*pair.rolling.last().unwrap(), Some(pair.correlation));
+    }
+
+    #[test]
+    fn rolling_correlation_follows_a_regime_change() {
+        let closes = walk(60, 1);
+        let mut other = scaled(&closes[..40], 2.);
+        other.extend(inverse(&closes[40..]).iter().map(|close| close * 200.));
+
+        let rolling = rolling_correlation(
+            &series("EURUSD", 0, &closes),
+            &series("USDCHF", 0, &other),
+            10,
+            30,
+        );
+        assert_eq!(rolling.len(), 30);
+        assert!((rolling[0].unwrap() - 1.).abs() < 1e-9);
+        assert!((rolling[29].unwrap() + 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn average_linkage_needs_the_whole_group_correlated() {
+        let symbols: Vec<String> = ["A", "B", "C"].iter().map(|s| s.to_string()).collect();
+        let matrix = vec![
+            vec![Some(1.), Some(0.9), Some(0.75)],
+            vec![Some(0.9), Some(1.), Some(0.3)],
+            vec![Some(0.75), Some(0.3), Some(1.)],
+        ];
+
+        let groups = clusters(&symbols, &matrix, 0.7);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].symbols, vec!["A", "B"]);
+        assert!((groups[0].avg_correlation - 0.9).abs() < 1e-9);
+
+        let groups = clusters(&symbols, &matrix, 0.5);
+        assert_eq!(groups[0].symbols, vec!["A", "B", "C"]);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::error::Result;
+
+use rs_algo_shared::models::market::*;
+use rs_algo_shared::models::time_frame::TimeFrameType;
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+const DAY: i64 = 86400;
+const WEEK: i64 = 7 * DAY;
+const WEEKEND: i64 = 2 * DAY;
+// 1970-01-05, first Monday after the epoch. Weekly bars close on Mondays.
+const MONDAY: i64 = 4 * DAY;
+// Time frames from this one on close at the start of every month
+const MONTH_MINUTES: i64 = 43200;
+
+#[derive(Debug, Clone)]
+pub struct DaemonParams {
+    pub enabled: bool,
+    /// Wait after each close so the broker has the finished bar.
+    pub delay: Duration,
+}
+
+impl DaemonParams {
+    pub fn from_env() -> Self {
+        let enabled = env::var("SCANNER_DAEMON").unwrap().parse::<bool>().unwrap();
+
+        let delay = env::var("SCANNER_DAEMON_DELAY")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+
+        Self {
+            enabled,
+            delay: Duration::from_secs(delay),
+        }
+    }
+}
+
+/// Time frame to scan after a bar close, with the markets that traded in that bar.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub time_frame: TimeFrameType,
+    pub close: DateTime<Utc>,
+    pub markets: Vec<Market>,
+}
+
+impl Trigger {
+    /// One-shot scan of every market.
+    pub fn now(time_frame: &TimeFrameType) -> Self {
+        Self {
+            time_frame: time_frame.to_owned(),
+            close: Utc::now(),
+            markets: vec![Market::Stock, Market::Forex, Market::Crypto],
+        }
+    }
+}
+
+/// Keeps the scanner running, scanning each time frame shortly after its bars close.
+/// Scans run one after the other: closes that happen while a scan is running are
+/// coalesced into the next one.
+#[derive(Debug)]
+pub struct Daemon {
+    params: DaemonParams,
+    time_frames: Vec<TimeFrameType>,
+    markets: Vec<Market>,
+    last_closes: Vec<DateTime<Utc>>,
+}
+
+impl Daemon {
+    pub fn new(
+        params: DaemonParams,
+        time_frames: Vec<TimeFrameType>,
+        markets: Vec<Market>,
+    ) -> Self {
+        let at = Utc::now() - delay(&params);
+        let last_closes = time_frames
+            .iter()
+            .map(|time_frame| last_close(time_frame.to_number(), at))
+            .collect();
+
+        Self {
+            params,
+            time_frames,
+            markets,
+            last_closes,
+        }
+    }
+
+    pub async fn run<F, T>(&mut self, mut scan: F) -> Result<()>
+    where
+        F: FnMut(Vec<Trigger>) -> T,
+        T: Future<Output = Result<()>>,
+    {
+        let mut shutdown = shutdown_signal();
+
+        log::info!(
+            "[DAEMON] Scanning {:?} closes of {:?} markets, {:?} after the close",
+            self.time_frames
+                .iter()
+                .map(|time_frame| time_frame.to_string())
+                .collect::<Vec<String>>(),
+            self.markets,
+            self.params.delay
+        );
+
+        loop {
+            let triggers = self.due(Utc::now());
+
+            if triggers.is_empty() {
+                let now = Utc::now();
+                let wake = self.next_wake(now);
+                log::info!("[DAEMON] Next scan at {}", wake);
+
+                let wait = (wake - now).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => continue,
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            for trigger in triggers.iter() {
+                log::info!(
+                    "[DAEMON] {} bar closed at {}. Markets {:?}",
+                    trigger.time_frame,
+                    trigger.close,
+                    trigger.markets
+                );
+            }
+
+            // A scan is never interrupted, a shutdown requested meanwhile waits for it
+            if let Err(err) = scan(triggers).await {
+                log::error!("[DAEMON] Scan failed: {}", err);
+            }
+
+            if *shutdown.borrow() {
+                break;
+            }
+        }
+
+        log::info!("[DAEMON] Stopped");
+        Ok(())
+    }
+
+    /// Time frames with a close since the last scan. Closes of bars where none of the
+    /// markets traded are skipped.
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<Trigger> {
+        let at = now - delay(&self.params);
+        let mut triggers = vec![];
+
+        for (time_frame, last) in self.time_frames.iter().zip(self.last_closes.iter_mut()) {
+            let minutes = time_frame.to_number();
+            let close = last_close(minutes, at);
+            if close <= *last {
+                continue;
+            }
+
+            *last = close;
+            let open = bar_open(minutes, close);
+            let markets: Vec<Market> = self
+                .markets
+                .iter()
+                .filter(|market| in_session(market, open, close))
+                .cloned()
+                .collect();
+
+            if !markets.is_empty() {
+                triggers.push(Trigger {
+                    time_frame: time_frame.to_owned(),
+                    close,
+                    markets,
+                });
+            }
+        }
+
+        triggers
+    }
+
+    pub fn next_wake(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let delay = delay(&self.params);
+        self.time_frames
+            .iter()
+            .map(|time_frame| next_close(time_frame.to_number(), now - delay) + delay)
+            .min()
+            .unwrap_or(now + chrono::Duration::seconds(DAY))
+    }
+}
+
+fn delay(params: &DaemonParams) -> chrono::Duration {
+    chrono::Duration::from_std(params.delay).unwrap()
+}
+
+/// Latest bar close at or before `at`.
+pub fn last_close(minutes: i64, at: DateTime<Utc>) -> DateTime<Utc> {
+    if minutes >= MONTH_MINUTES {
+        return month_start(at.year(), at.month());
+    }
+
+    let (period, offset) = period(minutes);
+    timestamp(offset + (at.timestamp() - offset).div_euclid(period) * period)
+}
+
+/// First bar close after `at`.
+pub fn next_close(minutes: i64, at: DateTime<Utc>) -> DateTime<Utc> {
+    if minutes >= MONTH_MINUTES {
+        return match at.month() {
+            12 => month_start(at.year() + 1, 1),
+            month => month_start(at.year(), month + 1),
+        };
+    }
+
+    let (period, _offset) = period(minutes);
+    timestamp(last_close(minutes, at).timestamp() + period)
+}
+
+pub fn bar_open(minutes: i64, close: DateTime<Utc>) -> DateTime<Utc> {
+    if minutes >= MONTH_MINUTES {
+        return match close.month() {
+            1 => month_start(close.year() - 1, 12),
+            month => month_start(close.year(), month - 1),
+        };
+    }
+
+    timestamp(close.timestamp() - period(minutes).0)
+}
+
+/// Whether the market traded at some point of the bar. Crypto trades 24/7, forex
+/// from Sunday 22:00 to Friday 22:00 UTC and stocks on weekdays.
+pub fn in_session(market: &Market, open: DateTime<Utc>, close: DateTime<Utc>) -> bool {
+    let weekend_start = match market {
+        Market::Crypto => return true,
+        Market::Forex => 4 * DAY + 22 * 3600,
+        _ => 5 * DAY,
+    };
+
+    let from_monday = (open.timestamp() - MONDAY).rem_euclid(WEEK);
+    let length = close.timestamp() - open.timestamp();
+
+    !(from_monday >= weekend_start && from_monday + length <= weekend_start + WEEKEND)
+}
+
+fn period(minutes: i64) -> (i64, i64) {
+    let period = minutes.max(1) * 60;
+    match period >= WEEK {
+        true => (period, MONDAY),
+        false => (period, 0),
+    }
+}
+
+fn timestamp(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(seconds, 0).unwrap()
+}
+
+fn month_start(year: i32, month: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap()
+}
+
+/// Flips to true on SIGINT or SIGTERM.
+pub fn shutdown_signal() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+
+    tokio::spawn(async move {
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+
+        log::info!("[DAEMON] Shutdown requested");
+        let _ = sender.send(true);
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_algo_shared::models::time_frame::TimeFrame;
+
+    fn date(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn daemon(time_frames: &[&str], markets: Vec<Market>, now: DateTime<Utc>) -> Daemon {
+        let params = DaemonParams {
+            enabled: true,
+            delay: Duration::from_secs(60),
+        };
+        let time_frames: Vec<TimeFrameType> = time_frames
+            .iter()
+            .map(|time_frame| TimeFrame::new(time_frame))
+            .collect();
+        let last_closes = time_frames
+            .iter()
+            .map(|time_frame| last_close(time_frame.to_number(), now - delay(&params)))
+            .collect();
+
+        Daemon {
+            params,
+            time_frames,
+            markets,
+            last_closes,
+        }
+    }
+
+    #[test]
+    fn bar_closes() {
+        // Wednesday
+        let at = date(2023, 9, 13, 10, 17);
+        assert_eq!(next_close(240, at), date(2023, 9, 13, 12, 0));
+        assert_eq!(last_close(1440, at), date(2023, 9, 13, 0, 0));
+        assert_eq!(next_close(1440, at), date(2023, 9, 14, 0, 0));
+        assert_eq!(next_close(10080, at), date(2023, 9, 18, 0, 0));
+        assert_eq!(
+            bar_open(10080, date(2023, 9, 18, 0, 0)),
+            date(2023, 9, 11, 0, 0)
+        );
+        assert_eq!(
+            next_close(43200, date(2023, 12, 5, 0, 0)),
+            date(2024, 1, 1, 0, 0)
+        );
+        assert_eq!(
+            bar_open(43200, date(2024, 1, 1, 0, 0)),
+            date(2023, 12, 1, 0, 0)
+        );
+        assert_eq!(
+            last_close(240, date(2023, 9, 13, 12, 0)),
+            date(2023, 9, 13, 12, 0)
+        );
+    }
+
+    #[test]
+    fn market_sessions() {
+        // Saturday and Sunday daily bars
+        let saturday = (date(2023, 9, 16, 0, 0), date(2023, 9, 17, 0, 0));
+        let sunday = (date(2023, 9, 17, 0, 0), date(2023, 9, 18, 0, 0));
+
+        assert!(in_session(&Market::Crypto, saturday.0, saturday.1));
+        assert!(!in_session(&Market::Forex, saturday.0, saturday.1));
+        assert!(in_session(&Market::Forex, sunday.0, sunday.1));
+        assert!(!in_session(&Market::Stock, sunday.0, sunday.1));
+
+        // Forex closes on Friday at 22:00 and opens on Sunday at 22:00
+        assert!(in_session(
+            &Market::Forex,
+            date(2023, 9, 15, 21, 0),
+            date(2023, 9, 15, 22, 0)
+        ));
+        assert!(!in_session(
+            &Market::Forex,
+            date(2023, 9, 15, 22, 0),
+            date(2023, 9, 15, 23, 0)
+        ));
+        assert!(in_session(
+            &Market::Forex,
+            date(2023, 9, 17, 22, 0),
+            date(2023, 9, 17, 23, 0)
+        ));
+        assert!(in_session(
+            &Market::Stock,
+            date(2023, 9, 11, 0, 0),
+            date(2023, 9, 18, 0, 0)
+        ));
+    }
+
+    #[test]
+    fn triggers_once_per_close() {
+        let mut daemon = daemon(
+            &["H4", "D"],
+            vec![Market::Stock, Market::Crypto],
+            date(2023, 9, 13, 10, 17),
+        );
+
+        assert!(daemon.due(date(2023, 9, 13, 11, 59)).is_empty());
+        assert_eq!(
+            daemon.next_wake(date(2023, 9, 13, 11, 59)),
+            date(2023, 9, 13, 12, 1)
+        );
+        assert!(daemon.due(date(2023, 9, 13, 12, 0)).is_empty());
+
+        let triggers = daemon.due(date(2023, 9, 13, 12, 1));
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].time_frame.to_number(), 240);
+        assert!(daemon.due(date(2023, 9, 13, 12, 2)).is_empty());
+
+        // Closes missed while scanning are coalesced
+        let triggers = daemon.due(date(2023, 9, 14, 5, 0));
+        assert_eq!(triggers.len(), 2);
+        assert_eq!(triggers[0].close, date(2023, 9, 14, 4, 0));
+        assert_eq!(triggers[1].close, date(2023, 9, 14, 0, 0));
+    }
+
+    #[test]
+    fn weekend_closes_only_scan_crypto() {
+        let mut daemon = daemon(
+            &["D"],
+            vec![Market::Stock, Market::Forex],
+            date(2023, 9, 16, 12, 0),
+        );
+        assert!(daemon.due(date(2023, 9, 17, 0, 1)).is_empty());
+
+        let mut daemon = daemon(
+            &["D"],
+            vec![Market::Stock, Market::Crypto],
+            date(2023, 9, 16, 12, 0),
+        );
+        let triggers = daemon.due(date(2023, 9, 17, 0, 1));
+        assert_eq!(triggers[0].markets, vec![Market::Crypto]);
+    }
+}
@@ -1,4 +1,5 @@
 use std::fmt::{self, Display};
+use std::path::Path;
 use thiserror::Error;
 
 pub type Result<T> = ::anyhow::Result<T, RsAlgoError>;
@@ -13,27 +14,81 @@ pub enum RsAlgoErrorKind {
     InvalidPeak,
     #[error("Error on Request!")]
     RequestError,
+    #[error("Broker Error!")]
+    BrokerError,
+    #[error("IO Error!")]
+    IoError,
 }
 
 #[derive(Debug, Error)]
 pub struct RsAlgoError {
     pub err: RsAlgoErrorKind,
+    pub context: Option<String>,
 }
 
 impl RsAlgoError {
+    pub fn new(kind: RsAlgoErrorKind, context: &str) -> RsAlgoError {
+        RsAlgoError {
+            err: kind,
+            context: Some(context.to_owned()),
+        }
+    }
+
     pub fn kind(&self) -> RsAlgoErrorKind {
         self.err
     }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.err,
+            RsAlgoErrorKind::RequestError | RsAlgoErrorKind::BrokerError
+        )
+    }
+}
+
+impl RsAlgoError {
+    /// Keeps the kinds of the broker errors that retrying can't fix.
+    pub fn from_broker(err: &rs_algo_shared::error::RsAlgoError, context: &str) -> RsAlgoError {
+        use rs_algo_shared::error::RsAlgoErrorKind as BrokerErrorKind;
+
+        let kind = match err.err {
+            BrokerErrorKind::WrongInstrumentConf => RsAlgoErrorKind::WrongInstrumentConf,
+            BrokerErrorKind::InvalidCandle => RsAlgoErrorKind::InvalidCandle,
+            _ => RsAlgoErrorKind::BrokerError,
+        };
+
+        RsAlgoError::new(kind, &[context, &err.to_string()].concat())
+    }
 }
 
 impl From<RsAlgoErrorKind> for RsAlgoError {
     fn from(kind: RsAlgoErrorKind) -> RsAlgoError {
-        RsAlgoError { err: kind }
+        RsAlgoError {
+            err: kind,
+            context: None,
+        }
+    }
+}
+
+impl From<rs_algo_synthetic::SyntheticError> for RsAlgoError {
+    fn from(err: rs_algo_synthetic::SyntheticError) -> RsAlgoError {
+        RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
     }
 }
 
 impl Display for RsAlgoError {
     fn fmt(&self, err: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Display::fmt(&self.err, err)
+        match &self.context {
+            Some(context) => write!(err, "{} {}", self.err, context),
+            None => Display::fmt(&self.err, err),
+        }
     }
 }
+
+/// Local file errors aren't retried.
+pub fn io_error(path: &Path, err: std::io::Error) -> RsAlgoError {
+    RsAlgoError::new(
+        RsAlgoErrorKind::IoError,
+        &[&path.to_string_lossy(), ": ", &err.to_string()].concat(),
+    )
+}
@@ -1,15 +1,16 @@
 use crate::error::Result;
 use find_peaks::PeakFinder;
 use std::cmp::Ordering;
-use std::env;
 
+/// Peaks of `x_values` with their `y_values`. Log prices are converted back
+/// to prices when `logarithmic` is set.
 pub fn maxima_minima(
     x_values: &Vec<f64>,
     y_values: &Vec<f64>,
     min_prominence: f64,
     min_distance: usize,
+    logarithmic: bool,
 ) -> Result<Vec<(usize, f64)>> {
-    let logarithmic = env::var("LOGARITHMIC_SCANNER").unwrap().parse::<bool>().unwrap();
     let result: Vec<(usize, f64)> = PeakFinder::new(x_values)
         .with_min_prominence(min_prominence)
         .with_min_distance(min_distance)
@@ -77,3 +78,26 @@ where
         Ordering::Equal
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_peaks_in_prices() {
+        let values = vec![10., 30., 10., 50., 10.];
+        let mut peaks = maxima_minima(&values, &values, 1., 1, false).unwrap();
+        peaks.sort_by_key(|peak| peak.0);
+        assert_eq!(peaks, vec![(1, 30.), (3, 50.)]);
+    }
+
+    #[test]
+    fn converts_log_prices_back() {
+        let values: Vec<f64> = [10., 30., 10., 50., 10.].iter().map(|x: &f64| x.ln()).collect();
+        let mut peaks = maxima_minima(&values, &values, 0.1, 1, true).unwrap();
+        peaks.sort_by_key(|peak| peak.0);
+        assert_eq!(peaks.len(), 2);
+        assert!((peaks[0].1 - 30.).abs() < 1e-9);
+        assert!((peaks[1].1 - 50.).abs() < 1e-9);
+    }
+}
@@ -1,5 +1,3 @@
-use std::env;
-
 fn gauss_const(h: f64) -> f64 {
     let pi = std::f64::consts::PI * 2.;
     1. / (h * pi.sqrt())
@@ -11,31 +9,222 @@ fn gauss_exp(x: f64, y: f64, h: f64) -> f64 {
     num / den
 }
 
-fn kernel_function_vec(h: f64, y: f64, x: &Vec<f64>, logarithmic: bool) -> Vec<f64> {
-    x.iter().map(|x| kernel_function(h, y, *x, logarithmic)).collect()
+fn kernel_function(h: f64, x: f64, y: f64) -> f64 {
+    gauss_const(h) * gauss_exp(x, y, h).exp()
 }
 
-fn kernel_function(h: f64, x: f64, y: f64, logarithmic: bool) -> f64 {
+/// Nadaraya-Watson estimate at `x`. Weights are normalized in the same pass.
+pub fn kernel_regression(bandwidth: f64, x: f64, data: &[f64]) -> f64 {
+    let (weighted_sum, kernel_sum) = data.iter().fold((0., 0.), |(weighted_sum, kernel_sum), y| {
+        let k = kernel_function(bandwidth, x, *y);
+        (weighted_sum + k * y, kernel_sum + k)
+    });
+    weighted_sum / kernel_sum
+}
 
-    let gauss_exp = match logarithmic {
-        true => gauss_exp(x, y, h).exp(),
-        false => gauss_exp(x, y, h)
-    };
-    gauss_const(h) * gauss_exp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoother {
+    Kernel {
+        bandwidth: f64,
+    },
+    Loess,
+    Kalman {
+        process_noise: f64,
+        measurement_noise: f64,
+    },
 }
 
-fn weights(bandwidth: f64, x: f64, data: &Vec<f64>, logarithmic: bool) -> Vec<f64> {
-    let mut w_row: Vec<f64> = vec![];
-    let kernel_sum: f64 = kernel_function_vec(bandwidth, x, data, logarithmic).iter().sum();
-    for x_i in data {
-        let ki = kernel_function(bandwidth, x, *x_i, logarithmic);
-        w_row.push(ki / kernel_sum);
+/// Causal smoothing: every value only depends on the previous `window` values
+/// (the whole past for the Kalman filter), so the cost is linear in the series length.
+pub fn smooth(smoother: &Smoother, data: &[f64], window: usize) -> Vec<f64> {
+    let window = window.max(1);
+    match *smoother {
+        Smoother::Kernel { bandwidth } => (0..data.len())
+            .map(|index| {
+                let from = (index + 1).saturating_sub(window);
+                kernel_regression(bandwidth, data[index], &data[from..=index])
+            })
+            .collect(),
+        Smoother::Loess => (0..data.len())
+            .map(|index| loess(&data[(index + 1).saturating_sub(window)..=index]))
+            .collect(),
+        Smoother::Kalman {
+            process_noise,
+            measurement_noise,
+        } => kalman(data, process_noise, measurement_noise),
+    }
+}
+
+/// Local linear fit with tricube weights evaluated at the last value.
+fn loess(data: &[f64]) -> f64 {
+    let last = data.len() - 1;
+    let span = data.len() as f64;
+    let (mut sw, mut swx, mut swy, mut swxx, mut swxy) = (0., 0., 0., 0., 0.);
+
+    for (index, y) in data.iter().enumerate() {
+        let x = index as f64 - last as f64;
+        let w = (1. - (x.abs() / span).powi(3)).powi(3);
+        sw += w;
+        swx += w * x;
+        swy += w * y;
+        swxx += w * x * x;
+        swxy += w * x * y;
+    }
+
+    let den = sw * swxx - swx * swx;
+    match den.abs() > f64::EPSILON {
+        true => (swy * swxx - swx * swxy) / den,
+        false => swy / sw,
     }
-    w_row
 }
 
-pub fn kernel_regression(bandwidth: f64, x: f64, data: &Vec<f64>) -> f64 {
-    let logarithmic = env::var("LOGARITHMIC_SCANNER").unwrap().parse::<bool>().unwrap();
-    let w = weights(bandwidth, x, data, logarithmic);
-    data.iter().zip(w.iter()).map(|(a, b)| (a * b)).sum()
+/// Local level model: the level follows a random walk with `process_noise`
+/// variance and every value is measured with `measurement_noise` variance.
+fn kalman(data: &[f64], process_noise: f64, measurement_noise: f64) -> Vec<f64> {
+    let mut level = match data.first() {
+        Some(first) => *first,
+        None => return vec![],
+    };
+    let mut variance = measurement_noise;
+
+    data.iter()
+        .map(|value| {
+            variance += process_noise;
+            let gain = variance / (variance + measurement_noise);
+            level += gain * (value - level);
+            variance *= 1. - gain;
+            level
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|index| {
+                let x = index as f64;
+                (100. + x * 0.05 + (x / 7.).sin() * 3. + (x * 1.3).cos()).ln()
+            })
+            .collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    /// The previous O(n²) implementation, with the Gaussian kernel on both scales:
+    /// every value regressed over the whole past, normalizing the weights in a second pass.
+    fn full_series(bandwidth: f64, data: &[f64]) -> Vec<f64> {
+        (0..data.len())
+            .map(|index| {
+                let past = &data[..=index];
+                let kernels: Vec<f64> = past
+                    .iter()
+                    .map(|y| (-0.5 * ((y - data[index]) / bandwidth).powi(2)).exp())
+                    .collect();
+                let kernel_sum: f64 = kernels.iter().sum();
+                past.iter()
+                    .zip(kernels.iter())
+                    .map(|(y, k)| y * k / kernel_sum)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Log and raw prices with the bandwidths the deployments use.
+    fn cases() -> Vec<(f64, Vec<f64>)> {
+        let log_prices = series(300);
+        let prices: Vec<f64> = log_prices.iter().map(|price| price.exp()).collect();
+        vec![(0.05, log_prices), (0.05, prices.clone()), (1., prices)]
+    }
+
+    #[test]
+    fn kernel_smoothing_matches_the_full_series_implementation() {
+        for (bandwidth, data) in cases() {
+            let smoothed = smooth(&Smoother::Kernel { bandwidth }, &data, data.len());
+            let expected = full_series(bandwidth, &data);
+            for (value, expected) in smoothed.iter().zip(expected.iter()) {
+                assert!(close(*value, *expected), "{} {}", value, expected);
+            }
+        }
+    }
+
+    /// The estimate is a weighted mean of the values around each one, so dropping
+    /// the older bars moves it by less than the bandwidth.
+    #[test]
+    fn windowed_kernel_stays_within_the_bandwidth_of_the_full_series() {
+        for (bandwidth, data) in cases() {
+            let smoothed = smooth(&Smoother::Kernel { bandwidth }, &data, 50);
+            let expected = full_series(bandwidth, &data);
+            for (value, expected) in smoothed.iter().zip(expected.iter()) {
+                assert!(
+                    (value - expected).abs() < bandwidth,
+                    "{} {}",
+                    value,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn smoothing_does_not_depend_on_later_bars() {
+        let data = series(300);
+        let smoothers = [
+            Smoother::Kernel { bandwidth: 0.05 },
+            Smoother::Loess,
+            Smoother::Kalman {
+                process_noise: 0.01,
+                measurement_noise: 1.,
+            },
+        ];
+        for smoother in smoothers.iter() {
+            let past = smooth(smoother, &data[..200], 20);
+            let full = smooth(smoother, &data, 20);
+            assert!(past
+                .iter()
+                .zip(full.iter())
+                .all(|(past, full)| close(*past, *full)));
+        }
+    }
+
+    #[test]
+    fn loess_follows_lines_exactly() {
+        let data: Vec<f64> = (0..50).map(|index| 2. * index as f64 + 1.).collect();
+        let smoothed = smooth(&Smoother::Loess, &data, 10);
+        for (value, expected) in smoothed.iter().zip(data.iter()) {
+            assert!((value - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn kalman_converges_to_a_new_level() {
+        let mut data = vec![10.; 20];
+        data.extend(vec![20.; 50]);
+        let smoother = Smoother::Kalman {
+            process_noise: 0.1,
+            measurement_noise: 1.,
+        };
+        let smoothed = smooth(&smoother, &data, 1);
+        assert!((smoothed[19] - 10.).abs() < 1e-9);
+        assert!(smoothed[20] > 10. && smoothed[20] < 20.);
+        assert!((smoothed[69] - 20.).abs() < 0.01);
+    }
+
+    #[test]
+    fn symmetric_window_regresses_to_the_center() {
+        let data = vec![1., 2., 3.];
+        assert!((kernel_regression(1., 2., &data) - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn far_values_have_no_weight() {
+        let data = vec![4.6, 4.61, 4.62, 9.];
+        assert!((kernel_regression(0.05, 4.61, &data) - 4.61).abs() < 1e-6);
+        let data = vec![100., 101., 102., 300.];
+        assert!((kernel_regression(1., 101., &data) - 101.).abs() < 1e-6);
+    }
 }
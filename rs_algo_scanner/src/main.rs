@@ -1,168 +1,312 @@
 use crate::error::Result;
-use error::RsAlgoErrorKind;
+use analysis::{AnalysisContext, ScannedInstrument};
+use brokers::file::FileBroker;
+use brokers::synthetic::SyntheticBroker;
+use correlation::{CorrelationCollector, CorrelationParams};
+use daemon::{Daemon, DaemonParams, Trigger};
 use rs_algo_shared::broker::xtb::*;
 use rs_algo_shared::broker::*;
-use rs_algo_shared::helpers::comp::symbol_in_list;
 
+use outbox::Outbox;
+use relative_strength::{RelativeStrengthParams, RelativeStrengthRanker};
 use rs_algo_shared::helpers::date::*;
-use rs_algo_shared::helpers::http::request;
-use rs_algo_shared::helpers::symbols::{crypto, forex, sp500};
+use rs_algo_shared::models::mode;
 use rs_algo_shared::models::mode::ExecutionMode;
 use rs_algo_shared::models::time_frame::*;
-use rs_algo_shared::models::{market::*, mode};
-use rs_algo_shared::scanner::instrument::Instrument;
+use scheduler::{Job, Scheduler};
 use screener::Screener;
-use std::time::Instant;
+use sinks::Sinks;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stream::StreamParams;
+use universe::Universe;
 
+mod analysis;
+mod api;
 mod backend;
+mod brokers;
+mod correlation;
+mod daemon;
 mod error;
 mod helpers;
+mod outbox;
 mod prices;
+mod relative_strength;
+mod report;
+mod retry;
+mod scheduler;
 mod screener;
+mod sinks;
+mod stream;
+mod universe;
 
 use dotenv::dotenv;
-use rs_algo_shared::helpers::http::HttpMethod;
 
 use std::env;
-use std::{thread, time};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
+    let broker = env::var("BROKER").unwrap();
+
+    match broker.as_ref() {
+        "file" => run::<FileBroker>().await,
+        "synthetic" => run::<SyntheticBroker>().await,
+        _ => run::<Xtb>().await,
+    }
+}
+
+async fn run<BK: Broker>() -> Result<()> {
+    let time_frames: Vec<TimeFrameType> = env::var("TIME_FRAMES")
+        .unwrap()
+        .split(',')
+        .map(|time_frame| time_frame.trim())
+        .filter(|time_frame| !time_frame.is_empty())
+        .map(TimeFrame::new)
+        .collect();
+
+    let stream_params = StreamParams::from_env();
+    if stream_params.enabled {
+        return stream::run::<BK>(stream_params, Arc::new(AnalysisContext::from_env())).await;
+    }
+
+    let daemon_params = DaemonParams::from_env();
+
+    match daemon_params.enabled {
+        true => {
+            let markets = Universe::from_env(false)?.markets();
+            Daemon::new(daemon_params, time_frames, markets)
+                .run(scan::<BK>)
+                .await
+        }
+        false => scan::<BK>(time_frames.iter().map(Trigger::now).collect()).await,
+    }
+}
+
+async fn scan<BK: Broker>(triggers: Vec<Trigger>) -> Result<()> {
     let start = Instant::now();
-    let env = env::var("ENV").unwrap();
-    let username = &env::var("BROKER_USERNAME").unwrap();
-    let password = &env::var("BROKER_PASSWORD").unwrap();
-    let sleep_time = &env::var("SLEEP_TIME").unwrap().parse::<u64>().unwrap();
-    let time_frame_str = &env::var("TIME_FRAME").unwrap();
+    let started_at = Local::now();
+    let username = &env::var("BROKER_USERNAME").unwrap_or_default();
+    let password = &env::var("BROKER_PASSWORD").unwrap_or_default();
     let execution_mode = mode::from_str(&env::var("EXECUTION_MODE").unwrap());
 
-    let sleep = time::Duration::from_millis(*sleep_time);
-    let time_frame = TimeFrame::new(time_frame_str);
+    let concurrency = env::var("SCANNER_CONCURRENCY")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+
+    let rate_limit = env::var("BROKER_RATE_LIMIT")
+        .unwrap()
+        .parse::<f64>()
+        .unwrap();
+
+    let rate_burst = env::var("BROKER_RATE_BURST")
+        .unwrap()
+        .parse::<f64>()
+        .unwrap();
 
     let num_bars = match execution_mode {
         mode::ExecutionMode::Scanner => env::var("NUM_BARS").unwrap().parse::<i64>().unwrap(),
         _ => env::var("NUM_TEST_BARS").unwrap().parse::<i64>().unwrap(),
     };
 
-    let time_frame_from = TimeFrame::get_starting_bar(num_bars, &time_frame, &execution_mode); // stupid
+    let time_frames: Vec<TimeFrameType> = triggers
+        .iter()
+        .map(|trigger| trigger.time_frame.to_owned())
+        .collect();
+
+    let time_frames_from: Vec<(&Trigger, i64)> = triggers
+        .iter()
+        .map(|trigger| {
+            let time_frame_from =
+                TimeFrame::get_starting_bar(num_bars, &trigger.time_frame, &execution_mode); // stupid
+            log::info!(
+                "Preparing {} Scanner from {}",
+                trigger.time_frame,
+                time_frame_from
+            );
+            (trigger, time_frame_from.timestamp())
+        })
+        .collect();
+
+    let context = Arc::new(AnalysisContext::from_env());
 
-    log::info!("Preparing Scanner from {}", time_frame_from);
-    let mut screener = Screener::<Xtb>::new().await?;
+    let mut screener = Screener::<BK>::new(context.clone()).await?;
     screener.login(username, password).await?;
-    let mut symbols = screener.get_symbols().await.unwrap().symbols;
+    let symbols = screener.get_symbols().await?.symbols;
 
     let backtest_mode = match execution_mode {
         ExecutionMode::ScannerBackTest => true,
         _ => false,
     };
 
-    if env == "development" {
-        symbols = vec![Symbol {
-            symbol: "BITCOIN".to_owned(),
-            category: "".to_owned(),
-            description: "".to_owned(),
-            currency: "".to_owned(),
-        }]
-    };
+    let universe = Universe::from_env(backtest_mode)?;
+    let markets = universe.markets();
+    let universe = universe.resolve(symbols).await?;
+
+    let incremental_scan = env::var("INCREMENTAL_SCAN")
+        .unwrap()
+        .parse::<bool>()
+        .unwrap();
+
+    let mut last_candles: HashMap<String, HashMap<String, i64>> = HashMap::new();
 
-    let mut market: Market = Market::Stock;
-    let mut sp500_symbols = vec![];
-    let mut forex_symbols = vec![];
-    let mut crypto_symbols = vec![];
-    let mut is_sp500: bool = false;
-    let mut is_forex: bool = false;
-    let mut is_crypto: bool = false;
-
-    if backtest_mode {
-        sp500_symbols = sp500::get_symbols();
-        forex_symbols = forex::get_symbols();
-        crypto_symbols = crypto::get_symbols();
+    if incremental_scan && !backtest_mode {
+        for time_frame in time_frames.iter() {
+            let time_frame = time_frame.to_string();
+            match api::get_last_candles(&time_frame).await {
+                Ok(time_frame_candles) => {
+                    log::info!(
+                        "Incremental {} scan. {} stored instruments",
+                        time_frame,
+                        time_frame_candles.len()
+                    );
+                    last_candles.insert(time_frame, time_frame_candles);
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Can't get stored {} instruments, running a full scan: {}",
+                        time_frame,
+                        err
+                    );
+                }
+            }
+        }
     }
 
-    for s in symbols {
-        let now = Instant::now();
-
-        if backtest_mode {
-            if symbol_in_list(&s.symbol, &sp500_symbols) {
-                is_sp500 = true;
-                is_forex = false;
-                is_crypto = false;
-                market = Market::Stock;
-            } else if symbol_in_list(&s.symbol, &forex_symbols) {
-                is_forex = true;
-                is_sp500 = false;
-                is_crypto = false;
-                market = Market::Forex;
-            } else if symbol_in_list(&s.symbol, &crypto_symbols) {
-                is_crypto = true;
-                is_sp500 = false;
-                is_forex = false;
-                market = Market::Crypto;
-            } else {
-                is_sp500 = false;
-                is_forex = false;
-                is_crypto = false;
+    let mut jobs = vec![];
+    let mut skipped = vec![];
+
+    for (s, market) in universe {
+        for (trigger, start_date) in time_frames_from.iter() {
+            let time_frame = &trigger.time_frame;
+
+            // Markets that didn't trade in the bar that closed
+            if !trigger.markets.contains(&market) {
+                skipped.push((s.symbol.clone(), time_frame.to_string()));
+                continue;
             }
+
+            let last_date = last_candles
+                .get(&time_frame.to_string())
+                .and_then(|candles| candles.get(api::backend_symbol(&s.symbol)))
+                .copied()
+                .filter(|last_date| last_date > start_date);
+
+            // Up to date when a bar opened at or after the last close is already stored
+            let last_close = daemon::last_close(time_frame.to_number(), trigger.close);
+            if matches!(last_date, Some(last_date) if last_date >= last_close.timestamp()) {
+                skipped.push((s.symbol.clone(), time_frame.to_string()));
+                continue;
+            }
+
+            jobs.push(Job {
+                symbol: s.symbol.clone(),
+                market: market.clone(),
+                time_frame: time_frame.to_owned(),
+                start_date: *start_date,
+                last_date,
+            });
+        }
+    }
+
+    let outbox = Arc::new(Outbox::from_env()?);
+    outbox.flush().await;
+
+    let outbox_flush_interval = env::var("OUTBOX_FLUSH_INTERVAL")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+
+    let outbox_flusher = outbox
+        .clone()
+        .spawn_flusher(Duration::from_secs(outbox_flush_interval));
+
+    let sinks = Arc::new(Sinks::from_env(backtest_mode, outbox.clone())?);
+    let scheduler = Scheduler::new(concurrency, rate_limit, rate_burst, context);
+
+    // Ranks are stored on the compact instruments, which only exist in daily mode
+    let mut relative_strength_params = RelativeStrengthParams::from_env();
+    relative_strength_params.enabled &= !backtest_mode;
+    let relative_strength = Arc::new(RelativeStrengthRanker::new(relative_strength_params));
+    let ranker = relative_strength.clone();
+
+    let mut correlation_params = CorrelationParams::from_env();
+    correlation_params.enabled &= !backtest_mode;
+    let correlations = Arc::new(CorrelationCollector::new(correlation_params));
+    let correlator = correlations.clone();
+
+    let mut report = scheduler
+        .run::<BK, _, _>(
+            jobs,
+            (username, password),
+            move |scanned: ScannedInstrument| {
+                let sinks = sinks.clone();
+                ranker.add(&scanned);
+                correlator.add(&scanned);
+                async move {
+                    let instrument = &scanned.instrument;
+                    log::info!(
+                        "{} scanned {} from {} to {}",
+                        &instrument.symbol(),
+                        &instrument.time_frame(),
+                        &instrument.data().first().unwrap().date(),
+                        &instrument.date(),
+                    );
+
+                    sinks.write(&scanned).await
+                }
+            },
+        )
+        .await?;
+
+    for (symbol, time_frame) in skipped.iter() {
+        report.add_skipped(symbol, time_frame);
+    }
+
+    if relative_strength.enabled() && relative_strength.covers(&triggers, &markets) {
+        if let Err(err) = relative_strength.upload(&outbox).await {
+            log::error!("[RELATIVE STRENGTH] Can't upload the ranks: {}", err);
         }
+    }
 
-        if !backtest_mode || (backtest_mode && (is_sp500 || is_forex || is_crypto)) {
-            log::info!("processing {} ...", &s.symbol);
-
-            screener
-                .get_instrument_data(
-                    &s.symbol,
-                    &market,
-                    &time_frame,
-                    time_frame_from.timestamp(),
-                    |instrument: Instrument| async move {
-                        let endpoint = env::var("BACKEND_INSTRUMENTS_ENDPOINT").unwrap().clone();
-                        let time_frame = &env::var("TIME_FRAME").unwrap();
-
-                        log::info!(
-                            "{} scanned {} from {} to {} in {:?}",
-                            &instrument.symbol(),
-                            &time_frame,
-                            &instrument.data().first().unwrap().date(),
-                            &instrument.date(),
-                            now.elapsed(),
-                        );
-
-                        let url = match backtest_mode {
-                            true => [
-                                endpoint.as_ref(),
-                                "?mode=backtest",
-                                "&time_frame=",
-                                time_frame,
-                            ]
-                            .concat(),
-                            false => [endpoint.as_ref(), "?mode=daily", "&time_frame=", time_frame]
-                                .concat(),
-                        };
-
-                        let now = Instant::now();
-                        let res = request(&url, &instrument, HttpMethod::Put)
-                            .await
-                            .map_err(|_e| RsAlgoErrorKind::RequestError)?;
-
-                        log::info!(
-                            "[BACKEND RESPONSE] {:?} status {:?} at {:?} in {:?}",
-                            &instrument.symbol(),
-                            res.status(),
-                            Local::now(),
-                            now.elapsed()
-                        );
-
-                        Ok(())
-                    },
-                )
-                .await?;
-
-            thread::sleep(sleep);
+    if correlations.enabled() && correlations.covers(&triggers, &markets) {
+        if let Err(err) = correlations.upload(&outbox).await {
+            log::error!("[CORRELATION] Can't upload the correlations: {}", err);
         }
     }
+
+    let summary = report.summary(
+        match backtest_mode {
+            true => "backtest",
+            false => "daily",
+        },
+        time_frames
+            .iter()
+            .map(|time_frame| time_frame.to_string())
+            .collect(),
+        started_at,
+        start.elapsed(),
+    );
+
+    if let Err(err) = summary.write() {
+        log::error!("[REPORT] Can't write the summary: {}", err);
+    }
+
+    if let Err(err) = summary.upload(&outbox).await {
+        log::error!("[REPORT] Can't upload the summary: {}", err);
+    }
+
+    outbox_flusher.abort();
+    let (_delivered, pending) = outbox.flush().await;
+    if pending > 0 {
+        log::warn!("[OUTBOX] {} uploads left for the next run", pending);
+    }
+
+    report.log();
     log::info!("[Finished] at {:?}  in {:?}", Local::now(), start.elapsed());
 
     Ok(())
@@ -0,0 +1,416 @@
+use crate::error::{io_error, Result, RsAlgoError, RsAlgoErrorKind};
+
+use rs_algo_shared::helpers::http::{request, HttpMethod};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+const MAX_DELAY: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub key: String,
+    pub url: String,
+    pub attempts: u32,
+    pub last_error: String,
+    pub created_at: i64,
+    pub next_attempt_at: i64,
+    pub body: serde_json::Value,
+}
+
+/// On-disk queue of backend uploads that failed.
+///
+/// Each pending upload is stored as `{key}.json`, so a newer scan of the same
+/// instrument replaces the stale one. Entries are retried with exponential backoff
+/// and moved to `dead_letter.ndjson` after `OUTBOX_MAX_ATTEMPTS` failures.
+#[derive(Debug)]
+pub struct Outbox {
+    path: PathBuf,
+    max_attempts: u32,
+    retry_delay: Duration,
+    /// Guards the entry files.
+    lock: Mutex<()>,
+    flushing: Mutex<()>,
+}
+
+impl Outbox {
+    pub fn from_env() -> Result<Self> {
+        let path = PathBuf::from(env::var("OUTBOX_PATH").unwrap());
+
+        let max_attempts = env::var("OUTBOX_MAX_ATTEMPTS")
+            .unwrap()
+            .parse::<u32>()
+            .unwrap();
+
+        let retry_delay = env::var("OUTBOX_RETRY_DELAY")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+
+        fs::create_dir_all(&path).map_err(|err| io_error(&path, err))?;
+
+        Ok(Self {
+            path,
+            max_attempts: max_attempts.max(1),
+            retry_delay: Duration::from_secs(retry_delay),
+            lock: Mutex::new(()),
+            flushing: Mutex::new(()),
+        })
+    }
+
+    pub async fn push(
+        &self,
+        key: &str,
+        url: &str,
+        body: serde_json::Value,
+        err: &RsAlgoError,
+    ) -> Result<()> {
+        let _lock = self.lock.lock().await;
+        let now = Local::now().timestamp();
+
+        let entry = OutboxEntry {
+            key: key.to_owned(),
+            url: url.to_owned(),
+            attempts: 1,
+            last_error: err.to_string(),
+            created_at: now,
+            next_attempt_at: now + self.delay(1),
+            body,
+        };
+
+        self.write_entry(&entry)?;
+        log::warn!("[OUTBOX] {} queued: {}", key, err);
+        Ok(())
+    }
+
+    /// Drops a pending upload once a newer one has been delivered.
+    pub async fn remove(&self, key: &str) {
+        let _lock = self.lock.lock().await;
+        let entry_path = self.entry_path(key);
+        if entry_path.exists() && fs::remove_file(&entry_path).is_ok() {
+            log::info!("[OUTBOX] {} superseded by a newer upload", key);
+        }
+    }
+
+    /// Retries the due uploads. Returns the number of delivered and pending entries.
+    /// Uploads run without holding the lock, so the sinks can keep queueing meanwhile.
+    pub async fn flush(&self) -> (usize, usize) {
+        let _flushing = self.flushing.lock().await;
+        let now = Local::now().timestamp();
+        let mut delivered = 0;
+        let mut pending = 0;
+
+        let entries = {
+            let _lock = self.lock.lock().await;
+            self.entries()
+        };
+
+        for mut entry in entries {
+            if entry.next_attempt_at > now {
+                pending += 1;
+                continue;
+            }
+
+            let uploaded = upload(&entry.url, &entry.body).await;
+
+            let _lock = self.lock.lock().await;
+            // Pushed again or removed by a newer upload meanwhile
+            if !self.is_current(&entry) {
+                continue;
+            }
+
+            match uploaded {
+                Ok(()) => {
+                    log::info!(
+                        "[OUTBOX] {} delivered after {} attempts",
+                        entry.key,
+                        entry.attempts + 1
+                    );
+                    let _ = fs::remove_file(self.entry_path(&entry.key));
+                    delivered += 1;
+                }
+                Err(err) => {
+                    entry.attempts += 1;
+                    entry.last_error = err.to_string();
+
+                    let result = match entry.attempts >= self.max_attempts {
+                        true => self.dead_letter(&entry),
+                        false => {
+                            entry.next_attempt_at = now + self.delay(entry.attempts);
+                            pending += 1;
+                            self.write_entry(&entry)
+                        }
+                    };
+
+                    if let Err(err) = result {
+                        log::error!("[OUTBOX] Can't update {}: {}", entry.key, err);
+                    }
+                }
+            }
+        }
+
+        if delivered > 0 || pending > 0 {
+            log::info!(
+                "[OUTBOX] {} uploads delivered, {} pending",
+                delivered,
+                pending
+            );
+        }
+
+        (delivered, pending)
+    }
+
+    pub fn spawn_flusher(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                self.flush().await;
+            }
+        })
+    }
+
+    fn delay(&self, attempts: u32) -> i64 {
+        self.retry_delay
+            .saturating_mul(2_u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(MAX_DELAY)
+            .as_secs() as i64
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.path.join([key, ".json"].concat())
+    }
+
+    fn is_current(&self, entry: &OutboxEntry) -> bool {
+        fs::read_to_string(self.entry_path(&entry.key))
+            .ok()
+            .and_then(|content| serde_json::from_str::<OutboxEntry>(&content).ok())
+            .filter(|current| {
+                current.created_at == entry.created_at
+                    && current.attempts == entry.attempts
+                    && current.body == entry.body
+            })
+            .is_some()
+    }
+
+    fn entries(&self) -> Vec<OutboxEntry> {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("[OUTBOX] Can't read {:?}: {}", self.path, err);
+                return vec![];
+            }
+        };
+
+        let mut entries: Vec<OutboxEntry> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"))
+            .filter_map(|path| match fs::read_to_string(&path) {
+                Ok(content) => match serde_json::from_str::<OutboxEntry>(&content) {
+                    Ok(entry) => Some(entry),
+                    Err(err) => {
+                        log::error!("[OUTBOX] Corrupted entry {:?}: {}", path, err);
+                        None
+                    }
+                },
+                Err(_) => None,
+            })
+            .collect();
+
+        entries.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        entries
+    }
+
+    fn write_entry(&self, entry: &OutboxEntry) -> Result<()> {
+        let entry_path = self.entry_path(&entry.key);
+        let tmp_path = self.path.join([&entry.key, ".tmp"].concat());
+        let content = serde_json::to_string(entry).map_err(|err| {
+            RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+        })?;
+
+        fs::write(&tmp_path, content).map_err(|err| io_error(&tmp_path, err))?;
+        fs::rename(&tmp_path, &entry_path).map_err(|err| io_error(&entry_path, err))
+    }
+
+    fn dead_letter(&self, entry: &OutboxEntry) -> Result<()> {
+        let dead_letter_path = self.path.join("dead_letter.ndjson");
+        let mut line = serde_json::to_string(entry).map_err(|err| {
+            RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+        })?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&dead_letter_path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|err| io_error(&dead_letter_path, err))?;
+
+        log::error!(
+            "[OUTBOX] {} moved to the dead letter file after {} attempts: {}",
+            entry.key,
+            entry.attempts,
+            entry.last_error
+        );
+
+        let entry_path = self.entry_path(&entry.key);
+        fs::remove_file(&entry_path).map_err(|err| io_error(&entry_path, err))
+    }
+}
+
+pub async fn upload<T: Serialize>(url: &str, body: &T) -> Result<()> {
+    let res = request(url, body, HttpMethod::Put)
+        .await
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))?;
+
+    match res.status().is_success() {
+        true => Ok(()),
+        false => Err(RsAlgoError::new(
+            RsAlgoErrorKind::RequestError,
+            &["backend responded ", res.status().as_str()].concat(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn outbox(name: &str, max_attempts: u32) -> Outbox {
+        let path = env::temp_dir().join(
+            [
+                "rs_algo_outbox_",
+                name,
+                "_",
+                &std::process::id().to_string(),
+            ]
+            .concat(),
+        );
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Outbox {
+            path,
+            max_attempts,
+            retry_delay: Duration::from_secs(0),
+            lock: Mutex::new(()),
+            flushing: Mutex::new(()),
+        }
+    }
+
+    fn failure() -> RsAlgoError {
+        RsAlgoError::new(RsAlgoErrorKind::RequestError, "backend down")
+    }
+
+    /// Nothing listens on port 1, so the uploads fail right away.
+    const DOWN: &str = "http://127.0.0.1:1/api/instruments";
+
+    /// Backend answering 200 to every request.
+    async fn backend() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = ["http://", &listener.local_addr().unwrap().to_string(), "/"].concat();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _address)) = listener.accept().await {
+                let mut buffer = [0; 4096];
+                let _ = stream.read(&mut buffer).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let mut outbox = outbox("delay", 3);
+        outbox.retry_delay = Duration::from_secs(60);
+        let delays: Vec<i64> = [1, 2, 3, 6, 7, 40, u32::MAX]
+            .into_iter()
+            .map(|attempts| outbox.delay(attempts))
+            .collect();
+        assert_eq!(delays, vec![60, 120, 240, 1920, 3600, 3600, 3600]);
+    }
+
+    #[tokio::test]
+    async fn push_replaces_and_remove_drops_entries() {
+        let outbox = outbox("push", 3);
+        outbox
+            .push(
+                "EURUSD_D",
+                DOWN,
+                serde_json::json!({ "close": 1 }),
+                &failure(),
+            )
+            .await
+            .unwrap();
+        outbox
+            .push(
+                "EURUSD_D",
+                DOWN,
+                serde_json::json!({ "close": 2 }),
+                &failure(),
+            )
+            .await
+            .unwrap();
+
+        let entries = outbox.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].body["close"], 2);
+
+        outbox.remove("EURUSD_D").await;
+        assert!(outbox.entries().is_empty());
+        fs::remove_dir_all(&outbox.path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn flush_delivers_due_entries() {
+        let outbox = outbox("flush", 3);
+        let url = backend().await;
+        outbox
+            .push("EURUSD_D", &url, serde_json::json!({}), &failure())
+            .await
+            .unwrap();
+        outbox
+            .push("GBPUSD_D", DOWN, serde_json::json!({}), &failure())
+            .await
+            .unwrap();
+
+        assert_eq!(outbox.flush().await, (1, 1));
+
+        let entries = outbox.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            (entries[0].key.as_str(), entries[0].attempts),
+            ("GBPUSD_D", 2)
+        );
+        fs::remove_dir_all(&outbox.path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dead_letters_after_max_attempts() {
+        let outbox = outbox("dead_letter", 2);
+        outbox
+            .push("EURUSD_D", DOWN, serde_json::json!({}), &failure())
+            .await
+            .unwrap();
+
+        assert_eq!(outbox.flush().await, (0, 0));
+        assert!(outbox.entries().is_empty());
+
+        let dead_letter = fs::read_to_string(outbox.path.join("dead_letter.ndjson")).unwrap();
+        let entry: OutboxEntry = serde_json::from_str(dead_letter.trim()).unwrap();
+        assert_eq!((entry.key.as_str(), entry.attempts), ("EURUSD_D", 2));
+        fs::remove_dir_all(&outbox.path).unwrap();
+    }
+}
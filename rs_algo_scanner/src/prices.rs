@@ -1,114 +1,188 @@
+use crate::analysis::Bars;
 use crate::helpers::slope_intercept::slope_intercept;
 
 use rs_algo_shared::helpers::comp::percentage_change;
 use rs_algo_shared::helpers::date::*;
-use rs_algo_shared::scanner::candle::Candle;
 use rs_algo_shared::scanner::pattern::{DataPoints, PatternDirection, PatternType};
 
+use serde::Serialize;
 use std::env;
 
-pub type PriceBreak = (bool, usize, f64, DbDateTime);
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum BreakDirection {
+    Up,
+    Down,
+}
+
+/// Which price has to cross the line: the close or the high/low of the bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakRule {
+    Close,
+    Wick,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakConfirmation {
+    None,
+    /// The close has to stay beyond the line for N bars, the break bar included.
+    Bars(usize),
+    /// The break bar volume has to be `factor` times the average of the previous `period` bars.
+    Volume {
+        factor: f64,
+        period: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakoutConfig {
+    pub rule: BreakRule,
+    pub confirmation: BreakConfirmation,
+    pub retest_tolerance: f64,
+}
+
+impl BreakoutConfig {
+    pub fn from_env() -> Self {
+        let rule = match env::var("PRICE_BREAK_RULE").unwrap().as_ref() {
+            "wick" => BreakRule::Wick,
+            _ => BreakRule::Close,
+        };
+
+        let confirmation = match env::var("PRICE_BREAK_CONFIRMATION").unwrap().as_ref() {
+            "bars" => BreakConfirmation::Bars(
+                env::var("PRICE_BREAK_CONFIRMATION_BARS")
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap(),
+            ),
+            "volume" => BreakConfirmation::Volume {
+                factor: env::var("PRICE_BREAK_VOLUME_FACTOR")
+                    .unwrap()
+                    .parse::<f64>()
+                    .unwrap(),
+                period: env::var("PRICE_BREAK_VOLUME_PERIOD")
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap(),
+            },
+            _ => BreakConfirmation::None,
+        };
+
+        let retest_tolerance = env::var("PRICE_BREAK_RETEST_TOLERANCE")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        Self {
+            rule,
+            confirmation,
+            retest_tolerance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PriceBreak {
+    pub index: usize,
+    pub date: DbDateTime,
+    /// Line price at the break bar.
+    pub price: f64,
+    pub confirmed_index: Option<usize>,
+    pub confirmed_date: Option<DbDateTime>,
+    /// Bars that came back to the line after the break without closing through it.
+    pub retests: Vec<usize>,
+    /// First close back on the other side of the line.
+    pub failed_index: Option<usize>,
+    pub failed_date: Option<DbDateTime>,
+}
 
 pub fn price_is_upperupper_band_top(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[2], data[4]];
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[2], data[4]], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_upperupper_band_bottom(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[3], data[5]];
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[3], data[5]], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_lower_low_band_bottom(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[3], data[5]];
-    let bottom_break = |price: f64, price_break: f64| price < price_break;
-    search_price_break(points, candles, &bottom_break)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[3], data[5]], bars, BreakDirection::Down, config)
 }
 
 pub fn price_is_lower_low_band_top(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[2], data[4]];
-    let break_price_comparator = |price: f64, price_break: f64| price < price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[2], data[4]], bars, BreakDirection::Down, config)
 }
 
 pub fn price_is_upperlast_high_top(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[2]];
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[2]], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_upperlast_high_bottom(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[3]];
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[3]], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_lower_last_low_top(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[3]];
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[3]], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_lower_last_low_bottom(
     data: &DataPoints,
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let points = vec![data[2]];
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(points, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[data[2]], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_upperpeak(
     peak: (usize, f64),
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let mut band = vec![];
-    band.push(peak);
-    let break_price_comparator = |price: f64, price_break: f64| price > price_break;
-    search_price_break(band, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[peak], bars, BreakDirection::Up, config)
 }
 
 pub fn price_is_lower_peak(
     peak: (usize, f64),
-    candles: &Vec<Candle>,
+    bars: &Bars,
     _pattern_type: &PatternType,
-) -> PriceBreak {
-    let mut band = vec![];
-    band.push(peak);
-    let break_price_comparator = |price: f64, price_break: f64| price < price_break;
-    search_price_break(band, candles, &break_price_comparator)
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    search_price_break(&[peak], bars, BreakDirection::Down, config)
 }
 
 pub fn calculate_price_change(data_points: &DataPoints) -> f64 {
@@ -130,41 +204,412 @@ pub fn calculate_price_target(
     }
 }
 
-//FIXME UPDATE PATTERN BREAK DETECTION
+/// Searches the first bar after the last point that crosses the line through the
+/// points (a horizontal line for a single point) and follows what happened next:
+/// confirmation, retests of the line and failure. Points and bars must use the same
+/// price scale.
 pub fn search_price_break(
-    points: Vec<(usize, f64)>,
-    candles: &Vec<Candle>,
-    comparator: &dyn Fn(f64, f64) -> bool,
-) -> PriceBreak {
-    let logarithmic = env::var("LOGARITHMIC_SCANNER")
-        .unwrap()
-        .parse::<bool>()
-        .unwrap();
-
-    let len = points.len();
-    if len > 1 {
-        let start = points[0];
-        let end = points[1];
-
-        let start_index = start.0;
-        let end_index = candles.len(); //end.0 as usize;
-
-        let (slope, y_intercept) = slope_intercept(start.0 as f64, start.1, end.0 as f64, end.1);
-        for n in (start_index..=end_index).step_by(2) {
-            if n < end_index {
-                let next_price = (slope * n as f64) + y_intercept;
-                let current_price = match logarithmic {
-                    true => candles[n].close().exp(),
-                    false => candles[n].close(),
-                };
-                let current_date = &candles[n].date();
-
-                if comparator(current_price, next_price) {
-                    return (true, n, next_price, to_dbtime(*current_date));
+    points: &[(usize, f64)],
+    bars: &Bars,
+    direction: BreakDirection,
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    let from_index = points.iter().map(|point| point.0 + 1).max()?;
+    search_breakout(points, from_index, bars, direction, config)
+}
+
+pub fn search_breakout(
+    points: &[(usize, f64)],
+    from_index: usize,
+    bars: &Bars,
+    direction: BreakDirection,
+    config: &BreakoutConfig,
+) -> Option<PriceBreak> {
+    let line = line(points)?;
+    let len = bars.close.len();
+
+    let beyond = |price: f64, index: usize| match direction {
+        BreakDirection::Up => price > line(index),
+        BreakDirection::Down => price < line(index),
+    };
+
+    let index = (from_index..len).find(|index| {
+        let price = match (config.rule, direction) {
+            (BreakRule::Close, _) => bars.close[*index],
+            (BreakRule::Wick, BreakDirection::Up) => bars.high[*index],
+            (BreakRule::Wick, BreakDirection::Down) => bars.low[*index],
+        };
+        beyond(price, *index)
+    })?;
+
+    let mut failed_index = None;
+    let confirmed_index = match config.confirmation {
+        BreakConfirmation::None => Some(index),
+        BreakConfirmation::Bars(num_bars) => {
+            let last = index + num_bars.max(1) - 1;
+            match (index..=last.min(len - 1)).find(|bar| !beyond(bars.close[*bar], *bar)) {
+                Some(bar) => {
+                    failed_index = Some(bar);
+                    None
                 }
+                None if last < len => Some(last),
+                None => None,
+            }
+        }
+        BreakConfirmation::Volume { factor, period } => {
+            let from = index.saturating_sub(period);
+            let previous = &bars.volume[from..index];
+            let average = match previous.is_empty() {
+                true => 0.,
+                false => previous.iter().sum::<f64>() / previous.len() as f64,
+            };
+            match bars.volume[index] >= average * factor {
+                true => Some(index),
+                false => None,
+            }
+        }
+    };
+
+    let mut retests = vec![];
+    if let Some(confirmed_index) = confirmed_index {
+        let mut touching = false;
+        for bar in confirmed_index + 1..len {
+            if !beyond(bars.close[bar], bar) {
+                failed_index = Some(bar);
+                break;
             }
+
+            let tolerance = line(bar).abs() * config.retest_tolerance;
+            let touches = match direction {
+                BreakDirection::Up => bars.low[bar] <= line(bar) + tolerance,
+                BreakDirection::Down => bars.high[bar] >= line(bar) - tolerance,
+            };
+
+            if touches && !touching {
+                retests.push(bar);
+            }
+            touching = touches;
+        }
+    }
+
+    Some(PriceBreak {
+        index,
+        date: bars.dates[index],
+        price: line(index),
+        confirmed_index,
+        confirmed_date: confirmed_index.map(|bar| bars.dates[bar]),
+        retests,
+        failed_index,
+        failed_date: failed_index.map(|bar| bars.dates[bar]),
+    })
+}
+
+fn line(points: &[(usize, f64)]) -> Option<impl Fn(usize) -> f64> {
+    let (slope, y_intercept) = match points {
+        [] => return None,
+        [point] => (0., point.1),
+        [start, end, ..] => slope_intercept(start.0 as f64, start.1, end.0 as f64, end.1),
+    };
+
+    Some(move |index: usize| slope * index as f64 + y_intercept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Swings;
+    use rs_algo_synthetic::{Generator, Shape, ShapeType};
+
+    fn config(confirmation: BreakConfirmation) -> BreakoutConfig {
+        BreakoutConfig {
+            rule: BreakRule::Close,
+            confirmation,
+            retest_tolerance: 0.005,
+        }
+    }
+
+    fn bars(base: f64, points: &[(usize, f64)]) -> Bars {
+        let mut closes = vec![base; 30];
+        for (index, close) in points {
+            closes[*index] = *close;
         }
+        Bars::from_closes(&closes, 0.5)
+    }
+
+    /// Top band at 100 (points 2 and 4) and bottom band at 90 (points 3 and 5).
+    fn band() -> DataPoints {
+        vec![
+            (0, 95.),
+            (2, 95.),
+            (4, 100.),
+            (6, 90.),
+            (8, 100.),
+            (10, 90.),
+        ]
+    }
+
+    fn break_index(price_break: Option<PriceBreak>) -> Option<usize> {
+        price_break.map(|price_break| price_break.index)
+    }
+
+    #[test]
+    fn band_breaks() {
+        let config = config(BreakConfirmation::None);
+        let pattern_type = PatternType::Rectangle;
+        let up = bars(95., &[(15, 101.)]);
+        let down = bars(95., &[(15, 89.)]);
+
+        assert_eq!(
+            break_index(price_is_upperupper_band_top(
+                &band(),
+                &up,
+                &pattern_type,
+                &config
+            )),
+            Some(15)
+        );
+        assert_eq!(
+            break_index(price_is_upperupper_band_bottom(
+                &band(),
+                &up,
+                &pattern_type,
+                &config
+            )),
+            Some(11)
+        );
+        assert_eq!(
+            break_index(price_is_lower_low_band_bottom(
+                &band(),
+                &down,
+                &pattern_type,
+                &config
+            )),
+            Some(15)
+        );
+        assert_eq!(
+            break_index(price_is_lower_low_band_top(
+                &band(),
+                &down,
+                &pattern_type,
+                &config
+            )),
+            Some(9)
+        );
+        assert_eq!(
+            break_index(price_is_upperupper_band_top(
+                &band(),
+                &down,
+                &pattern_type,
+                &config
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn last_point_breaks() {
+        let config = config(BreakConfirmation::None);
+        let pattern_type = PatternType::Rectangle;
+        let up = bars(95., &[(15, 101.)]);
+
+        assert_eq!(
+            break_index(price_is_upperlast_high_top(
+                &band(),
+                &up,
+                &pattern_type,
+                &config
+            )),
+            Some(15)
+        );
+        assert_eq!(
+            break_index(price_is_upperlast_high_bottom(
+                &band(),
+                &up,
+                &pattern_type,
+                &config
+            )),
+            Some(7)
+        );
+        assert_eq!(
+            break_index(price_is_lower_last_low_top(
+                &band(),
+                &up,
+                &pattern_type,
+                &config
+            )),
+            Some(7)
+        );
+        assert_eq!(
+            break_index(price_is_lower_last_low_bottom(
+                &band(),
+                &up,
+                &pattern_type,
+                &config
+            )),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn peak_breaks() {
+        let config = config(BreakConfirmation::None);
+        let pattern_type = PatternType::Rectangle;
+
+        assert_eq!(
+            break_index(price_is_upperpeak(
+                (4, 100.),
+                &bars(95., &[(15, 101.)]),
+                &pattern_type,
+                &config
+            )),
+            Some(15)
+        );
+        assert_eq!(
+            break_index(price_is_lower_peak(
+                (6, 90.),
+                &bars(95., &[(15, 89.)]),
+                &pattern_type,
+                &config
+            )),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn checks_every_bar_after_the_last_point() {
+        let config = config(BreakConfirmation::None);
+        let line = [(4, 100.), (8, 100.)];
+        let bars = bars(95., &[(6, 101.), (13, 101.)]);
+
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.index, 13);
+        assert_eq!(price_break.date, bars.dates[13]);
+        assert!((price_break.price - 100.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn follows_sloped_lines() {
+        let config = config(BreakConfirmation::None);
+        let line = [(0, 90.), (10, 100.)];
+        let bars = bars(95., &[(12, 101.), (20, 111.)]);
+
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.index, 20);
+        assert!((price_break.price - 110.).abs() < 1e-9);
     }
 
-    (false, 0, 0., to_dbtime(Local::now() - Duration::days(1000)))
+    #[test]
+    fn wick_rule_breaks_on_highs_and_lows() {
+        let line = [(4, 100.), (8, 100.)];
+        let bars = bars(95., &[(15, 99.8)]);
+        let mut wick = config(BreakConfirmation::None);
+        wick.rule = BreakRule::Wick;
+
+        let close = config(BreakConfirmation::None);
+        assert!(search_price_break(&line, &bars, BreakDirection::Up, &close).is_none());
+        assert_eq!(
+            break_index(search_price_break(&line, &bars, BreakDirection::Up, &wick)),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn bars_confirmation_fails_when_price_comes_back() {
+        let line = [(4, 100.), (8, 100.)];
+        let bars = bars(95., &[(15, 101.), (16, 99.)]);
+        let config = config(BreakConfirmation::Bars(2));
+
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.index, 15);
+        assert_eq!(price_break.confirmed_index, None);
+        assert_eq!(price_break.failed_index, Some(16));
+    }
+
+    #[test]
+    fn bars_confirmation_is_pending_at_the_end_of_the_data() {
+        let line = [(4, 100.), (8, 100.)];
+        let bars = bars(95., &[(29, 101.)]);
+        let config = config(BreakConfirmation::Bars(2));
+
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.index, 29);
+        assert_eq!(price_break.confirmed_index, None);
+        assert_eq!(price_break.failed_index, None);
+    }
+
+    #[test]
+    fn tracks_retests_and_failed_breaks() {
+        let line = [(4, 100.), (8, 100.)];
+        let bars = bars(
+            95.,
+            &[
+                (15, 101.),
+                (16, 102.),
+                (17, 103.),
+                (18, 100.3),
+                (19, 103.),
+                (20, 99.),
+            ],
+        );
+        let config = config(BreakConfirmation::Bars(2));
+
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.confirmed_index, Some(16));
+        assert_eq!(price_break.confirmed_date, Some(bars.dates[16]));
+        assert_eq!(price_break.retests, vec![18]);
+        assert_eq!(price_break.failed_index, Some(20));
+        assert_eq!(price_break.failed_date, Some(bars.dates[20]));
+    }
+
+    #[test]
+    fn volume_confirmation() {
+        let line = [(4, 100.), (8, 100.)];
+        let config = config(BreakConfirmation::Volume {
+            factor: 1.5,
+            period: 10,
+        });
+
+        let mut bars = bars(95., &[(15, 101.), (16, 101.)]);
+        bars.volume[15] = 1200.;
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.confirmed_index, None);
+
+        bars.volume[15] = 2000.;
+        let price_break = search_price_break(&line, &bars, BreakDirection::Up, &config).unwrap();
+        assert_eq!(price_break.confirmed_index, Some(15));
+        assert_eq!(price_break.failed_index, Some(17));
+    }
+
+    #[test]
+    fn injected_double_bottom_is_broken_up() {
+        let generator = Generator::new(42, "gbm".parse().unwrap())
+            .shape(Shape::new(ShapeType::DoubleBottom, 200));
+        let bars = Bars::from_generator(&generator, 262);
+        let swings = Swings::new(&bars, 0.03, 5, true);
+
+        let bottoms: Vec<(usize, f64)> = swings
+            .lows
+            .iter()
+            .copied()
+            .filter(|low| low.0 > 200 && low.0 <= 260)
+            .collect();
+        assert_eq!(bottoms.len(), 2, "{:?}", swings.lows);
+        assert!((bottoms[0].0 as i64 - 215).abs() <= 2);
+        assert!((bottoms[1].0 as i64 - 245).abs() <= 2);
+
+        // The neckline is the top between the bottoms
+        let neckline: Vec<(usize, f64)> = swings
+            .highs
+            .iter()
+            .copied()
+            .filter(|high| high.0 > bottoms[0].0 && high.0 < bottoms[1].0)
+            .collect();
+        assert_eq!(neckline.len(), 1, "{:?}", swings.highs);
+
+        let config = config(BreakConfirmation::Bars(2));
+        let price_break =
+            search_price_break(&neckline, &bars, BreakDirection::Up, &config).unwrap();
+        assert!(price_break.index > bottoms[1].0 && price_break.index < 260);
+        assert!(price_break.confirmed_index.is_some());
+    }
 }
@@ -0,0 +1,376 @@
+use crate::analysis::ScannedInstrument;
+use crate::api;
+use crate::daemon::Trigger;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::outbox::{self, Outbox};
+
+use rs_algo_shared::models::market::Market;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone)]
+pub struct RelativeStrengthParams {
+    pub enabled: bool,
+    pub benchmark: String,
+    /// Look-back windows in bars.
+    pub windows: Vec<usize>,
+}
+
+impl RelativeStrengthParams {
+    pub fn from_env() -> Self {
+        let enabled = env::var("RELATIVE_STRENGTH")
+            .unwrap()
+            .parse::<bool>()
+            .unwrap();
+
+        let windows = env::var("RELATIVE_STRENGTH_WINDOWS")
+            .unwrap()
+            .split(',')
+            .map(|window| window.trim())
+            .filter(|window| !window.is_empty())
+            .map(|window| window.parse::<usize>().unwrap())
+            .collect();
+
+        Self {
+            enabled,
+            benchmark: env::var("RELATIVE_STRENGTH_BENCHMARK").unwrap(),
+            windows,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelativeStrengthWindow {
+    pub bars: usize,
+    /// Return over the window in percent.
+    pub performance: f64,
+    /// Outperformance of the benchmark in percent, when the benchmark was scanned.
+    pub relative: Option<f64>,
+    /// Percentile of the performance within the market, from 0 to 100.
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelativeStrength {
+    pub symbol: String,
+    pub market: String,
+    pub time_frame: String,
+    pub benchmark: String,
+    /// Mean of the window ranks, used to sort by leadership.
+    pub rank: f64,
+    pub windows: Vec<RelativeStrengthWindow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub symbol: String,
+    pub market: String,
+    pub time_frame: String,
+    pub closes: Vec<f64>,
+}
+
+/// Keeps the closes of every scanned instrument so the universe can be ranked once
+/// the scan is over.
+#[derive(Debug)]
+pub struct RelativeStrengthRanker {
+    params: RelativeStrengthParams,
+    series: Mutex<Vec<Series>>,
+}
+
+impl RelativeStrengthRanker {
+    pub fn new(params: RelativeStrengthParams) -> Self {
+        Self {
+            params,
+            series: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.params.enabled
+    }
+
+    pub fn add(&self, scanned: &ScannedInstrument) {
+        if !self.params.enabled {
+            return;
+        }
+
+        let instrument = &scanned.instrument;
+        self.series.lock().unwrap().push(Series {
+            symbol: api::backend_symbol(instrument.symbol()).to_owned(),
+            market: format!("{:?}", instrument.market),
+            time_frame: instrument.time_frame().to_string(),
+            closes: instrument
+                .data()
+                .iter()
+                .map(|candle| candle.close())
+                .collect(),
+        });
+    }
+
+    /// The upload replaces the stored ranks, so every time frame scanned needs
+    /// every market of the universe to rank against.
+    pub fn covers(&self, triggers: &[Trigger], markets: &[Market]) -> bool {
+        !triggers.is_empty()
+            && triggers.iter().all(|trigger| {
+                markets
+                    .iter()
+                    .all(|market| trigger.markets.contains(market))
+            })
+    }
+
+    pub fn rank(&self) -> Vec<RelativeStrength> {
+        rank(&self.series.lock().unwrap(), &self.params)
+    }
+
+    /// Sends the ranks to the backend, queueing them in the outbox when it's down.
+    pub async fn upload(&self, outbox: &Outbox) -> Result<()> {
+        let ranks = self.rank();
+        let url = [
+            &env::var("BACKEND_INSTRUMENTS_ENDPOINT").unwrap(),
+            "/relative_strength",
+        ]
+        .concat();
+
+        if ranks.is_empty() {
+            log::info!("[RELATIVE STRENGTH] No instruments scanned, nothing to upload");
+            return Ok(());
+        }
+
+        log::info!("[RELATIVE STRENGTH] {} instruments ranked", ranks.len());
+
+        match outbox::upload(&url, &ranks).await {
+            Ok(()) => {
+                outbox.remove("relative_strength").await;
+                Ok(())
+            }
+            Err(err) => {
+                let body = serde_json::to_value(&ranks).map_err(|err| {
+                    RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+                })?;
+                outbox.push("relative_strength", &url, body, &err).await
+            }
+        }
+    }
+}
+
+/// Ranks every instrument against the others of its market and time frame. Dividing
+/// by the benchmark return keeps the order, so the ranks don't depend on the
+/// benchmark being part of the scan.
+pub fn rank(series: &[Series], params: &RelativeStrengthParams) -> Vec<RelativeStrength> {
+    let performances: Vec<Vec<Option<f64>>> = series
+        .iter()
+        .map(|series| {
+            params
+                .windows
+                .iter()
+                .map(|window| performance(&series.closes, *window))
+                .collect()
+        })
+        .collect();
+
+    let mut peers: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+    for (index, series) in series.iter().enumerate() {
+        peers
+            .entry((series.market.as_str(), series.time_frame.as_str()))
+            .or_default()
+            .push(index);
+    }
+
+    series
+        .iter()
+        .enumerate()
+        .map(|(index, current)| {
+            let benchmark = series.iter().position(|other| {
+                other.symbol == params.benchmark && other.time_frame == current.time_frame
+            });
+
+            let windows: Vec<RelativeStrengthWindow> = params
+                .windows
+                .iter()
+                .enumerate()
+                .filter_map(|(window, bars)| {
+                    let performance = performances[index][window]?;
+                    let others: Vec<f64> = peers
+                        [&(current.market.as_str(), current.time_frame.as_str())]
+                        .iter()
+                        .filter(|other| **other != index)
+                        .filter_map(|other| performances[*other][window])
+                        .collect();
+
+                    let rank = match others.is_empty() {
+                        true => 100.,
+                        false => {
+                            let below = others
+                                .iter()
+                                .map(|other| match other.total_cmp(&performance) {
+                                    Ordering::Less => 1.,
+                                    Ordering::Equal => 0.5,
+                                    Ordering::Greater => 0.,
+                                })
+                                .sum::<f64>();
+                            below / others.len() as f64 * 100.
+                        }
+                    };
+
+                    let relative = benchmark
+                        .and_then(|benchmark| performances[benchmark][window])
+                        .map(|benchmark| {
+                            ((1. + performance / 100.) / (1. + benchmark / 100.) - 1.) * 100.
+                        });
+
+                    Some(RelativeStrengthWindow {
+                        bars: *bars,
+                        performance,
+                        relative,
+                        rank,
+                    })
+                })
+                .collect();
+
+            let rank = match windows.is_empty() {
+                true => 0.,
+                false => {
+                    windows.iter().map(|window| window.rank).sum::<f64>() / windows.len() as f64
+                }
+            };
+
+            RelativeStrength {
+                symbol: current.symbol.clone(),
+                market: current.market.clone(),
+                time_frame: current.time_frame.clone(),
+                benchmark: params.benchmark.clone(),
+                rank,
+                windows,
+            }
+        })
+        .collect()
+}
+
+fn performance(closes: &[f64], window: usize) -> Option<f64> {
+    let len = closes.len();
+    if window == 0 || len <= window {
+        return None;
+    }
+
+    let from = closes[len - 1 - window];
+    match from > 0. {
+        true => Some((closes[len - 1] / from - 1.) * 100.),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_algo_shared::models::time_frame::TimeFrame;
+
+    fn params() -> RelativeStrengthParams {
+        RelativeStrengthParams {
+            enabled: true,
+            benchmark: String::from("US500"),
+            windows: vec![2, 4],
+        }
+    }
+
+    fn series(symbol: &str, market: &str, closes: &[f64]) -> Series {
+        Series {
+            symbol: symbol.to_owned(),
+            market: market.to_owned(),
+            time_frame: String::from("D"),
+            closes: closes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn covers_every_market_of_each_time_frame() {
+        let ranker = RelativeStrengthRanker::new(params());
+        let markets = vec![Market::Forex, Market::Crypto];
+        let trigger = |time_frame: &str, markets: Vec<Market>| Trigger {
+            time_frame: TimeFrame::new(time_frame),
+            close: chrono::Utc::now(),
+            markets,
+        };
+
+        let daily = trigger("D", markets.clone());
+        let hourly = trigger("H1", markets.clone());
+        let crypto_hourly = trigger("H1", vec![Market::Crypto]);
+
+        assert!(ranker.covers(&[daily.clone()], &markets));
+        assert!(ranker.covers(&[daily.clone(), hourly], &markets));
+        assert!(!ranker.covers(&[daily, crypto_hourly], &markets));
+        assert!(!ranker.covers(&[], &markets));
+    }
+
+    fn find<'a>(ranks: &'a [RelativeStrength], symbol: &str) -> &'a RelativeStrength {
+        ranks.iter().find(|rank| rank.symbol == symbol).unwrap()
+    }
+
+    #[test]
+    fn performance_over_the_window() {
+        assert!((performance(&[100., 105., 110.], 2).unwrap() - 10.).abs() < 1e-9);
+        assert_eq!(performance(&[100., 110.], 2), None);
+        assert_eq!(performance(&[0., 110.], 1), None);
+    }
+
+    #[test]
+    fn ranks_within_the_market() {
+        let universe = vec![
+            series("US500", "Stock", &[100., 100., 100., 102., 104.]),
+            series("AAPL", "Stock", &[100., 100., 100., 105., 110.]),
+            series("MSFT", "Stock", &[100., 100., 100., 100., 100.]),
+            series("BITCOIN", "Crypto", &[100., 100., 100., 90., 80.]),
+        ];
+
+        let ranks = rank(&universe, &params());
+        assert_eq!(find(&ranks, "AAPL").windows[0].rank, 100.);
+        assert_eq!(find(&ranks, "US500").windows[0].rank, 50.);
+        assert_eq!(find(&ranks, "MSFT").windows[0].rank, 0.);
+        assert_eq!(find(&ranks, "BITCOIN").windows[0].rank, 100.);
+        assert_eq!(find(&ranks, "AAPL").rank, 100.);
+    }
+
+    #[test]
+    fn relative_to_the_benchmark() {
+        let universe = vec![
+            series("US500", "Stock", &[100., 100., 100., 100., 110.]),
+            series("AAPL", "Stock", &[100., 100., 100., 100., 121.]),
+        ];
+
+        let ranks = rank(&universe, &params());
+        let window = &find(&ranks, "AAPL").windows[0];
+        assert!((window.performance - 21.).abs() < 1e-9);
+        assert!((window.relative.unwrap() - 10.).abs() < 1e-9);
+
+        let ranks = rank(&universe[1..], &params());
+        assert_eq!(ranks[0].windows[0].relative, None);
+    }
+
+    #[test]
+    fn short_series_skip_long_windows() {
+        let universe = vec![
+            series("AAPL", "Stock", &[100., 105., 110.]),
+            series("MSFT", "Stock", &[100., 100., 100., 100., 100.]),
+        ];
+
+        let ranks = rank(&universe, &params());
+        let aapl = find(&ranks, "AAPL");
+        assert_eq!(aapl.windows.len(), 1);
+        assert_eq!(aapl.windows[0].bars, 2);
+        assert_eq!(find(&ranks, "MSFT").windows[1].rank, 100.);
+    }
+
+    #[test]
+    fn ties_share_the_rank() {
+        let universe = vec![
+            series("AAPL", "Stock", &[100., 100., 110.]),
+            series("MSFT", "Stock", &[100., 100., 110.]),
+        ];
+
+        let ranks = rank(&universe, &params());
+        assert_eq!(ranks[0].windows[0].rank, 50.);
+        assert_eq!(ranks[1].windows[0].rank, 50.);
+    }
+}
@@ -0,0 +1,335 @@
+use crate::analysis::Analysis;
+use crate::error::{io_error, Result, RsAlgoError, RsAlgoErrorKind};
+use crate::outbox::{self, Outbox};
+
+use rs_algo_shared::helpers::date::DbDateTime;
+use rs_algo_shared::scanner::instrument::Instrument;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolFailure {
+    pub symbol: String,
+    pub time_frame: String,
+    pub kind: String,
+    pub reason: String,
+}
+
+/// Patterns and divergences found in the instruments of a run, by type.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScanEvents {
+    pub new_patterns: BTreeMap<String, usize>,
+    pub activated_patterns: BTreeMap<String, usize>,
+    pub divergences: BTreeMap<String, usize>,
+}
+
+impl ScanEvents {
+    /// Counts the patterns dated after `since`, the patterns activated after
+    /// `activated_since` and the divergences dated after `since`, in the same way the
+    /// home page lists them.
+    pub fn add(
+        &mut self,
+        instrument: &Instrument,
+        analysis: &Analysis,
+        since: DbDateTime,
+        activated_since: DbDateTime,
+    ) {
+        for pattern in instrument.patterns.local_patterns.iter() {
+            let pattern_type = format!("{:?}", pattern.pattern_type);
+            if pattern.active.active && pattern.active.date > activated_since {
+                *self.activated_patterns.entry(pattern_type).or_default() += 1;
+            } else if !pattern.active.active && pattern.date > since {
+                *self.new_patterns.entry(pattern_type).or_default() += 1;
+            }
+        }
+
+        for divergence in instrument.divergences.data.iter() {
+            if divergence.date > since {
+                let divergence_type = format!("{:?}", divergence.divergence_type);
+                *self.divergences.entry(divergence_type).or_default() += 1;
+            }
+        }
+
+        for divergence in analysis.divergences.iter() {
+            if matches!(divergence.confirmed_date, Some(date) if date > since) {
+                let divergence_type = format!("{:?}", divergence.divergence_type);
+                *self.divergences.entry(divergence_type).or_default() += 1;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: ScanEvents) {
+        for (events, other) in [
+            (&mut self.new_patterns, other.new_patterns),
+            (&mut self.activated_patterns, other.activated_patterns),
+            (&mut self.divergences, other.divergences),
+        ] {
+            for (event_type, count) in other {
+                *events.entry(event_type).or_default() += count;
+            }
+        }
+    }
+}
+
+/// Latency percentiles in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Latency {
+    pub count: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Latency {
+    pub fn from_durations(durations: &[Duration]) -> Self {
+        let mut millis: Vec<f64> = durations
+            .iter()
+            .map(|duration| duration.as_secs_f64() * 1000.)
+            .collect();
+        millis.sort_by(|a, b| a.total_cmp(b));
+
+        // Nearest rank
+        let percentile = |percentile: f64| match millis.is_empty() {
+            true => 0.,
+            false => {
+                let rank = (percentile / 100. * millis.len() as f64).ceil() as usize;
+                millis[rank.clamp(1, millis.len()) - 1]
+            }
+        };
+
+        Self {
+            count: millis.len(),
+            p50: percentile(50.),
+            p90: percentile(90.),
+            p99: percentile(99.),
+            max: millis.last().copied().unwrap_or(0.),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanReport {
+    pub processed: Vec<(String, String)>,
+    pub skipped: Vec<(String, String)>,
+    pub failures: Vec<SymbolFailure>,
+    pub bars_fetched: usize,
+    pub fetch_latencies: Vec<Duration>,
+    pub upload_latencies: Vec<Duration>,
+    pub events: ScanEvents,
+}
+
+/// Machine readable summary of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanSummary {
+    pub mode: String,
+    pub time_frames: Vec<String>,
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// Seconds.
+    pub elapsed: f64,
+    pub num_processed: usize,
+    pub num_skipped: usize,
+    pub num_failed: usize,
+    pub processed: Vec<(String, String)>,
+    pub skipped: Vec<(String, String)>,
+    pub failures: Vec<SymbolFailure>,
+    pub bars_fetched: usize,
+    pub fetch_latency: Latency,
+    pub upload_latency: Latency,
+    pub events: ScanEvents,
+}
+
+impl ScanReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_processed(&mut self, symbol: &str, time_frame: &str) {
+        self.processed
+            .push((symbol.to_owned(), time_frame.to_owned()));
+    }
+
+    pub fn add_skipped(&mut self, symbol: &str, time_frame: &str) {
+        self.skipped
+            .push((symbol.to_owned(), time_frame.to_owned()));
+    }
+
+    pub fn add_failure(&mut self, symbol: &str, time_frame: &str, err: &RsAlgoError) {
+        self.failures.push(SymbolFailure {
+            symbol: symbol.to_owned(),
+            time_frame: time_frame.to_owned(),
+            kind: format!("{:?}", err.kind()),
+            reason: err.to_string(),
+        });
+    }
+
+    pub fn add_fetch(&mut self, bars: usize, latency: Duration, events: ScanEvents) {
+        self.bars_fetched += bars;
+        self.fetch_latencies.push(latency);
+        self.events.merge(events);
+    }
+
+    pub fn add_upload_latency(&mut self, latency: Duration) {
+        self.upload_latencies.push(latency);
+    }
+
+    pub fn merge(&mut self, other: ScanReport) {
+        self.processed.extend(other.processed);
+        self.skipped.extend(other.skipped);
+        self.failures.extend(other.failures);
+        self.bars_fetched += other.bars_fetched;
+        self.fetch_latencies.extend(other.fetch_latencies);
+        self.upload_latencies.extend(other.upload_latencies);
+        self.events.merge(other.events);
+    }
+
+    pub fn summary(
+        &self,
+        mode: &str,
+        time_frames: Vec<String>,
+        started_at: DateTime<Local>,
+        elapsed: Duration,
+    ) -> ScanSummary {
+        ScanSummary {
+            mode: mode.to_owned(),
+            time_frames,
+            started_at: started_at.timestamp(),
+            finished_at: Local::now().timestamp(),
+            elapsed: elapsed.as_secs_f64(),
+            num_processed: self.processed.len(),
+            num_skipped: self.skipped.len(),
+            num_failed: self.failures.len(),
+            processed: self.processed.clone(),
+            skipped: self.skipped.clone(),
+            failures: self.failures.clone(),
+            bars_fetched: self.bars_fetched,
+            fetch_latency: Latency::from_durations(&self.fetch_latencies),
+            upload_latency: Latency::from_durations(&self.upload_latencies),
+            events: self.events.clone(),
+        }
+    }
+
+    pub fn log(&self) {
+        log::info!(
+            "[REPORT] {} symbols processed, {} skipped, {} failed",
+            self.processed.len(),
+            self.skipped.len(),
+            self.failures.len()
+        );
+
+        for failure in self.failures.iter() {
+            log::error!(
+                "[REPORT] {} {} failed ({}): {}",
+                failure.symbol,
+                failure.time_frame,
+                failure.kind,
+                failure.reason
+            );
+        }
+    }
+}
+
+impl ScanSummary {
+    /// Appends the summary as one line to `SCAN_SUMMARY_FILE`.
+    pub fn write(&self) -> Result<()> {
+        let file_name = env::var("SCAN_SUMMARY_FILE").unwrap();
+        let path = Path::new(&file_name);
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|err| io_error(path, err))?;
+        }
+
+        let line = serde_json::to_string(self).map_err(|err| {
+            RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+        })?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| io_error(path, err))?;
+
+        writeln!(file, "{}", line).map_err(|err| io_error(path, err))
+    }
+
+    /// Sends the summary to the backend, queueing it in the outbox when it's down.
+    pub async fn upload(&self, outbox: &Outbox) -> Result<()> {
+        let url = env::var("BACKEND_SCANS_ENDPOINT").unwrap();
+        let key = ["scan_", &self.started_at.to_string()].concat();
+
+        match outbox::upload(&url, self).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let body = serde_json::to_value(self).map_err(|err| {
+                    RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+                })?;
+                outbox.push(&key, &url, body, &err).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values
+            .iter()
+            .map(|value| Duration::from_millis(*value))
+            .collect()
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let latency = Latency::from_durations(&millis(&(1..=100).rev().collect::<Vec<u64>>()));
+        assert_eq!(latency.count, 100);
+        assert_eq!(latency.p50, 50.);
+        assert_eq!(latency.p90, 90.);
+        assert_eq!(latency.p99, 99.);
+        assert_eq!(latency.max, 100.);
+
+        let latency = Latency::from_durations(&millis(&[30, 10]));
+        assert_eq!((latency.p50, latency.p90), (10., 30.));
+        assert_eq!(Latency::from_durations(&[]), Latency::default());
+    }
+
+    #[test]
+    fn merges_worker_reports() {
+        let mut events = ScanEvents::default();
+        events.divergences.insert(String::from("Bullish"), 2);
+
+        let mut worker = ScanReport::new();
+        worker.add_processed("AAPL", "D");
+        worker.add_skipped("MSFT", "D");
+        worker.add_fetch(250, Duration::from_millis(40), events.clone());
+        worker.add_upload_latency(Duration::from_millis(5));
+
+        let mut report = ScanReport::new();
+        report.add_fetch(100, Duration::from_millis(20), events);
+        report.merge(worker);
+
+        let summary = report.summary(
+            "daily",
+            vec![String::from("D")],
+            Local::now(),
+            Duration::from_secs(2),
+        );
+        assert_eq!(summary.num_processed, 1);
+        assert_eq!(summary.num_skipped, 1);
+        assert_eq!(summary.num_failed, 0);
+        assert_eq!(summary.bars_fetched, 350);
+        assert_eq!(summary.fetch_latency.count, 2);
+        assert_eq!(summary.fetch_latency.max, 40.);
+        assert_eq!(summary.upload_latency.p50, 5.);
+        assert_eq!(summary.events.divergences["Bullish"], 4);
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+// Longest wait between two attempts
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    attempt: u32,
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            attempt: 0,
+            max_retries,
+            base_delay,
+        }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.base_delay
+            .saturating_mul(2_u32.saturating_pow(self.attempt))
+            .min(MAX_DELAY)
+    }
+
+    /// Sleeps for an exponentially growing delay. Returns false when there are no retries left.
+    pub async fn wait(&mut self) -> bool {
+        if self.attempt >= self.max_retries {
+            return false;
+        }
+
+        let delay = self.delay();
+        self.attempt += 1;
+        tokio::time::sleep(delay).await;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new(100, Duration::from_secs(1));
+        let mut delays = vec![];
+        for attempt in [0, 1, 2, 8, 31, 32, 99] {
+            backoff.attempt = attempt;
+            delays.push(backoff.delay().as_secs());
+        }
+        assert_eq!(delays, vec![1, 2, 4, 256, 300, 300, 300]);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::analysis::{AnalysisContext, ScannedInstrument};
+use crate::api;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::report::ScanReport;
+use crate::screener::Screener;
+
+use rs_algo_shared::broker::Broker;
+use rs_algo_shared::models::market::*;
+use rs_algo_shared::models::time_frame::TimeFrameType;
+
+use futures::future::join_all;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+type Upload = JoinHandle<(Result<()>, Duration)>;
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub symbol: String,
+    pub market: Market,
+    pub time_frame: TimeFrameType,
+    pub start_date: i64,
+    pub last_date: Option<i64>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket shared by all the workers so the broker never receives more than
+/// `rate` requests per second, with bursts of up to `capacity` requests. A rate of 0
+/// disables the limit.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        if self.rate <= 0. || !self.rate.is_finite() {
+            return;
+        }
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let elapsed = bucket.last_refill.elapsed().as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.capacity);
+                bucket.last_refill = Instant::now();
+
+                if bucket.tokens >= 1. {
+                    bucket.tokens -= 1.;
+                    return;
+                }
+
+                Duration::from_secs_f64((1. - bucket.tokens) / self.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    concurrency: usize,
+    rate_limiter: RateLimiter,
+    context: Arc<AnalysisContext>,
+}
+
+impl Scheduler {
+    pub fn new(concurrency: usize, rate: f64, burst: f64, context: Arc<AnalysisContext>) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            rate_limiter: RateLimiter::new(rate, burst.max(1.)),
+            context,
+        }
+    }
+
+    pub async fn run<BK, F, T>(
+        &self,
+        jobs: Vec<Job>,
+        credentials: (&str, &str),
+        callback: F,
+    ) -> Result<ScanReport>
+    where
+        BK: Broker,
+        F: Send + Clone + FnMut(ScannedInstrument) -> T,
+        T: Future<Output = Result<()>> + Send + 'static,
+    {
+        let start = Instant::now();
+        let total_jobs = jobs.len();
+        let queue = Mutex::new(jobs.into_iter().collect::<VecDeque<Job>>());
+
+        log::info!(
+            "[SCHEDULER] Scanning {} jobs with {} workers",
+            total_jobs,
+            self.concurrency
+        );
+
+        let workers = (0..self.concurrency)
+            .map(|worker| self.worker::<BK, F, T>(worker, &queue, credentials, callback.clone()));
+
+        let mut report = ScanReport::new();
+        let mut uploads = vec![];
+        for (worker_report, worker_uploads) in join_all(workers).await {
+            report.merge(worker_report);
+            uploads.extend(worker_uploads);
+        }
+
+        // Left in the queue when every worker failed to get a broker session
+        for job in queue.lock().unwrap().drain(..) {
+            report.add_failure(
+                &job.symbol,
+                &job.time_frame.to_string(),
+                &RsAlgoError::new(RsAlgoErrorKind::BrokerError, "no broker session"),
+            );
+        }
+
+        let fetch_elapsed = start.elapsed();
+        let num_uploads = uploads.len();
+        let (jobs, handles): (Vec<(String, String)>, Vec<Upload>) = uploads.into_iter().unzip();
+
+        for ((symbol, time_frame), upload) in jobs.iter().zip(join_all(handles).await) {
+            match upload {
+                Ok((result, latency)) => {
+                    report.add_upload_latency(latency);
+                    match result {
+                        Ok(()) => report.add_processed(symbol, time_frame),
+                        Err(err) => report.add_failure(symbol, time_frame, &err),
+                    }
+                }
+                Err(err) => report.add_failure(
+                    symbol,
+                    time_frame,
+                    &RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()),
+                ),
+            }
+        }
+
+        let elapsed = start.elapsed();
+        let processed = report.processed.len();
+        let throughput = match elapsed.as_secs_f64() > 0. {
+            true => processed as f64 / elapsed.as_secs_f64(),
+            false => 0.,
+        };
+
+        log::info!(
+            "[SCHEDULER] {} instruments fetched in {:?}, {} uploads awaited. Total {:?} ({:.2} instruments/s)",
+            num_uploads,
+            fetch_elapsed,
+            num_uploads,
+            elapsed,
+            throughput
+        );
+
+        Ok(report)
+    }
+
+    async fn worker<BK, F, T>(
+        &self,
+        worker: usize,
+        queue: &Mutex<VecDeque<Job>>,
+        credentials: (&str, &str),
+        callback: F,
+    ) -> (ScanReport, Vec<((String, String), Upload)>)
+    where
+        BK: Broker,
+        F: Send + Clone + FnMut(ScannedInstrument) -> T,
+        T: Future<Output = Result<()>> + Send + 'static,
+    {
+        let (username, password) = credentials;
+        let mut report = ScanReport::new();
+        let mut uploads = vec![];
+
+        let mut screener = match Screener::<BK>::new(self.context.clone()).await {
+            Ok(screener) => screener,
+            Err(err) => {
+                log::error!("[WORKER {}] Can't start: {}", worker, err);
+                return (report, uploads);
+            }
+        };
+
+        if let Err(err) = screener.login(username, password).await {
+            log::error!("[WORKER {}] Can't login: {}", worker, err);
+            return (report, uploads);
+        }
+
+        loop {
+            let job = queue.lock().unwrap().pop_front();
+            let job = match job {
+                Some(job) => job,
+                None => break,
+            };
+
+            self.rate_limiter.acquire().await;
+
+            let now = Instant::now();
+            log::info!(
+                "[WORKER {}] processing {} {} ...",
+                worker,
+                &job.symbol,
+                &job.time_frame
+            );
+
+            let (start_date, previous_data) = match job.last_date {
+                Some(last_date) => {
+                    match api::get_candles(&job.symbol, &job.time_frame.to_string()).await {
+                        Ok(candles) => (last_date, candles),
+                        Err(err) => {
+                            log::warn!(
+                                "[WORKER {}] {} full scan, can't get stored instrument: {}",
+                                worker,
+                                &job.symbol,
+                                err
+                            );
+                            (job.start_date, vec![])
+                        }
+                    }
+                }
+                None => (job.start_date, vec![]),
+            };
+
+            match screener
+                .get_instrument_data(
+                    &job.symbol,
+                    &job.market,
+                    &job.time_frame,
+                    start_date,
+                    previous_data,
+                    callback.clone(),
+                )
+                .await
+            {
+                Ok(fetched) => {
+                    let elapsed = now.elapsed();
+                    log::info!(
+                        "[WORKER {}] {} {} fetched in {:?}",
+                        worker,
+                        &job.symbol,
+                        &job.time_frame,
+                        elapsed
+                    );
+                    report.add_fetch(fetched.bars, elapsed, fetched.events);
+                    uploads.push(((job.symbol, job.time_frame.to_string()), fetched.upload));
+                }
+                Err(err) => {
+                    log::error!(
+                        "[WORKER {}] {} {} failed: {}",
+                        worker,
+                        &job.symbol,
+                        &job.time_frame,
+                        err
+                    );
+                    report.add_failure(&job.symbol, &job.time_frame.to_string(), &err);
+                }
+            }
+        }
+
+        (report, uploads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn zero_rate_is_unlimited() {
+        let limiter = RateLimiter::new(0., 1.);
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn waits_once_the_burst_is_spent() {
+        let limiter = RateLimiter::new(20., 2.);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -1,39 +1,154 @@
+use crate::analysis::{self, AnalysisContext, ScannedInstrument};
 use crate::backend::Backend;
-use crate::error::Result;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::report::ScanEvents;
+use crate::retry::Backoff;
 
 use rs_algo_shared::broker::{Broker, Response, VEC_DOHLC};
+use rs_algo_shared::helpers::date::*;
 use rs_algo_shared::models::market::*;
 use rs_algo_shared::models::time_frame::TimeFrameType;
 use rs_algo_shared::scanner::instrument::Instrument;
 
 use std::env;
 use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Instrument fetched and analyzed, with its upload running in the background.
+#[derive(Debug)]
+pub struct Fetched {
+    /// Upload result and how long it took.
+    pub upload: JoinHandle<(Result<()>, Duration)>,
+    pub bars: usize,
+    pub events: ScanEvents,
+}
 
 #[derive(Debug)]
 pub struct Screener<BK> {
     broker: BK,
     pub backend: Backend,
+    max_retries: u32,
+    retry_delay: Duration,
+    context: Arc<AnalysisContext>,
+    max_pattern_date: DbDateTime,
+    max_activated_date: DbDateTime,
 }
 
 impl<BK> Screener<BK>
 where
     BK: Broker,
 {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(context: Arc<AnalysisContext>) -> Result<Self> {
+        let max_retries = env::var("BROKER_MAX_RETRIES")
+            .unwrap()
+            .parse::<u32>()
+            .unwrap();
+
+        let retry_delay = env::var("BROKER_RETRY_DELAY")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+
+        let max_pattern_days = env::var("MAX_PATTERN_DAYS")
+            .unwrap()
+            .parse::<i64>()
+            .unwrap();
+
+        let max_pattern_activated_days = env::var("MAX_PATTERN_ACTIVATED_DAYS")
+            .unwrap()
+            .parse::<i64>()
+            .unwrap();
+
         Ok(Self {
             broker: BK::new().await,
             backend: Backend::new(),
+            max_retries,
+            retry_delay: Duration::from_millis(retry_delay),
+            context,
+            max_pattern_date: to_dbtime(Local::now() - chrono::Duration::days(max_pattern_days)),
+            max_activated_date: to_dbtime(
+                Local::now() - chrono::Duration::days(max_pattern_activated_days),
+            ),
         })
     }
 
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(self.max_retries, self.retry_delay)
+    }
+
     pub async fn login(&mut self, username: &str, password: &str) -> Result<()> {
-        let _result = self.broker.login(username, password).await.unwrap();
-        Ok(())
+        let mut backoff = self.backoff();
+        loop {
+            match self.broker.login(username, password).await {
+                Ok(_) => return Ok(()),
+                Err(err) => {
+                    let err = RsAlgoError::from_broker(&err, "login failed: ");
+                    log::warn!(
+                        "[SCREENER] Login attempt {} failed: {}",
+                        backoff.attempt(),
+                        err
+                    );
+                    if !err.is_retryable() || !backoff.wait().await {
+                        return Err(err);
+                    }
+                }
+            }
+        }
     }
 
     pub async fn get_symbols(&mut self) -> Result<Response<VEC_DOHLC>> {
-        let symbols = self.broker.get_symbols().await.unwrap();
-        Ok(symbols)
+        let mut backoff = self.backoff();
+        loop {
+            match self.broker.get_symbols().await {
+                Ok(symbols) => return Ok(symbols),
+                Err(err) => {
+                    let err = RsAlgoError::from_broker(&err, "can't get symbols: ");
+                    log::warn!(
+                        "[SCREENER] Get symbols attempt {} failed: {}",
+                        backoff.attempt(),
+                        err
+                    );
+                    if !err.is_retryable() || !backoff.wait().await {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn get_history(
+        &mut self,
+        symbol: &str,
+        time_frame: &TimeFrameType,
+        start_date: i64,
+    ) -> Result<VEC_DOHLC> {
+        let mut backoff = self.backoff();
+        loop {
+            match self
+                .broker
+                .get_instrument_data(symbol, time_frame.to_number() as usize, start_date)
+                .await
+            {
+                Ok(res) => return Ok(res.data),
+                Err(err) => {
+                    let err = RsAlgoError::from_broker(
+                        &err,
+                        &["can't get data for ", symbol, ": "].concat(),
+                    );
+                    log::warn!(
+                        "[SCREENER] {} attempt {} failed: {}",
+                        symbol,
+                        backoff.attempt(),
+                        err
+                    );
+                    if !err.is_retryable() || !backoff.wait().await {
+                        return Err(err);
+                    }
+                }
+            }
+        }
     }
 
     pub async fn get_instrument_data<F, T>(
@@ -42,38 +157,122 @@ where
         market: &Market,
         time_frame: &TimeFrameType,
         start_date: i64,
+        previous_data: VEC_DOHLC,
         mut callback: F,
-    ) -> Result<()>
+    ) -> Result<Fetched>
     where
-        F: Send + FnMut(Instrument) -> T,
+        F: Send + FnMut(ScannedInstrument) -> T,
         T: Future<Output = Result<()>> + Send + 'static,
     {
-        let res = self
-            .broker
-            .get_instrument_data(symbol, time_frame.to_number() as usize, start_date)
-            .await
-            .unwrap();
+        let res = self.get_history(symbol, time_frame, start_date).await?;
+        let bars = res.len();
+        let data = match previous_data.is_empty() {
+            true => res,
+            false => merge_data(previous_data, res),
+        };
+
+        validate_data(symbol, &data)?;
 
         let mut instrument = Instrument::new()
             .symbol(symbol)
             .market(market.to_owned())
             .time_frame(time_frame.to_owned())
             .build()
-            .unwrap();
+            .map_err(|err| {
+                RsAlgoError::new(
+                    RsAlgoErrorKind::WrongInstrumentConf,
+                    &[symbol, ": ", &err.to_string()].concat(),
+                )
+            })?;
 
-        instrument.set_data(res.data).unwrap();
+        instrument.set_data(data).map_err(|err| {
+            RsAlgoError::new(
+                RsAlgoErrorKind::WrongInstrumentConf,
+                &["can't process ", symbol, ": ", &err.to_string()].concat(),
+            )
+        })?;
 
         let render_to_image = env::var("RENDER_TO_IMAGE")
             .unwrap()
             .parse::<bool>()
             .unwrap();
 
+        let analysis = analysis::analyze(&instrument, &self.context);
+
         if render_to_image {
-            self.backend.render(&instrument).unwrap();
+            if let Err(err) = self.backend.render(&instrument, &analysis) {
+                log::error!("[SCREENER] Can't render {}: {}", symbol, err);
+            }
         }
 
-        tokio::spawn(callback(instrument));
+        let mut events = ScanEvents::default();
+        events.add(
+            &instrument,
+            &analysis,
+            self.max_pattern_date,
+            self.max_activated_date,
+        );
+
+        let upload = callback(ScannedInstrument {
+            instrument,
+            analysis,
+        });
+
+        Ok(Fetched {
+            upload: tokio::spawn(async move {
+                let now = Instant::now();
+                let result = upload.await;
+                (result, now.elapsed())
+            }),
+            bars,
+            events,
+        })
+    }
+}
+
+/// Replaces the stored bars from the first newer bar onwards (the last stored bar
+/// may have been an unfinished one) and keeps the series length.
+fn merge_data(previous_data: VEC_DOHLC, newer_data: VEC_DOHLC) -> VEC_DOHLC {
+    let first_date = match newer_data.first() {
+        Some(candle) => candle.0,
+        None => return previous_data,
+    };
+
+    let max_len = previous_data.len().max(newer_data.len());
+    let mut data: VEC_DOHLC = previous_data
+        .into_iter()
+        .filter(|candle| candle.0 < first_date)
+        .collect();
+
+    data.extend(newer_data);
+
+    if data.len() > max_len {
+        data.drain(..data.len() - max_len);
+    }
+
+    data
+}
+
+fn validate_data(symbol: &str, data: &VEC_DOHLC) -> Result<()> {
+    if data.is_empty() {
+        return Err(RsAlgoError::new(
+            RsAlgoErrorKind::InvalidCandle,
+            &[symbol, ": no candles received"].concat(),
+        ));
+    }
+
+    let invalid = data
+        .iter()
+        .position(|(_date, open, high, low, close, _volume)| {
+            let prices = [*open, *high, *low, *close];
+            prices.iter().any(|price| !price.is_finite()) || high < low
+        });
 
-        Ok(())
+    match invalid {
+        Some(index) => Err(RsAlgoError::new(
+            RsAlgoErrorKind::InvalidCandle,
+            &[symbol, ": invalid candle at ", &data[index].0.to_string()].concat(),
+        )),
+        None => Ok(()),
     }
 }
@@ -0,0 +1,86 @@
+use super::Sink;
+use crate::analysis::ScannedInstrument;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::outbox::{self, Outbox};
+
+use async_trait::async_trait;
+use chrono::Local;
+use std::env;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Uploads instruments to the backend. Failed uploads are queued in the outbox
+/// instead of being lost.
+#[derive(Debug)]
+pub struct BackendSink {
+    endpoint: String,
+    mode: &'static str,
+    outbox: Arc<Outbox>,
+}
+
+impl BackendSink {
+    pub fn new(backtest_mode: bool, outbox: Arc<Outbox>) -> Self {
+        Self {
+            endpoint: env::var("BACKEND_INSTRUMENTS_ENDPOINT").unwrap(),
+            mode: match backtest_mode {
+                true => "backtest",
+                false => "daily",
+            },
+            outbox,
+        }
+    }
+
+    pub fn url(&self, scanned: &ScannedInstrument) -> String {
+        [
+            &self.endpoint,
+            "?mode=",
+            self.mode,
+            "&time_frame=",
+            &scanned.instrument.time_frame().to_string(),
+        ]
+        .concat()
+    }
+
+    fn outbox_key(&self, scanned: &ScannedInstrument) -> String {
+        [
+            self.mode,
+            "_",
+            &scanned.instrument.time_frame().to_string(),
+            "_",
+            scanned.instrument.symbol(),
+        ]
+        .concat()
+    }
+}
+
+#[async_trait]
+impl Sink for BackendSink {
+    fn name(&self) -> &str {
+        "backend"
+    }
+
+    async fn write(&self, scanned: &ScannedInstrument) -> Result<()> {
+        let now = Instant::now();
+        let url = self.url(scanned);
+        let key = self.outbox_key(scanned);
+
+        match outbox::upload(&url, scanned).await {
+            Ok(()) => {
+                self.outbox.remove(&key).await;
+                log::info!(
+                    "[BACKEND RESPONSE] {:?} uploaded at {:?} in {:?}",
+                    scanned.instrument.symbol(),
+                    Local::now(),
+                    now.elapsed()
+                );
+                Ok(())
+            }
+            Err(err) => {
+                let body = serde_json::to_value(scanned).map_err(|err| {
+                    RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+                })?;
+                self.outbox.push(&key, &url, body, &err).await
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use super::Sink;
+use crate::analysis::ScannedInstrument;
+use crate::error::{io_error, Result, RsAlgoError, RsAlgoErrorKind};
+
+use async_trait::async_trait;
+use chrono::Local;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// One line per instrument appended to a file per run.
+    Ndjson,
+    /// One `{SYMBOL}_{TIME_FRAME}.json` file per instrument, overwritten on every run.
+    Json,
+}
+
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    format: FileFormat,
+    ndjson: Option<Mutex<File>>,
+}
+
+impl FileSink {
+    pub fn new(format: FileFormat) -> Result<Self> {
+        let path = PathBuf::from(env::var("OUTPUT_SINKS_PATH").unwrap());
+        fs::create_dir_all(&path).map_err(|err| io_error(&path, err))?;
+
+        let ndjson = match format {
+            FileFormat::Ndjson => {
+                let file_name = [
+                    "scan_",
+                    &Local::now().format("%Y%m%d_%H%M%S").to_string(),
+                    ".ndjson",
+                ]
+                .concat();
+                let file_path = path.join(file_name);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&file_path)
+                    .map_err(|err| io_error(&file_path, err))?;
+
+                log::info!("[FILE SINK] Writing instruments to {:?}", file_path);
+                Some(Mutex::new(file))
+            }
+            FileFormat::Json => None,
+        };
+
+        Ok(Self {
+            path,
+            format,
+            ndjson,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        match self.format {
+            FileFormat::Ndjson => "ndjson",
+            FileFormat::Json => "json",
+        }
+    }
+
+    async fn write(&self, scanned: &ScannedInstrument) -> Result<()> {
+        match &self.ndjson {
+            Some(file) => {
+                let mut line = serde_json::to_string(scanned).map_err(json_error)?;
+                line.push('\n');
+                file.lock()
+                    .unwrap()
+                    .write_all(line.as_bytes())
+                    .map_err(|err| io_error(&self.path, err))
+            }
+            None => {
+                let file_path = self.path.join(
+                    [
+                        scanned.instrument.symbol(),
+                        "_",
+                        &scanned.instrument.time_frame().to_string(),
+                        ".json",
+                    ]
+                    .concat(),
+                );
+                let content = serde_json::to_string_pretty(scanned).map_err(json_error)?;
+                fs::write(&file_path, content).map_err(|err| io_error(&file_path, err))
+            }
+        }
+    }
+}
+
+fn json_error(err: serde_json::Error) -> RsAlgoError {
+    RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+}
@@ -0,0 +1,104 @@
+use crate::analysis::ScannedInstrument;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::outbox::Outbox;
+
+use async_trait::async_trait;
+use std::env;
+use std::sync::Arc;
+
+pub mod backend;
+pub mod file;
+pub mod stdout;
+
+use backend::BackendSink;
+use file::{FileFormat, FileSink};
+use stdout::StdoutSink;
+
+/// Destination for scanned instruments.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &str;
+    async fn write(&self, scanned: &ScannedInstrument) -> Result<()>;
+}
+
+/// Writes every instrument to all the configured sinks.
+pub struct Sinks {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Sinks {
+    pub fn new(sinks: Vec<Box<dyn Sink>>) -> Self {
+        Self { sinks }
+    }
+
+    /// Builds the sinks listed in `OUTPUT_SINKS` (`backend`, `ndjson`, `json`, `stdout`).
+    pub fn from_env(backtest_mode: bool, outbox: Arc<Outbox>) -> Result<Self> {
+        let output_sinks = env::var("OUTPUT_SINKS").unwrap();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![];
+
+        for sink in output_sinks
+            .split(',')
+            .map(|sink| sink.trim())
+            .filter(|sink| !sink.is_empty())
+        {
+            let sink: Box<dyn Sink> = match sink {
+                "backend" => Box::new(BackendSink::new(backtest_mode, outbox.clone())),
+                "ndjson" => Box::new(FileSink::new(FileFormat::Ndjson)?),
+                "json" => Box::new(FileSink::new(FileFormat::Json)?),
+                "stdout" => Box::new(StdoutSink),
+                _ => {
+                    return Err(RsAlgoError::new(
+                        RsAlgoErrorKind::WrongInstrumentConf,
+                        &["unknown output sink ", sink].concat(),
+                    ))
+                }
+            };
+            sinks.push(sink);
+        }
+
+        if sinks.is_empty() {
+            return Err(RsAlgoError::new(
+                RsAlgoErrorKind::WrongInstrumentConf,
+                "no output sinks configured",
+            ));
+        }
+
+        log::info!(
+            "[SINKS] Writing to {}",
+            sinks
+                .iter()
+                .map(|sink| sink.name())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+
+        Ok(Self::new(sinks))
+    }
+
+    /// Every sink receives the instrument even if a previous one failed.
+    pub async fn write(&self, scanned: &ScannedInstrument) -> Result<()> {
+        let mut failed: Option<(RsAlgoErrorKind, Vec<String>)> = None;
+
+        for sink in self.sinks.iter() {
+            if let Err(err) = sink.write(scanned).await {
+                log::error!(
+                    "[SINKS] {} can't write {}: {}",
+                    sink.name(),
+                    scanned.instrument.symbol(),
+                    err
+                );
+
+                let reason = [sink.name(), ": ", &err.to_string()].concat();
+                match failed.as_mut() {
+                    Some((_kind, reasons)) => reasons.push(reason),
+                    None => failed = Some((err.kind(), vec![reason])),
+                }
+            }
+        }
+
+        match failed {
+            Some((kind, reasons)) => Err(RsAlgoError::new(kind, &reasons.join(", "))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use super::Sink;
+use crate::analysis::ScannedInstrument;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+
+use async_trait::async_trait;
+use std::io::{self, Write};
+
+/// Prints one JSON line per instrument. Logs go to stderr so the output can be piped.
+#[derive(Debug, Default)]
+pub struct StdoutSink;
+
+#[async_trait]
+impl Sink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    async fn write(&self, scanned: &ScannedInstrument) -> Result<()> {
+        let line = serde_json::to_string(scanned).map_err(|err| {
+            RsAlgoError::new(RsAlgoErrorKind::WrongInstrumentConf, &err.to_string())
+        })?;
+
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{}", line)
+            .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))
+    }
+}
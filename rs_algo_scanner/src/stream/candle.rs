@@ -0,0 +1,124 @@
+use super::Tick;
+use crate::daemon;
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+
+pub type DOHLC = (DateTime<Local>, f64, f64, f64, f64, f64);
+
+/// Builds the current bar of a time frame from the bid prices of the ticks. Ticks
+/// carry no traded volume (`bidVolume` is the order book depth at the bid), so the
+/// bars built from ticks have a volume of 0. The broker bar the builder starts from
+/// keeps its own volume.
+#[derive(Debug, Clone)]
+pub struct CandleBuilder {
+    minutes: i64,
+    /// Broker bars may not start at the UTC boundaries (daily bars at midnight CET).
+    offset: chrono::Duration,
+    current: Option<DOHLC>,
+}
+
+impl CandleBuilder {
+    /// `current` is the last bar received from the broker, which may be unfinished.
+    pub fn new(minutes: i64, current: Option<DOHLC>) -> Self {
+        let offset = match &current {
+            Some(candle) => {
+                let date = candle.0.with_timezone(&Utc);
+                date - daemon::last_close(minutes, date)
+            }
+            None => chrono::Duration::zero(),
+        };
+
+        Self {
+            minutes,
+            offset,
+            current,
+        }
+    }
+
+    pub fn current(&self) -> Option<&DOHLC> {
+        self.current.as_ref()
+    }
+
+    /// Adds the tick to the current bar. Returns the previous bar when the tick opens
+    /// a new one. Ticks older than the current bar are ignored.
+    pub fn add(&mut self, tick: &Tick) -> Option<DOHLC> {
+        let date = Utc.timestamp_millis_opt(tick.timestamp).single()?;
+        let start = (daemon::last_close(self.minutes, date - self.offset) + self.offset)
+            .with_timezone(&Local);
+        let price = tick.bid;
+
+        match self.current.as_mut() {
+            Some(candle) if candle.0 == start => {
+                candle.2 = candle.2.max(price);
+                candle.3 = candle.3.min(price);
+                candle.4 = price;
+                None
+            }
+            Some(candle) if candle.0 > start => None,
+            _ => self
+                .current
+                .replace((start, price, price, price, price, 0.)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(symbol: &str, date: DateTime<Utc>, bid: f64) -> Tick {
+        Tick {
+            symbol: symbol.to_owned(),
+            timestamp: date.timestamp_millis(),
+            bid,
+            ask: bid + 0.1,
+            bid_volume: 1.,
+        }
+    }
+
+    fn date(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 9, 13, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn builds_bars_from_ticks() {
+        let mut builder = CandleBuilder::new(60, None);
+
+        assert_eq!(builder.add(&tick("EURUSD", date(10, 5), 1.10)), None);
+        assert_eq!(builder.add(&tick("EURUSD", date(10, 20), 1.12)), None);
+        assert_eq!(builder.add(&tick("EURUSD", date(10, 40), 1.09)), None);
+        assert_eq!(builder.add(&tick("EURUSD", date(10, 59), 1.11)), None);
+
+        let closed = builder.add(&tick("EURUSD", date(11, 0), 1.13)).unwrap();
+        assert_eq!(closed.0, date(10, 0).with_timezone(&Local));
+        assert_eq!(
+            (closed.1, closed.2, closed.3, closed.4),
+            (1.10, 1.12, 1.09, 1.11)
+        );
+        assert_eq!(closed.5, 0.);
+
+        // Late ticks don't reopen the closed bar
+        assert_eq!(builder.add(&tick("EURUSD", date(10, 59), 1.00)), None);
+        assert_eq!(builder.current().unwrap().4, 1.13);
+    }
+
+    #[test]
+    fn keeps_the_broker_bar_alignment() {
+        // Daily bars starting at 22:00 UTC
+        let open = Utc.with_ymd_and_hms(2023, 9, 12, 22, 0, 0).unwrap();
+        let mut builder = CandleBuilder::new(
+            1440,
+            Some((open.with_timezone(&Local), 1.1, 1.2, 1.0, 1.15, 10.)),
+        );
+
+        assert_eq!(builder.add(&tick("EURUSD", date(21, 59), 1.25)), None);
+        assert_eq!(builder.current().unwrap().2, 1.25);
+
+        let closed = builder.add(&tick("EURUSD", date(22, 0), 1.3)).unwrap();
+        assert_eq!((closed.4, closed.5), (1.25, 10.));
+        assert_eq!(
+            builder.current().unwrap().0,
+            date(22, 0).with_timezone(&Local)
+        );
+    }
+}
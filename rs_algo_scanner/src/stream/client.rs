@@ -0,0 +1,140 @@
+//! Streaming side of the broker protocol. The shared `websocket` client serves the
+//! request/response commands behind the `Broker` trait, which doesn't expose the
+//! stream session id, and the replay needs the server side of the same socket, so
+//! both run on tokio-tungstenite here. Only the stream commands are implemented.
+
+use super::{Tick, TickMessage};
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Command connection of the broker. The stream session is only valid while it's open.
+pub struct Session {
+    socket: Socket,
+    pub stream_session_id: String,
+}
+
+impl Session {
+    pub async fn login(url: &str, username: &str, password: &str) -> Result<Self> {
+        let (mut socket, _response) = connect_async(url).await.map_err(socket_error)?;
+
+        send(
+            &mut socket,
+            json!({
+                "command": "login",
+                "arguments": { "userId": username, "password": password }
+            }),
+        )
+        .await?;
+
+        let response = read_json(&mut socket).await?;
+        let stream_session_id = response
+            .get("streamSessionId")
+            .and_then(|id| id.as_str())
+            .ok_or_else(|| {
+                RsAlgoError::new(
+                    RsAlgoErrorKind::BrokerError,
+                    &["login failed: ", &response.to_string()].concat(),
+                )
+            })?
+            .to_owned();
+
+        Ok(Self {
+            socket,
+            stream_session_id,
+        })
+    }
+
+    pub async fn ping(&mut self) -> Result<()> {
+        send(&mut self.socket, json!({ "command": "ping" })).await?;
+        read_json(&mut self.socket).await.map(|_response| ())
+    }
+}
+
+/// Tick prices stream, either from the broker or from the replay stand-in.
+pub struct TickStream {
+    socket: Socket,
+    session_id: String,
+}
+
+impl TickStream {
+    pub async fn connect(url: &str, session_id: &str) -> Result<Self> {
+        let (socket, _response) = connect_async(url).await.map_err(socket_error)?;
+        Ok(Self {
+            socket,
+            session_id: session_id.to_owned(),
+        })
+    }
+
+    /// `min_arrival_time` is the minimum time between two ticks of the symbol, in ms.
+    pub async fn subscribe(&mut self, symbol: &str, min_arrival_time: u64) -> Result<()> {
+        send(
+            &mut self.socket,
+            json!({
+                "command": "getTickPrices",
+                "streamSessionId": self.session_id,
+                "symbol": symbol,
+                "minArrivalTime": min_arrival_time,
+                "maxLevel": 0
+            }),
+        )
+        .await
+    }
+
+    pub async fn ping(&mut self) -> Result<()> {
+        send(
+            &mut self.socket,
+            json!({ "command": "ping", "streamSessionId": self.session_id }),
+        )
+        .await
+    }
+
+    /// Next tick, `None` once the server closes the stream. Other messages are skipped.
+    pub async fn next(&mut self) -> Result<Option<Tick>> {
+        loop {
+            match self.socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(message) = serde_json::from_str::<TickMessage>(&text) {
+                        if message.command == "tickPrices" {
+                            return Ok(Some(message.data));
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => return Ok(None),
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(socket_error(err)),
+            }
+        }
+    }
+}
+
+async fn send(socket: &mut Socket, message: serde_json::Value) -> Result<()> {
+    socket
+        .send(Message::Text(message.to_string()))
+        .await
+        .map_err(socket_error)
+}
+
+async fn read_json(socket: &mut Socket) -> Result<serde_json::Value> {
+    while let Some(message) = socket.next().await {
+        if let Message::Text(text) = message.map_err(socket_error)? {
+            return serde_json::from_str(&text)
+                .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::BrokerError, &err.to_string()));
+        }
+    }
+
+    Err(RsAlgoError::new(
+        RsAlgoErrorKind::BrokerError,
+        "connection closed",
+    ))
+}
+
+pub fn socket_error(err: tokio_tungstenite::tungstenite::Error) -> RsAlgoError {
+    RsAlgoError::new(RsAlgoErrorKind::BrokerError, &err.to_string())
+}
@@ -0,0 +1,495 @@
+use super::candle::{CandleBuilder, DOHLC};
+use super::Tick;
+use crate::analysis::head_and_shoulders::HeadAndShouldersType;
+use crate::analysis::levels::LevelType;
+use crate::analysis::{self, Analysis, AnalysisContext, ScannedInstrument};
+use crate::api;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::helpers::slope_intercept::slope_intercept;
+use crate::prices::BreakDirection;
+
+use rs_algo_shared::broker::VEC_DOHLC;
+use rs_algo_shared::helpers::date::*;
+use rs_algo_shared::models::market::*;
+use rs_algo_shared::models::time_frame::TimeFrameType;
+use rs_algo_shared::scanner::instrument::Instrument;
+
+use serde::Serialize;
+use ta::indicators::{ExponentialMovingAverage, RelativeStrengthIndex};
+use ta::Next;
+
+// Same period as the instrument RSI
+const RSI_PERIOD: usize = 14;
+const RSI_OVERSOLD: f64 = 30.;
+const RSI_OVERBOUGHT: f64 = 70.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Trend {
+    Bullish,
+    Bearish,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum RsiZone {
+    Oversold,
+    Neutral,
+    Overbought,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum BreakLine {
+    Support,
+    Resistance,
+    UpperTrendline,
+    LowerTrendline,
+    Neckline,
+}
+
+/// Line crossed by the current bar since the last close.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveBreak {
+    pub line: BreakLine,
+    pub direction: BreakDirection,
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveUpdate {
+    pub symbol: String,
+    pub time_frame: String,
+    /// Current bar.
+    pub date: DbDateTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub ema_a: f64,
+    pub ema_b: f64,
+    pub rsi: f64,
+    pub trend: Trend,
+    pub rsi_zone: RsiZone,
+    pub breaks: Vec<LiveBreak>,
+    pub bar_closed: bool,
+}
+
+/// What has to change for an update to be pushed.
+#[derive(Debug, Clone, PartialEq)]
+struct LiveState {
+    date: DbDateTime,
+    trend: Trend,
+    rsi_zone: RsiZone,
+    breaks: Vec<LiveBreak>,
+}
+
+/// Indicators updated bar by bar. Ticks are evaluated on a copy so the unfinished
+/// bar is never committed.
+#[derive(Debug, Clone)]
+pub struct LiveIndicators {
+    ema_a: ExponentialMovingAverage,
+    ema_b: ExponentialMovingAverage,
+    rsi: RelativeStrengthIndex,
+}
+
+impl LiveIndicators {
+    pub fn new(ema_a: usize, ema_b: usize, closes: &[f64]) -> Self {
+        let mut indicators = Self {
+            ema_a: ExponentialMovingAverage::new(ema_a.max(1)).unwrap(),
+            ema_b: ExponentialMovingAverage::new(ema_b.max(1)).unwrap(),
+            rsi: RelativeStrengthIndex::new(RSI_PERIOD).unwrap(),
+        };
+
+        for close in closes {
+            indicators.commit(*close);
+        }
+
+        indicators
+    }
+
+    pub fn commit(&mut self, close: f64) -> (f64, f64, f64) {
+        (
+            self.ema_a.next(close),
+            self.ema_b.next(close),
+            self.rsi.next(close),
+        )
+    }
+
+    pub fn peek(&self, close: f64) -> (f64, f64, f64) {
+        self.clone().commit(close)
+    }
+}
+
+/// Instrument kept up to date from the price ticks. The analysis is recomputed on
+/// every bar close, and the unfinished bar is checked against it on every tick.
+#[derive(Debug)]
+pub struct LiveInstrument {
+    symbol: String,
+    market: Market,
+    time_frame: TimeFrameType,
+    /// Closed bars.
+    data: VEC_DOHLC,
+    max_len: usize,
+    /// EMA periods.
+    periods: (usize, usize),
+    candle: CandleBuilder,
+    indicators: LiveIndicators,
+    analysis: Analysis,
+    state: Option<LiveState>,
+}
+
+impl LiveInstrument {
+    pub fn new(
+        symbol: &str,
+        market: Market,
+        time_frame: TimeFrameType,
+        mut data: VEC_DOHLC,
+        indicators: (usize, usize),
+    ) -> Self {
+        let current = data.pop();
+        let closes: Vec<f64> = data.iter().map(|candle| candle.4).collect();
+
+        Self {
+            symbol: symbol.to_owned(),
+            market,
+            candle: CandleBuilder::new(time_frame.to_number(), current),
+            time_frame,
+            max_len: data.len(),
+            periods: indicators,
+            data,
+            indicators: LiveIndicators::new(indicators.0, indicators.1, &closes),
+            analysis: Analysis::default(),
+            state: None,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Adds the tick to the current bar. Returns true when it closed the previous one.
+    pub fn add_tick(&mut self, tick: &Tick) -> bool {
+        match self.candle.add(tick) {
+            Some(closed) => {
+                self.close_bar(closed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn close_bar(&mut self, closed: DOHLC) {
+        self.indicators.commit(closed.4);
+        self.data.push(closed);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        if self.max_len > 0 && self.data.len() > self.max_len {
+            self.data.drain(..self.data.len() - self.max_len);
+        }
+    }
+
+    /// Timestamp of the last closed bar.
+    pub fn last_closed(&self) -> Option<i64> {
+        self.data.last().map(|candle| candle.0.timestamp())
+    }
+
+    /// Replaces the bars from the first broker bar onwards, the last one being the
+    /// current bar, and rebuilds the indicators. The analysis is reset until the
+    /// next refresh.
+    pub fn resync(&mut self, newer_data: VEC_DOHLC) {
+        let first_date = match newer_data.first() {
+            Some(candle) => candle.0,
+            None => return,
+        };
+
+        let mut data: VEC_DOHLC = self
+            .data
+            .iter()
+            .filter(|candle| candle.0 < first_date)
+            .copied()
+            .collect();
+        data.extend(newer_data);
+
+        let max_len = self.max_len;
+        *self = Self::new(
+            &self.symbol,
+            self.market.to_owned(),
+            self.time_frame.to_owned(),
+            data,
+            self.periods,
+        );
+        self.max_len = max_len;
+        self.trim();
+    }
+
+    /// Recomputes the instrument and its analysis from the closed bars.
+    pub fn refresh(&mut self, context: &AnalysisContext) -> Result<ScannedInstrument> {
+        let mut instrument = Instrument::new()
+            .symbol(&self.symbol)
+            .market(self.market.to_owned())
+            .time_frame(self.time_frame.to_owned())
+            .build()
+            .map_err(|err| {
+                RsAlgoError::new(
+                    RsAlgoErrorKind::WrongInstrumentConf,
+                    &[&self.symbol, ": ", &err.to_string()].concat(),
+                )
+            })?;
+
+        instrument.set_data(self.data.clone()).map_err(|err| {
+            RsAlgoError::new(
+                RsAlgoErrorKind::WrongInstrumentConf,
+                &["can't process ", &self.symbol, ": ", &err.to_string()].concat(),
+            )
+        })?;
+
+        self.analysis = analysis::analyze(&instrument, context);
+
+        Ok(ScannedInstrument {
+            instrument,
+            analysis: self.analysis.clone(),
+        })
+    }
+
+    /// Update of the current bar, only when its trend, RSI zone or breaks changed
+    /// since the last one.
+    pub fn update(&mut self, bar_closed: bool) -> Option<LiveUpdate> {
+        let candle = *self.candle.current()?;
+        let (ema_a, ema_b, rsi) = self.indicators.peek(candle.4);
+
+        let trend = match ema_a >= ema_b {
+            true => Trend::Bullish,
+            false => Trend::Bearish,
+        };
+
+        let rsi_zone = match rsi {
+            rsi if rsi <= RSI_OVERSOLD => RsiZone::Oversold,
+            rsi if rsi >= RSI_OVERBOUGHT => RsiZone::Overbought,
+            _ => RsiZone::Neutral,
+        };
+
+        let state = LiveState {
+            date: to_dbtime(candle.0),
+            trend,
+            rsi_zone,
+            breaks: self.breaks(candle.4),
+        };
+
+        if self.state.as_ref() == Some(&state) {
+            return None;
+        }
+
+        let update = LiveUpdate {
+            symbol: api::backend_symbol(&self.symbol).to_owned(),
+            time_frame: self.time_frame.to_string(),
+            date: state.date,
+            open: candle.1,
+            high: candle.2,
+            low: candle.3,
+            close: candle.4,
+            volume: candle.5,
+            ema_a,
+            ema_b,
+            rsi,
+            trend,
+            rsi_zone,
+            breaks: state.breaks.clone(),
+            bar_closed,
+        };
+
+        self.state = Some(state);
+        Some(update)
+    }
+
+    /// Levels, trend lines and unbroken necklines between the last close and `price`.
+    fn breaks(&self, price: f64) -> Vec<LiveBreak> {
+        let last_close = match self.data.last() {
+            Some(candle) => candle.4,
+            None => return vec![],
+        };
+
+        let crossed = |line: BreakLine, line_price: f64, direction: Option<BreakDirection>| {
+            let crossed = match direction {
+                Some(BreakDirection::Up) | None
+                    if last_close < line_price && price >= line_price =>
+                {
+                    Some(BreakDirection::Up)
+                }
+                Some(BreakDirection::Down) | None
+                    if last_close > line_price && price <= line_price =>
+                {
+                    Some(BreakDirection::Down)
+                }
+                _ => None,
+            };
+
+            crossed.map(|direction| LiveBreak {
+                line,
+                direction,
+                price: line_price,
+            })
+        };
+
+        let index = self.data.len();
+        let analysis = &self.analysis;
+        let mut breaks = vec![];
+
+        for level in analysis.levels.iter() {
+            let line = match level.level_type {
+                LevelType::Support => BreakLine::Support,
+                LevelType::Resistance => BreakLine::Resistance,
+            };
+            breaks.extend(crossed(line, level.price, None));
+        }
+
+        if let Some(upper) = &analysis.trendlines.upper {
+            breaks.extend(crossed(
+                BreakLine::UpperTrendline,
+                upper.value_at(index),
+                Some(BreakDirection::Up),
+            ));
+        }
+
+        if let Some(lower) = &analysis.trendlines.lower {
+            breaks.extend(crossed(
+                BreakLine::LowerTrendline,
+                lower.value_at(index),
+                Some(BreakDirection::Down),
+            ));
+        }
+
+        for pattern in analysis
+            .head_and_shoulders
+            .iter()
+            .filter(|pattern| pattern.neckline_break.is_none())
+        {
+            let (a, b) = (pattern.neckline[0], pattern.neckline[1]);
+            let (slope, y_intercept) = slope_intercept(a.0 as f64, a.1, b.0 as f64, b.1);
+            let direction = match pattern.pattern_type {
+                HeadAndShouldersType::HeadAndShoulders => BreakDirection::Down,
+                HeadAndShouldersType::InverseHeadAndShoulders => BreakDirection::Up,
+            };
+            breaks.extend(crossed(
+                BreakLine::Neckline,
+                slope * index as f64 + y_intercept,
+                Some(direction),
+            ));
+        }
+
+        breaks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::levels::Level;
+    use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+    use rs_algo_shared::models::time_frame::TimeFrame;
+
+    fn date(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 9, 13, hour, minute, 0).unwrap()
+    }
+
+    fn tick(date: DateTime<Utc>, bid: f64) -> Tick {
+        Tick {
+            symbol: String::from("EURUSD"),
+            timestamp: date.timestamp_millis(),
+            bid,
+            ask: bid,
+            bid_volume: 1.,
+        }
+    }
+
+    /// H1 bars closing at 100 up to 10:00, the last one unfinished.
+    fn live() -> LiveInstrument {
+        let data: VEC_DOHLC = (0..=10)
+            .map(|hour| {
+                let date = (date(0, 0) + Duration::hours(hour)).with_timezone(&Local);
+                (date, 100., 101., 99., 100., 10.)
+            })
+            .collect();
+
+        let mut live =
+            LiveInstrument::new("EURUSD", Market::Forex, TimeFrame::new("H1"), data, (3, 5));
+        live.analysis.levels.push(Level {
+            level_type: LevelType::Resistance,
+            price: 102.,
+            touches: 3,
+            strength: 1.,
+            distance: 2.,
+            points: vec![],
+            last_touch: to_dbtime(Local::now()),
+        });
+        live
+    }
+
+    #[test]
+    fn indicators_peek_without_committing() {
+        let indicators = LiveIndicators::new(3, 5, &[1., 2., 3., 4.]);
+        let peeked = indicators.peek(10.);
+        assert_eq!(indicators.peek(10.), peeked);
+
+        let mut committed = indicators.clone();
+        assert_eq!(committed.commit(10.), peeked);
+        assert_ne!(committed.peek(10.), peeked);
+    }
+
+    #[test]
+    fn updates_only_on_changes() {
+        let mut live = live();
+
+        assert!(!live.add_tick(&tick(date(10, 5), 100.5)));
+        let first = live.update(false).unwrap();
+        assert_eq!(first.close, 100.5);
+        assert!(first.breaks.is_empty());
+
+        assert!(!live.add_tick(&tick(date(10, 10), 100.6)));
+        assert_eq!(live.update(false), None);
+
+        // Resistance broken
+        assert!(!live.add_tick(&tick(date(10, 20), 102.5)));
+        let update = live.update(false).unwrap();
+        assert_eq!(update.breaks.len(), 1);
+        assert_eq!(update.breaks[0].line, BreakLine::Resistance);
+        assert_eq!(update.breaks[0].direction, BreakDirection::Up);
+        assert_eq!(update.high, 102.5);
+    }
+
+    #[test]
+    fn resync_replaces_the_bars_built_from_ticks() {
+        let mut live = live();
+        live.add_tick(&tick(date(10, 30), 100.5));
+        assert!(live.add_tick(&tick(date(11, 0), 100.6)));
+        assert_eq!(live.last_closed(), Some(date(10, 0).timestamp()));
+
+        let bar = |hour: i64, close: f64| {
+            let date = (date(0, 0) + Duration::hours(hour)).with_timezone(&Local);
+            (date, 100., 103., 98., close, 20.)
+        };
+        live.resync(vec![bar(10, 101.), bar(11, 102.), bar(12, 102.5)]);
+
+        assert_eq!(live.data.len(), 10);
+        assert_eq!(live.data.last(), Some(&bar(11, 102.)));
+        assert_eq!(live.candle.current(), Some(&bar(12, 102.5)));
+        assert_eq!(live.last_closed(), Some(date(11, 0).timestamp()));
+    }
+
+    #[test]
+    fn bar_close_commits_the_bar() {
+        let mut live = live();
+        live.add_tick(&tick(date(10, 30), 102.5));
+        live.update(false);
+
+        assert!(live.add_tick(&tick(date(11, 0), 102.6)));
+        assert_eq!(live.data.len(), 10);
+        assert_eq!(live.data.last().unwrap().4, 102.5);
+
+        // Measured from the new last close, the resistance is behind
+        let update = live.update(true).unwrap();
+        assert!(update.bar_closed);
+        assert!(update.breaks.is_empty());
+        assert_eq!(update.open, 102.6);
+    }
+}
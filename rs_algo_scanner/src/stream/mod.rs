@@ -0,0 +1,338 @@
+use crate::analysis::AnalysisContext;
+use crate::daemon;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+use crate::outbox::{self, Outbox};
+use crate::retry::Backoff;
+use crate::screener::Screener;
+use crate::sinks::Sinks;
+use crate::universe::Universe;
+
+use rs_algo_shared::broker::Broker;
+use rs_algo_shared::models::mode::ExecutionMode;
+use rs_algo_shared::models::time_frame::*;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod candle;
+pub mod client;
+pub mod live;
+pub mod replay;
+
+use client::{Session, TickStream};
+use live::LiveInstrument;
+use replay::ReplayServer;
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tick {
+    pub symbol: String,
+    /// Milliseconds.
+    pub timestamp: i64,
+    pub bid: f64,
+    pub ask: f64,
+    /// Order book depth at the bid, not traded volume.
+    #[serde(default)]
+    pub bid_volume: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickMessage {
+    pub command: String,
+    pub data: Tick,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamParams {
+    pub enabled: bool,
+    pub url: String,
+    pub time_frame: TimeFrameType,
+    /// Minimum time between two ticks of a symbol, in ms.
+    pub min_arrival_time: u64,
+    /// Recording replayed by a local stand-in instead of connecting to the broker.
+    pub replay_file: Option<String>,
+    pub replay_speed: f64,
+    /// Appends the received ticks, in the format expected by the replay.
+    pub record_file: Option<String>,
+    pub ema_a: usize,
+    pub ema_b: usize,
+}
+
+impl StreamParams {
+    pub fn from_env() -> Self {
+        let enabled = env::var("SCANNER_STREAMING")
+            .unwrap()
+            .parse::<bool>()
+            .unwrap();
+
+        let min_arrival_time = env::var("STREAM_MIN_ARRIVAL_TIME")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+
+        let replay_speed = env::var("STREAM_REPLAY_SPEED")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+
+        let optional = |name: &str| Some(env::var(name).unwrap()).filter(|value| !value.is_empty());
+
+        Self {
+            enabled,
+            url: env::var("STREAM_URL").unwrap(),
+            time_frame: TimeFrame::new(&env::var("STREAM_TIME_FRAME").unwrap()),
+            min_arrival_time,
+            replay_file: optional("STREAM_REPLAY_FILE"),
+            replay_speed,
+            record_file: optional("STREAM_RECORD_FILE"),
+            ema_a: env::var("EMA_A").unwrap().parse::<usize>().unwrap(),
+            ema_b: env::var("EMA_B").unwrap().parse::<usize>().unwrap(),
+        }
+    }
+}
+
+/// Streams the ticks of the universe symbols. Every change of a symbol is pushed
+/// to the backend, and the instrument is written to the sinks on every bar close.
+pub async fn run<BK: Broker>(params: StreamParams, context: Arc<AnalysisContext>) -> Result<()> {
+    let username = &env::var("BROKER_USERNAME").unwrap_or_default();
+    let password = &env::var("BROKER_PASSWORD").unwrap_or_default();
+    let num_bars = env::var("NUM_BARS").unwrap().parse::<i64>().unwrap();
+
+    let mut screener = Screener::<BK>::new(context.clone()).await?;
+    screener.login(username, password).await?;
+    let symbols = screener.get_symbols().await?.symbols;
+    let universe = Universe::from_env(false)?.resolve(symbols).await?;
+
+    let start_date =
+        TimeFrame::get_starting_bar(num_bars, &params.time_frame, &ExecutionMode::Scanner)
+            .timestamp();
+
+    let mut instruments: HashMap<String, LiveInstrument> = HashMap::new();
+    for (s, market) in universe {
+        let data = match screener
+            .get_history(&s.symbol, &params.time_frame, start_date)
+            .await
+        {
+            Ok(data) => data,
+            Err(err) => {
+                log::error!("[STREAM] {} skipped: {}", s.symbol, err);
+                continue;
+            }
+        };
+
+        let mut live = LiveInstrument::new(
+            &s.symbol,
+            market,
+            params.time_frame.to_owned(),
+            data,
+            (params.ema_a, params.ema_b),
+        );
+
+        match live.refresh(&context) {
+            Ok(_scanned) => {
+                instruments.insert(s.symbol.clone(), live);
+            }
+            Err(err) => log::error!("[STREAM] {} skipped: {}", s.symbol, err),
+        }
+    }
+
+    let outbox = Arc::new(Outbox::from_env()?);
+    let sinks = Sinks::from_env(false, outbox.clone())?;
+    let url = [&env::var("BACKEND_INSTRUMENTS_ENDPOINT").unwrap(), "/live"].concat();
+
+    let symbols: Vec<String> = instruments.keys().cloned().collect();
+    let mut shutdown = daemon::shutdown_signal();
+    let mut backoff = screener.backoff();
+    let mut reconnecting = false;
+
+    log::info!(
+        "[STREAM] Streaming {} {} instruments",
+        symbols.len(),
+        params.time_frame
+    );
+
+    loop {
+        let (mut stream, mut session) = match connect(&params, &symbols, username, password).await {
+            Ok(connection) => connection,
+            Err(err) => {
+                log::warn!(
+                    "[STREAM] Connection attempt {} failed: {}",
+                    backoff.attempt(),
+                    err
+                );
+                match err.is_retryable() && backoff.wait().await {
+                    true => continue,
+                    false => return Err(err),
+                }
+            }
+        };
+        backoff = screener.backoff();
+
+        // Ticks were lost while the stream was down
+        if reconnecting {
+            resync(&mut screener, &mut instruments, &params, &context, &sinks).await;
+        }
+        reconnecting = true;
+
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        let ended = loop {
+            let tick = tokio::select! {
+                tick = stream.next() => tick,
+                _ = ping.tick() => {
+                    let pinged = match session.as_mut() {
+                        Some(session) => session.ping().await,
+                        None => Ok(()),
+                    };
+                    match pinged.and(stream.ping().await) {
+                        Ok(()) => continue,
+                        Err(err) => Err(err),
+                    }
+                }
+                _ = shutdown.changed() => return Ok(()),
+            };
+
+            let tick = match tick {
+                Ok(Some(tick)) => tick,
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            };
+
+            let live = match instruments.get_mut(&tick.symbol) {
+                Some(live) => live,
+                None => continue,
+            };
+
+            if let Some(record_file) = &params.record_file {
+                record(record_file, &tick);
+            }
+
+            let bar_closed = live.add_tick(&tick);
+            if bar_closed {
+                match live.refresh(&context) {
+                    Ok(scanned) => {
+                        if let Err(err) = sinks.write(&scanned).await {
+                            log::error!("[STREAM] Can't write {}: {}", live.symbol(), err);
+                        }
+                    }
+                    Err(err) => log::error!("[STREAM] Can't refresh {}: {}", live.symbol(), err),
+                }
+            }
+
+            if let Some(update) = live.update(bar_closed) {
+                // Stale soon, so it's not worth queueing in the outbox
+                if let Err(err) = outbox::upload(&url, &update).await {
+                    log::error!("[STREAM] Can't upload {}: {}", live.symbol(), err);
+                }
+            }
+        };
+
+        match (&params.replay_file, ended) {
+            (Some(_replay_file), _) => {
+                log::info!("[STREAM] Replay finished");
+                return Ok(());
+            }
+            (None, Ok(())) => log::warn!("[STREAM] Stream closed by the broker, reconnecting"),
+            (None, Err(err)) => log::warn!("[STREAM] Stream failed, reconnecting: {}", err),
+        }
+    }
+}
+
+/// Rebuilds every instrument with the broker bars since its last closed bar and
+/// writes them to the sinks.
+async fn resync<BK: Broker>(
+    screener: &mut Screener<BK>,
+    instruments: &mut HashMap<String, LiveInstrument>,
+    params: &StreamParams,
+    context: &AnalysisContext,
+    sinks: &Sinks,
+) {
+    for (symbol, live) in instruments.iter_mut() {
+        let since = match live.last_closed() {
+            Some(since) => since,
+            None => continue,
+        };
+
+        match screener
+            .get_history(symbol, &params.time_frame, since)
+            .await
+        {
+            Ok(data) => live.resync(data),
+            Err(err) => {
+                log::error!("[STREAM] Can't resync {}: {}", symbol, err);
+                continue;
+            }
+        }
+
+        match live.refresh(context) {
+            Ok(scanned) => {
+                if let Err(err) = sinks.write(&scanned).await {
+                    log::error!("[STREAM] Can't write {}: {}", symbol, err);
+                }
+            }
+            Err(err) => log::error!("[STREAM] Can't refresh {}: {}", symbol, err),
+        }
+    }
+}
+
+async fn connect(
+    params: &StreamParams,
+    symbols: &[String],
+    username: &str,
+    password: &str,
+) -> Result<(TickStream, Option<Session>)> {
+    let (mut stream, session) = match &params.replay_file {
+        Some(replay_file) => {
+            let server = ReplayServer::bind(
+                replay::load(replay_file)?,
+                params.replay_speed,
+                symbols.len(),
+            )
+            .await?;
+            let url = server.url()?;
+            server.spawn();
+            log::info!("[STREAM] Replaying {} on {}", replay_file, url);
+            (TickStream::connect(&url, "replay").await?, None)
+        }
+        None => {
+            let session =
+                Session::login(&env::var("BROKER_URL").unwrap(), username, password).await?;
+            let stream = TickStream::connect(&params.url, &session.stream_session_id).await?;
+            (stream, Some(session))
+        }
+    };
+
+    for symbol in symbols {
+        stream.subscribe(symbol, params.min_arrival_time).await?;
+    }
+
+    Ok((stream, session))
+}
+
+fn record(record_file: &str, tick: &Tick) {
+    let message = TickMessage {
+        command: String::from("tickPrices"),
+        data: tick.clone(),
+    };
+
+    let recorded = serde_json::to_string(&message)
+        .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))
+        .and_then(|line| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(record_file)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::RequestError, &err.to_string()))
+        });
+
+    if let Err(err) = recorded {
+        log::error!("[STREAM] Can't record to {}: {}", record_file, err);
+    }
+}
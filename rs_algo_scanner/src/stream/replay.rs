@@ -0,0 +1,228 @@
+use super::client::socket_error;
+use super::{Tick, TickMessage};
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+
+use futures::{SinkExt, StreamExt};
+use std::collections::HashSet;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Local stand-in for the broker stream. Replays the recorded ticks of the
+/// subscribed symbols to every client and closes the connection at the end.
+pub struct ReplayServer {
+    listener: TcpListener,
+    ticks: Arc<Vec<Tick>>,
+    /// Replay speed relative to the recording. 0 sends the ticks without waiting.
+    speed: f64,
+    /// Symbols every client subscribes to before the replay starts.
+    subscriptions: usize,
+}
+
+impl ReplayServer {
+    pub async fn bind(ticks: Vec<Tick>, speed: f64, subscriptions: usize) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::BrokerError, &err.to_string()))?;
+
+        Ok(Self {
+            listener,
+            ticks: Arc::new(ticks),
+            speed,
+            subscriptions,
+        })
+    }
+
+    pub fn url(&self) -> Result<String> {
+        let address = self
+            .listener
+            .local_addr()
+            .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::BrokerError, &err.to_string()))?;
+        Ok(["ws://", &address.to_string()].concat())
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Ok((stream, _address)) = self.listener.accept().await {
+                let ticks = self.ticks.clone();
+                let speed = self.speed;
+                let subscriptions = self.subscriptions;
+                tokio::spawn(async move {
+                    if let Err(err) = replay(stream, ticks, speed, subscriptions).await {
+                        log::error!("[REPLAY] {}", err);
+                    }
+                });
+            }
+        })
+    }
+}
+
+/// Reads a recording, one `tickPrices` message per line.
+pub fn load(path: &str) -> Result<Vec<Tick>> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        RsAlgoError::new(
+            RsAlgoErrorKind::WrongInstrumentConf,
+            &[path, ": ", &err.to_string()].concat(),
+        )
+    })?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<TickMessage>(line)
+                .map(|message| message.data)
+                .map_err(|err| {
+                    RsAlgoError::new(
+                        RsAlgoErrorKind::WrongInstrumentConf,
+                        &[path, ": ", &err.to_string()].concat(),
+                    )
+                })
+        })
+        .collect()
+}
+
+async fn replay(
+    stream: TcpStream,
+    ticks: Arc<Vec<Tick>>,
+    speed: f64,
+    subscriptions: usize,
+) -> Result<()> {
+    let socket = accept_async(stream).await.map_err(socket_error)?;
+    let (mut sender, mut receiver) = socket.split();
+
+    let subscribed = Arc::new(Mutex::new(HashSet::new()));
+    let subscription = Arc::new(Notify::new());
+
+    let mut reader = {
+        let subscribed = subscribed.clone();
+        let subscription = subscription.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(message)) = receiver.next().await {
+                let command: serde_json::Value = match message {
+                    Message::Text(text) => match serde_json::from_str(&text) {
+                        Ok(command) => command,
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+
+                if command["command"] == "getTickPrices" {
+                    if let Some(symbol) = command["symbol"].as_str() {
+                        subscribed.lock().unwrap().insert(symbol.to_owned());
+                        subscription.notify_one();
+                    }
+                }
+            }
+        })
+    };
+
+    while subscribed.lock().unwrap().len() < subscriptions {
+        tokio::select! {
+            _ = subscription.notified() => {}
+            // Closed before subscribing to every symbol
+            _ = &mut reader => return Ok(()),
+        }
+    }
+
+    let mut previous: Option<i64> = None;
+    for tick in ticks.iter() {
+        if let (Some(previous), true) = (previous, speed > 0.) {
+            let wait = (tick.timestamp - previous).max(0) as f64 / speed;
+            tokio::time::sleep(Duration::from_millis(wait as u64)).await;
+        }
+        previous = Some(tick.timestamp);
+
+        if !subscribed.lock().unwrap().contains(&tick.symbol) {
+            continue;
+        }
+
+        let message = TickMessage {
+            command: String::from("tickPrices"),
+            data: tick.clone(),
+        };
+        let text = serde_json::to_string(&message)
+            .map_err(|err| RsAlgoError::new(RsAlgoErrorKind::BrokerError, &err.to_string()))?;
+        sender
+            .send(Message::Text(text))
+            .await
+            .map_err(socket_error)?;
+    }
+
+    sender
+        .send(Message::Close(None))
+        .await
+        .map_err(socket_error)?;
+    reader.abort();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::client::TickStream;
+    use super::*;
+
+    fn tick(symbol: &str, timestamp: i64, bid: f64) -> Tick {
+        Tick {
+            symbol: symbol.to_owned(),
+            timestamp,
+            bid,
+            ask: bid,
+            bid_volume: 0.,
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_the_subscribed_symbols() {
+        let ticks = vec![
+            tick("EURUSD", 1_000, 1.10),
+            tick("BITCOIN", 1_500, 26_000.),
+            tick("EURUSD", 2_000, 1.11),
+            tick("EURUSD", 3_000, 1.12),
+        ];
+
+        let server = ReplayServer::bind(ticks, 0., 1).await.unwrap();
+        let url = server.url().unwrap();
+        let handle = server.spawn();
+
+        let mut stream = TickStream::connect(&url, "replay").await.unwrap();
+        stream.subscribe("EURUSD", 0).await.unwrap();
+
+        let mut received = vec![];
+        while let Some(tick) = stream.next().await.unwrap() {
+            received.push(tick);
+        }
+        handle.abort();
+
+        let bids: Vec<f64> = received.iter().map(|tick| tick.bid).collect();
+        assert_eq!(bids, vec![1.10, 1.11, 1.12]);
+        assert!(received.iter().all(|tick| tick.symbol == "EURUSD"));
+    }
+
+    #[tokio::test]
+    async fn waits_for_every_subscription() {
+        let ticks = vec![tick("EURUSD", 1_000, 1.10), tick("BITCOIN", 1_500, 26_000.)];
+
+        let server = ReplayServer::bind(ticks, 0., 2).await.unwrap();
+        let url = server.url().unwrap();
+        let handle = server.spawn();
+
+        let mut stream = TickStream::connect(&url, "replay").await.unwrap();
+        stream.subscribe("EURUSD", 0).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        stream.subscribe("BITCOIN", 0).await.unwrap();
+
+        let mut received = vec![];
+        while let Some(tick) = stream.next().await.unwrap() {
+            received.push(tick.symbol);
+        }
+        handle.abort();
+
+        assert_eq!(received, vec!["EURUSD", "BITCOIN"]);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::api;
+use crate::error::{Result, RsAlgoError, RsAlgoErrorKind};
+
+use rs_algo_shared::broker::Symbol;
+use rs_algo_shared::helpers::comp::symbol_in_list;
+use rs_algo_shared::helpers::symbols::{crypto, forex, sp500};
+use rs_algo_shared::models::market::*;
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::env;
+
+#[derive(Debug)]
+pub struct Universe {
+    symbols: Vec<String>,
+    symbol_pattern: Option<Regex>,
+    category_pattern: Option<Regex>,
+    markets: Vec<Market>,
+    watch_list: bool,
+    portfolio: bool,
+    known_symbols_only: bool,
+}
+
+impl Universe {
+    pub fn from_env(backtest_mode: bool) -> Result<Self> {
+        let symbols: Vec<String> = split_list(&env::var("UNIVERSE_SYMBOLS").unwrap())
+            .map(|symbol| symbol.to_owned())
+            .collect();
+
+        let markets: Vec<Market> = split_list(&env::var("UNIVERSE_MARKETS").unwrap())
+            .map(|market| match market {
+                "Forex" => Market::Forex,
+                "Crypto" => Market::Crypto,
+                _ => Market::Stock,
+            })
+            .collect();
+
+        let watch_list = env::var("UNIVERSE_WATCH_LIST")
+            .unwrap()
+            .parse::<bool>()
+            .unwrap();
+
+        let portfolio = env::var("UNIVERSE_PORTFOLIO")
+            .unwrap()
+            .parse::<bool>()
+            .unwrap();
+
+        Ok(Self {
+            symbols,
+            symbol_pattern: parse_pattern(&env::var("UNIVERSE_SYMBOL_PATTERN").unwrap())?,
+            category_pattern: parse_pattern(&env::var("UNIVERSE_CATEGORY_PATTERN").unwrap())?,
+            markets,
+            watch_list,
+            portfolio,
+            known_symbols_only: backtest_mode,
+        })
+    }
+
+    /// Markets the universe is restricted to, all of them when none is configured.
+    pub fn markets(&self) -> Vec<Market> {
+        match self.markets.is_empty() {
+            true => vec![Market::Stock, Market::Forex, Market::Crypto],
+            false => self.markets.clone(),
+        }
+    }
+
+    fn has_selectors(&self) -> bool {
+        !self.symbols.is_empty()
+            || self.symbol_pattern.is_some()
+            || self.category_pattern.is_some()
+            || self.watch_list
+            || self.portfolio
+    }
+
+    pub async fn resolve(&self, symbols: Vec<Symbol>) -> Result<Vec<(Symbol, Market)>> {
+        let total = symbols.len();
+        let mut selected: HashSet<String> = HashSet::new();
+
+        if self.has_selectors() {
+            let explicit = self.select(&symbols, &mut selected, |s| {
+                self.symbols
+                    .iter()
+                    .any(|symbol| symbol == &s.symbol || symbol == api::backend_symbol(&s.symbol))
+            });
+
+            let by_symbol = match &self.symbol_pattern {
+                Some(pattern) => {
+                    self.select(&symbols, &mut selected, |s| pattern.is_match(&s.symbol))
+                }
+                None => 0,
+            };
+
+            let by_category = match &self.category_pattern {
+                Some(pattern) => {
+                    self.select(&symbols, &mut selected, |s| pattern.is_match(&s.category))
+                }
+                None => 0,
+            };
+
+            let watch_list = match self.watch_list {
+                true => {
+                    let watch_list = api::get_watch_list().await?;
+                    self.select(&symbols, &mut selected, |s| {
+                        watch_list.contains(api::backend_symbol(&s.symbol))
+                    })
+                }
+                false => 0,
+            };
+
+            let portfolio = match self.portfolio {
+                true => {
+                    let portfolio = api::get_portfolio().await?;
+                    self.select(&symbols, &mut selected, |s| {
+                        portfolio.contains(api::backend_symbol(&s.symbol))
+                    })
+                }
+                false => 0,
+            };
+
+            log::info!(
+                "[UNIVERSE] {} broker symbols. Explicit {}, symbol pattern {}, category pattern {}, watch list {}, portfolio {}",
+                total,
+                explicit,
+                by_symbol,
+                by_category,
+                watch_list,
+                portfolio
+            );
+        } else {
+            log::info!(
+                "[UNIVERSE] {} broker symbols. No selectors, using all of them",
+                total
+            );
+        }
+
+        let sp500_symbols = sp500::get_symbols();
+        let forex_symbols = forex::get_symbols();
+        let crypto_symbols = crypto::get_symbols();
+        let has_selectors = self.has_selectors();
+
+        let universe: Vec<(Symbol, Market)> = symbols
+            .into_iter()
+            .filter(|s| !has_selectors || selected.contains(&s.symbol))
+            .filter_map(|s| {
+                let market = if symbol_in_list(&s.symbol, &sp500_symbols) {
+                    Some(Market::Stock)
+                } else if symbol_in_list(&s.symbol, &forex_symbols) {
+                    Some(Market::Forex)
+                } else if symbol_in_list(&s.symbol, &crypto_symbols) {
+                    Some(Market::Crypto)
+                } else if self.known_symbols_only {
+                    None
+                } else {
+                    Some(category_market(&s.category))
+                };
+
+                market.map(|market| (s, market))
+            })
+            .filter(|(_s, market)| self.markets.is_empty() || self.markets.contains(market))
+            .collect();
+
+        log::info!(
+            "[UNIVERSE] {} symbols selected. Markets {:?}, known symbols only {}",
+            universe.len(),
+            self.markets,
+            self.known_symbols_only
+        );
+
+        Ok(universe)
+    }
+
+    fn select<P>(&self, symbols: &[Symbol], selected: &mut HashSet<String>, predicate: P) -> usize
+    where
+        P: Fn(&Symbol) -> bool,
+    {
+        symbols
+            .iter()
+            .filter(|s| predicate(s))
+            .map(|s| selected.insert(s.symbol.clone()))
+            .count()
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+}
+
+fn parse_pattern(pattern: &str) -> Result<Option<Regex>> {
+    match pattern.is_empty() {
+        true => Ok(None),
+        false => Regex::new(pattern).map(Some).map_err(|err| {
+            RsAlgoError::new(
+                RsAlgoErrorKind::WrongInstrumentConf,
+                &["invalid universe pattern ", pattern, ": ", &err.to_string()].concat(),
+            )
+        }),
+    }
+}
+
+fn category_market(category: &str) -> Market {
+    match category {
+        "FX" => Market::Forex,
+        "CRT" => Market::Crypto,
+        _ => Market::Stock,
+    }
+}
@@ -0,0 +1,20 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Sets the environment of a chart config (`KEY: "value"` lines), so the tests run
+/// with the same settings as the deployments.
+pub fn load(path: &Path) -> std::io::Result<()> {
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            env::set_var(name.trim(), value.trim().trim_matches('"'));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,404 @@
+//! Seeded synthetic market data, shared by the synthetic broker of the scanner and
+//! the tests of the scanner and the backtest.
+
+pub mod config;
+
+use chrono::{DateTime, Local, Utc};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Same layout as the broker bars: date, open, high, low, close and volume.
+pub type DOHLC = (DateTime<Local>, f64, f64, f64, f64, f64);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SyntheticError {
+    #[error("unknown synthetic model {0}")]
+    UnknownModel(String),
+    #[error("wrong synthetic shape {0}")]
+    WrongShape(String),
+}
+
+pub type Result<T> = std::result::Result<T, SyntheticError>;
+
+/// Seeded random numbers (SplitMix64), so a seed gives the same series on every
+/// platform and dependency version.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1].
+    pub fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal (Box-Muller).
+    pub fn normal(&mut self) -> f64 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// FNV-1a, used to derive the seed of every symbol from the base seed.
+pub fn hash(value: &str) -> u64 {
+    value.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// Price process of the closes. Drifts and volatilities are per bar.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Model {
+    /// Geometric Brownian motion.
+    Gbm { drift: f64, volatility: f64 },
+    /// Geometric Brownian motion whose drift and volatility switch between regimes.
+    RegimeSwitching {
+        regimes: Vec<(f64, f64)>,
+        switch_probability: f64,
+    },
+    /// Ornstein-Uhlenbeck process of the log price around `mean`.
+    MeanReverting {
+        mean: f64,
+        speed: f64,
+        volatility: f64,
+    },
+}
+
+impl FromStr for Model {
+    type Err = SyntheticError;
+
+    /// Models with their default parameters: `gbm`, `regime` and `mean_reverting`.
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "gbm" => Ok(Model::Gbm {
+                drift: 0.0003,
+                volatility: 0.015,
+            }),
+            "regime" => Ok(Model::RegimeSwitching {
+                regimes: vec![(0.002, 0.01), (0., 0.02), (-0.002, 0.015)],
+                switch_probability: 0.02,
+            }),
+            "mean_reverting" => Ok(Model::MeanReverting {
+                mean: 100.,
+                speed: 0.05,
+                volatility: 0.01,
+            }),
+            _ => Err(SyntheticError::UnknownModel(name.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShapeType {
+    DoubleBottom,
+    DoubleTop,
+    HeadAndShoulders,
+    InverseHeadAndShoulders,
+}
+
+impl ShapeType {
+    /// Turning points as (fraction of the width, price change from the first bar).
+    /// Every shape ends past its neckline.
+    fn anchors(&self, size: f64) -> Vec<(f64, f64)> {
+        match self {
+            ShapeType::DoubleBottom => vec![
+                (0., 0.),
+                (0.25, -size),
+                (0.5, -size / 2.),
+                (0.75, -size),
+                (1., size / 4.),
+            ],
+            ShapeType::DoubleTop => vec![
+                (0., 0.),
+                (0.25, size),
+                (0.5, size / 2.),
+                (0.75, size),
+                (1., -size / 4.),
+            ],
+            ShapeType::HeadAndShoulders => vec![
+                (0., 0.),
+                (1. / 6., size / 2.),
+                (2. / 6., 0.),
+                (3. / 6., size),
+                (4. / 6., 0.),
+                (5. / 6., size / 2.),
+                (1., -size / 4.),
+            ],
+            ShapeType::InverseHeadAndShoulders => vec![
+                (0., 0.),
+                (1. / 6., -size / 2.),
+                (2. / 6., 0.),
+                (3. / 6., -size),
+                (4. / 6., 0.),
+                (5. / 6., -size / 2.),
+                (1., size / 4.),
+            ],
+        }
+    }
+}
+
+/// Scripted shape drawn over the model from bar `at` to bar `at + width`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    pub shape_type: ShapeType,
+    pub at: usize,
+    pub width: usize,
+    /// Relative size of the shape (depth of the bottoms, height of the head...).
+    pub size: f64,
+}
+
+impl Shape {
+    pub fn new(shape_type: ShapeType, at: usize) -> Self {
+        Self {
+            shape_type,
+            at,
+            width: 60,
+            size: 0.1,
+        }
+    }
+
+    /// Relative price change from the first bar, `step` bars into the shape.
+    fn offset(&self, step: usize) -> f64 {
+        let x = step as f64 / self.width as f64;
+        self.shape_type
+            .anchors(self.size)
+            .windows(2)
+            .find(|pair| x <= pair[1].0)
+            .map(|pair| {
+                let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+                y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Shape {
+    type Err = SyntheticError;
+
+    /// `double_bottom@200`, optionally followed by the width and the size:
+    /// `head_and_shoulders@150:90:0.2`.
+    fn from_str(value: &str) -> Result<Self> {
+        let wrong = || SyntheticError::WrongShape(value.to_owned());
+
+        let (name, rest) = value.split_once('@').ok_or_else(wrong)?;
+        let shape_type = match name {
+            "double_bottom" => ShapeType::DoubleBottom,
+            "double_top" => ShapeType::DoubleTop,
+            "head_and_shoulders" => ShapeType::HeadAndShoulders,
+            "inverse_head_and_shoulders" => ShapeType::InverseHeadAndShoulders,
+            _ => return Err(wrong()),
+        };
+
+        let mut fields = rest.split(':');
+        let at = fields
+            .next()
+            .and_then(|at| at.parse::<usize>().ok())
+            .ok_or_else(wrong)?;
+        let mut shape = Shape::new(shape_type, at);
+
+        if let Some(width) = fields.next() {
+            shape.width = width.parse::<usize>().map_err(|_e| wrong())?.max(1);
+        }
+        if let Some(size) = fields.next() {
+            shape.size = size.parse::<f64>().map_err(|_e| wrong())?;
+        }
+
+        Ok(shape)
+    }
+}
+
+/// Reproducible OHLCV series: the same settings always give the same prices.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub seed: u64,
+    pub model: Model,
+    pub start_price: f64,
+    pub shapes: Vec<Shape>,
+    /// Standard deviation of the closes around the shapes.
+    pub shape_noise: f64,
+    /// Standard deviation of the wicks beyond the open and the close.
+    pub wick: f64,
+    pub volume: f64,
+}
+
+impl Generator {
+    pub fn new(seed: u64, model: Model) -> Self {
+        Self {
+            seed,
+            model,
+            start_price: 100.,
+            shapes: vec![],
+            shape_noise: 0.002,
+            wick: 0.003,
+            volume: 1000.,
+        }
+    }
+
+    pub fn shape(mut self, shape: Shape) -> Self {
+        self.shapes.push(shape);
+        self
+    }
+
+    pub fn closes(&self, len: usize) -> Vec<f64> {
+        let mut rng = Rng::new(self.seed);
+        let mut regime = 0;
+        let mut closes: Vec<f64> = Vec::with_capacity(len);
+
+        for index in 0..len {
+            let previous = closes.last().copied().unwrap_or(self.start_price);
+            let noise = rng.normal();
+
+            let shape = self
+                .shapes
+                .iter()
+                .find(|shape| index > shape.at && index <= shape.at + shape.width);
+
+            let close = match (shape, closes.get(shape.map_or(0, |shape| shape.at))) {
+                (Some(shape), Some(base)) => {
+                    base * (1. + shape.offset(index - shape.at)) * (self.shape_noise * noise).exp()
+                }
+                _ => match &self.model {
+                    Model::Gbm { drift, volatility } => {
+                        previous * (drift - volatility.powi(2) / 2. + volatility * noise).exp()
+                    }
+                    Model::RegimeSwitching {
+                        regimes,
+                        switch_probability,
+                    } => {
+                        if regimes.len() > 1 && rng.uniform() <= *switch_probability {
+                            let next = (rng.next_u64() % (regimes.len() as u64 - 1)) as usize;
+                            regime = (regime + 1 + next) % regimes.len();
+                        }
+                        let (drift, volatility) = regimes.get(regime).copied().unwrap_or_default();
+                        previous * (drift - volatility.powi(2) / 2. + volatility * noise).exp()
+                    }
+                    Model::MeanReverting {
+                        mean,
+                        speed,
+                        volatility,
+                    } => {
+                        let log_price = previous.ln();
+                        (log_price + speed * (mean.ln() - log_price) + volatility * noise).exp()
+                    }
+                },
+            };
+
+            closes.push(close);
+        }
+
+        closes
+    }
+
+    /// One bar for every date. The calendar is up to the caller, so bar `n` always
+    /// has the same prices for a given seed.
+    pub fn generate(&self, dates: &[DateTime<Utc>]) -> Vec<DOHLC> {
+        let closes = self.closes(dates.len());
+        // Independent stream for the wicks and volumes, so they don't shift the closes
+        let mut rng = Rng::new(self.seed ^ hash("ohlcv"));
+        let mut open = self.start_price;
+        let mut data: Vec<DOHLC> = Vec::with_capacity(dates.len());
+
+        for (date, close) in dates.iter().zip(closes) {
+            let high = open.max(close) * (self.wick * rng.normal().abs()).exp();
+            let low = open.min(close) * (-self.wick * rng.normal().abs()).exp();
+            let volume = self.volume * (0.25 * rng.normal()).exp();
+
+            data.push((date.with_timezone(&Local), open, high, low, close, volume));
+            open = close;
+        }
+
+        data
+    }
+}
+
+/// `len` dates `minutes` apart from `from`, for the series that don't need a
+/// market calendar.
+pub fn dates(len: usize, minutes: i64, from: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    (0..len as i64)
+        .map(|index| from + chrono::Duration::minutes(index * minutes))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn from() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 1, 2, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_series() {
+        for model in ["gbm", "regime", "mean_reverting"] {
+            let model = model.parse::<Model>().unwrap();
+            let a = Generator::new(7, model.clone()).generate(&dates(300, 60, from()));
+            let b = Generator::new(7, model.clone()).generate(&dates(300, 60, from()));
+            let c = Generator::new(8, model).generate(&dates(300, 60, from()));
+
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+            assert!(a
+                .iter()
+                .all(|bar| bar.3 <= bar.1.min(bar.4) && bar.2 >= bar.1.max(bar.4) && bar.3 > 0.));
+        }
+
+        let dates: Vec<i64> = Generator::new(7, "gbm".parse().unwrap())
+            .generate(&dates(3, 60, from()))
+            .iter()
+            .map(|bar| bar.0.timestamp() - from().timestamp())
+            .collect();
+        assert_eq!(dates, vec![0, 3600, 7200]);
+    }
+
+    #[test]
+    fn mean_reverting_stays_around_the_mean() {
+        let closes = Generator::new(3, "mean_reverting".parse().unwrap()).closes(2000);
+        let average = closes[500..].iter().sum::<f64>() / 1500.;
+        assert!((average - 100.).abs() < 5., "{}", average);
+    }
+
+    #[test]
+    fn parses_models_and_shapes() {
+        assert_eq!(
+            "double_bottom@200".parse::<Shape>().unwrap(),
+            Shape::new(ShapeType::DoubleBottom, 200)
+        );
+
+        let shape = "head_and_shoulders@150:90:0.2".parse::<Shape>().unwrap();
+        assert_eq!((shape.at, shape.width, shape.size), (150, 90, 0.2));
+        assert!("double_bottom".parse::<Shape>().is_err());
+        assert!("triangle@10".parse::<Shape>().is_err());
+        assert_eq!(
+            "brownian".parse::<Model>(),
+            Err(SyntheticError::UnknownModel(String::from("brownian")))
+        );
+    }
+
+    #[test]
+    fn shape_replaces_the_model() {
+        let generator = Generator::new(5, "gbm".parse().unwrap())
+            .shape(Shape::new(ShapeType::DoubleBottom, 100));
+        let closes = generator.closes(200);
+        let base = closes[100];
+
+        // Bottoms a quarter and three quarters into the shape, then past the neckline
+        for (index, offset) in [(115, -0.1), (130, -0.05), (145, -0.1), (160, 0.025)] {
+            let change = closes[index] / base - 1.;
+            assert!((change - offset).abs() < 0.01, "{} {}", index, change);
+        }
+    }
+}